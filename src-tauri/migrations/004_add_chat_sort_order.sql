@@ -0,0 +1,4 @@
+-- Add manual ordering to chats. NULL means the chat follows recency ordering.
+ALTER TABLE chats ADD COLUMN sort_order INTEGER;
+
+CREATE INDEX IF NOT EXISTS idx_chats_sort_order ON chats(sort_order);
@@ -0,0 +1,10 @@
+-- Create message_flags table for bookmarks and reactions
+CREATE TABLE IF NOT EXISTS message_flags (
+    message_id TEXT NOT NULL,
+    flag_type TEXT NOT NULL CHECK (flag_type IN ('bookmark', 'thumbs_up', 'thumbs_down')),
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (message_id, flag_type),
+    FOREIGN KEY (message_id) REFERENCES messages (id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_message_flags_message_id ON message_flags(message_id);
@@ -0,0 +1,3 @@
+-- Per-chat overrides for generation parameters. NULL falls back to the API config.
+ALTER TABLE chats ADD COLUMN temperature_override REAL;
+ALTER TABLE chats ADD COLUMN max_tokens_override INTEGER;
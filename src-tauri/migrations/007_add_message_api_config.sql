@@ -0,0 +1,2 @@
+-- Record which API config produced an assistant message, when it differs from the chat's
+ALTER TABLE messages ADD COLUMN api_config_id TEXT;
@@ -0,0 +1,9 @@
+-- Global defaults applied when an API config omits them. Always exactly one row.
+CREATE TABLE IF NOT EXISTS app_settings (
+    id INTEGER PRIMARY KEY NOT NULL CHECK (id = 1),
+    default_temperature REAL NOT NULL DEFAULT 0.7,
+    default_max_tokens INTEGER,
+    default_provider TEXT CHECK (default_provider IN ('openai', 'anthropic', 'google', 'ollama', 'custom'))
+);
+
+INSERT OR IGNORE INTO app_settings (id) VALUES (1);
@@ -0,0 +1,6 @@
+-- Arbitrary UI preferences (theme, font size, ...) stored as JSON values
+CREATE TABLE IF NOT EXISTS preferences (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL,
+    updated_at DATETIME NOT NULL
+);
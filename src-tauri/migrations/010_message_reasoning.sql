@@ -0,0 +1,2 @@
+-- Reasoning ("thinking") returned separately from the visible reply by reasoning models
+ALTER TABLE messages ADD COLUMN reasoning TEXT;
@@ -0,0 +1,2 @@
+-- Abort a streamed reply when the provider sends nothing for this many seconds
+ALTER TABLE app_settings ADD COLUMN stream_idle_timeout_secs INTEGER NOT NULL DEFAULT 60;
@@ -0,0 +1,2 @@
+-- Retry a streamed reply this many times after the connection drops (0 disables)
+ALTER TABLE app_settings ADD COLUMN stream_max_reconnects INTEGER NOT NULL DEFAULT 2;
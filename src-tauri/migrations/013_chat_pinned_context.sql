@@ -0,0 +1,2 @@
+-- Notes sent as a system message ahead of every request in the chat
+ALTER TABLE chats ADD COLUMN pinned_context TEXT;
@@ -0,0 +1,35 @@
+-- Track each chat's newest message so chat lists don't scan every message
+ALTER TABLE chats ADD COLUMN last_message_id TEXT;
+ALTER TABLE chats ADD COLUMN last_message_at DATETIME;
+
+UPDATE chats SET
+    last_message_id = (
+        SELECT id FROM messages WHERE chat_id = chats.id
+        ORDER BY created_at DESC, rowid DESC LIMIT 1
+    ),
+    last_message_at = (
+        SELECT MAX(created_at) FROM messages WHERE chat_id = chats.id
+    );
+
+-- Keep the columns current however messages are added or removed
+CREATE TRIGGER IF NOT EXISTS trg_messages_last_insert
+AFTER INSERT ON messages
+BEGIN
+    UPDATE chats SET last_message_id = NEW.id, last_message_at = NEW.created_at
+    WHERE id = NEW.chat_id
+      AND (last_message_at IS NULL OR NEW.created_at >= last_message_at);
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_messages_last_delete
+AFTER DELETE ON messages
+BEGIN
+    UPDATE chats SET
+        last_message_id = (
+            SELECT id FROM messages WHERE chat_id = OLD.chat_id
+            ORDER BY created_at DESC, rowid DESC LIMIT 1
+        ),
+        last_message_at = (
+            SELECT MAX(created_at) FROM messages WHERE chat_id = OLD.chat_id
+        )
+    WHERE id = OLD.chat_id AND last_message_id = OLD.id;
+END;
@@ -0,0 +1,9 @@
+-- Per-chat message queries filter on chat_id and sort by created_at, so one
+-- composite index serves both. It also covers chat_id-only lookups, which
+-- makes the old single-column index redundant. chats(api_config_id) is
+-- already indexed by 002.
+CREATE INDEX IF NOT EXISTS idx_messages_chat_id_created_at ON messages(chat_id, created_at);
+DROP INDEX IF EXISTS idx_messages_chat_id;
+
+-- Refresh planner statistics so the new index is picked up
+ANALYZE;
@@ -0,0 +1,2 @@
+-- Config to try when this one's provider is unavailable
+ALTER TABLE api_configs ADD COLUMN fallback_config_id TEXT;
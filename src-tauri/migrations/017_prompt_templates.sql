@@ -0,0 +1,8 @@
+-- Reusable prompts with {{variable}} placeholders
+CREATE TABLE IF NOT EXISTS prompt_templates (
+    id TEXT PRIMARY KEY NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    body TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL
+);
@@ -0,0 +1,14 @@
+-- Every agent action, across sessions, for the activity log
+CREATE TABLE IF NOT EXISTS agent_actions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    action_type TEXT NOT NULL,
+    description TEXT NOT NULL,
+    parameters TEXT NOT NULL DEFAULT '{}',
+    success BOOLEAN NOT NULL,
+    cancelled BOOLEAN NOT NULL DEFAULT FALSE,
+    error_message TEXT,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_agent_actions_created ON agent_actions(created_at);
@@ -0,0 +1,2 @@
+-- How long Ollama keeps the model loaded after a request
+ALTER TABLE api_configs ADD COLUMN keep_alive TEXT;
@@ -0,0 +1,2 @@
+-- Which messages are sent as context; JSON-encoded ContextStrategy
+ALTER TABLE api_configs ADD COLUMN context_strategy TEXT NOT NULL DEFAULT '{"type":"recent_n","value":10}';
@@ -0,0 +1,2 @@
+-- Worker threads for multi-file search and copy (0 uses one per CPU)
+ALTER TABLE app_settings ADD COLUMN max_threads INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+-- Audit trail of what the system-operation commands did to the user's machine
+CREATE TABLE IF NOT EXISTS operation_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    operation TEXT NOT NULL,
+    parameters TEXT NOT NULL DEFAULT '{}',
+    success BOOLEAN NOT NULL,
+    result TEXT NOT NULL,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_operation_log_created ON operation_log(created_at);
@@ -0,0 +1,9 @@
+-- Running summary of the messages a Summarized context leaves out, one per chat
+CREATE TABLE IF NOT EXISTS chat_context_summaries (
+    chat_id TEXT PRIMARY KEY,
+    covered_messages INTEGER NOT NULL,
+    fingerprint TEXT NOT NULL,
+    summary TEXT NOT NULL,
+    updated_at DATETIME NOT NULL,
+    FOREIGN KEY (chat_id) REFERENCES chats (id) ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Pinned chats are kept by delete_chats_older_than. sort_order only records
+-- manual ordering, which reorder_chats assigns to every chat it is given.
+ALTER TABLE chats ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,3 @@
+-- Why the model stopped writing a reply ("stop", "length", ...), so a reply
+-- cut off by max_tokens can be continued
+ALTER TABLE messages ADD COLUMN finish_reason TEXT;
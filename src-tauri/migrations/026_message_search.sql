@@ -0,0 +1,27 @@
+-- Full-text index over message bodies for global_search. It reads the text
+-- from messages itself (external content keyed by rowid), and the triggers
+-- keep it in step with every insert, edit and delete. unicode61 folds case
+-- and diacritics beyond ASCII, which LIKE does not.
+CREATE VIRTUAL TABLE IF NOT EXISTS messages_fts USING fts5(
+    content,
+    content = 'messages',
+    content_rowid = 'rowid',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_insert AFTER INSERT ON messages BEGIN
+    INSERT INTO messages_fts (rowid, content) VALUES (new.rowid, new.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_delete AFTER DELETE ON messages BEGIN
+    INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+END;
+
+CREATE TRIGGER IF NOT EXISTS messages_fts_update AFTER UPDATE OF content ON messages BEGIN
+    INSERT INTO messages_fts (messages_fts, rowid, content) VALUES ('delete', old.rowid, old.content);
+    INSERT INTO messages_fts (rowid, content) VALUES (new.rowid, new.content);
+END;
+
+-- Index the messages that already exist. messages has no INTEGER PRIMARY KEY,
+-- so a VACUUM may renumber its rowids; run this again after one.
+INSERT INTO messages_fts (messages_fts) VALUES ('rebuild');
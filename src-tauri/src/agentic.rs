@@ -1,12 +1,16 @@
 use serde::{Deserialize, Serialize, Serializer};
 use std::collections::HashMap;
-use std::sync::{Mutex, Arc};
+use std::sync::{Mutex, Arc, LazyLock};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use anyhow::{Result, anyhow};
-use crate::file_operations::{read_directory_contents, search_in_files, read_file_contents, write_file_contents, open_with_default_app};
+use regex::Regex;
+use crate::file_operations::{Cancelled, read_directory_contents, search_in_files, count_matches_in_files, find_duplicate_files, SearchMatcher, SearchOptions, read_file_contents, read_structured_file, write_file_with_diff, touch_file, create_archive, extract_archive, ArchiveFormat, open_with_default_app, open_url, read_image_as_data_url};
 use crate::system_operations::{
     get_installed_applications, launch_application, execute_terminal_command,
     perform_file_operation, get_running_processes, kill_process, check_permission_level,
-    FileSystemOperation, FileOperationType, PermissionLevel};
+    find_executable, read_clipboard_text, write_clipboard_text, capture_screenshot_data_url, git_status, git_log, git_diff, run_code, ShellSession, ShellStream, FileSystemOperation, FileOperationType, OperationPermission, PermissionLevel,
+    DEFAULT_CODE_INTERPRETERS, DEFAULT_RUN_CODE_TIMEOUT, MAX_RUN_CODE_TIMEOUT};
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AgentAction {
     pub action_type: String,
@@ -15,6 +19,37 @@ pub struct AgentAction {
     pub result: Option<serde_json::Value>,
     pub success: bool,
     pub error_message: Option<String>,
+    /// Stopped by `cancel_agent_action`; `result` then describes the work already done
+    #[serde(default)]
+    pub cancelled: bool,
+}
+
+/// Upper bound on live sessions; the least recently used one is evicted past this
+pub const MAX_AGENT_SESSIONS: usize = 32;
+
+/// Per-file search budget for agent searches, which may run model-written patterns
+const DEFAULT_SEARCH_FILE_TIMEOUT_MS: u64 = 5_000;
+
+/// How long `shell_send` waits for output by default, and at most
+const DEFAULT_SHELL_WAIT_MS: u64 = 1_000;
+const MAX_SHELL_WAIT_MS: u64 = 30_000;
+
+/// `shell_send` returns early once output has been quiet for this long
+const SHELL_QUIET_PERIOD: Duration = Duration::from_millis(200);
+
+/// The persistent shell behind `shell_send`. Output is buffered until the
+/// next `shell_send` collects it.
+#[derive(Debug)]
+pub struct AgentShell {
+    session: ShellSession,
+    output: Arc<Mutex<ShellOutput>>,
+}
+
+#[derive(Debug, Default)]
+struct ShellOutput {
+    stdout: String,
+    stderr: String,
+    last_output: Option<Instant>,
 }
 
 #[derive(Debug, Clone)]
@@ -22,9 +57,34 @@ pub struct AgentSession {
     pub id: String,
     pub active: bool,
     pub actions: Arc<Mutex<Vec<AgentAction>>>,
-    pub context: HashMap<String, serde_json::Value>,
+    pub context: Arc<Mutex<HashMap<String, serde_json::Value>>>,
     pub current_directory: Arc<Mutex<String>>,
     pub capabilities: Vec<String>,
+    pub last_used: Arc<Mutex<Instant>>,
+    /// Raised by `cancel`; long-running actions check it between files
+    pub cancel_requested: Arc<AtomicBool>,
+    /// Number of `execute_action`/`execute_actions` calls in progress
+    pub running: Arc<AtomicUsize>,
+    /// Interpreters the `run_code` action may use
+    pub allowed_interpreters: Arc<Mutex<Vec<String>>>,
+    /// Started by the first `shell_send`; killed by `shell_close` or when the session goes
+    pub shell: Arc<Mutex<Option<AgentShell>>>,
+}
+
+/// Counts a session as running for as long as it is alive
+struct RunningAction<'a>(&'a AtomicUsize);
+
+impl<'a> RunningAction<'a> {
+    fn start(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self(counter)
+    }
+}
+
+impl Drop for RunningAction<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
 }
 
 impl Serialize for AgentSession {
@@ -33,23 +93,38 @@ impl Serialize for AgentSession {
         S: Serializer,
     {
         use serde::ser::SerializeStruct;
-        let mut state = serializer.serialize_struct("AgentSession", 5)?;
+        let mut state = serializer.serialize_struct("AgentSession", 7)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("active", &self.active)?;
         
         let actions = self.actions.lock().map_err(serde::ser::Error::custom)?.clone();
         state.serialize_field("actions", &actions)?;
         
-        state.serialize_field("context", &self.context)?;
+        let context = self.context.lock().map_err(serde::ser::Error::custom)?.clone();
+        state.serialize_field("context", &context)?;
         
         let current_dir = self.current_directory.lock().map_err(serde::ser::Error::custom)?.clone();
         state.serialize_field("current_directory", &current_dir)?;
         
         state.serialize_field("capabilities", &self.capabilities)?;
+        
+        let allowed_interpreters = self.allowed_interpreters.lock().map_err(serde::ser::Error::custom)?.clone();
+        state.serialize_field("allowed_interpreters", &allowed_interpreters)?;
         state.end()
     }
 }
 
+/// One step of a multi-action plan
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ActionRequest {
+    pub action_type: String,
+    #[serde(default)]
+    pub parameters: HashMap<String, serde_json::Value>,
+    /// Context key to save this step's result under for later `${context.key}` references
+    #[serde(default)]
+    pub store_as: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AgentCapability {
     pub name: String,
@@ -66,6 +141,49 @@ pub struct AgentParameter {
     pub default_value: Option<serde_json::Value>,
 }
 
+impl AgentCapability {
+    /// This capability as an OpenAI function-calling tool definition
+    pub fn to_tool(&self) -> serde_json::Value {
+        let properties: serde_json::Map<String, serde_json::Value> = self.parameters.iter()
+            .map(|parameter| (parameter.name.clone(), parameter.json_schema()))
+            .collect();
+        let required: Vec<&str> = self.parameters.iter()
+            .filter(|parameter| parameter.required)
+            .map(|parameter| parameter.name.as_str())
+            .collect();
+
+        serde_json::json!({
+            "type": "function",
+            "function": {
+                "name": self.name,
+                "description": self.description,
+                "parameters": {
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                }
+            }
+        })
+    }
+}
+
+impl AgentParameter {
+    /// JSON Schema for the parameter; unknown types are offered as strings
+    fn json_schema(&self) -> serde_json::Value {
+        let mut schema = match self.parameter_type.as_str() {
+            "boolean" | "number" | "integer" | "object" => serde_json::json!({ "type": self.parameter_type }),
+            // Every list parameter so far takes strings (patterns, paths)
+            "array" => serde_json::json!({ "type": "array", "items": { "type": "string" } }),
+            _ => serde_json::json!({ "type": "string" }),
+        };
+        schema["description"] = serde_json::Value::String(self.description.clone());
+        if let Some(default) = &self.default_value {
+            schema["default"] = default.clone();
+        }
+        schema
+    }
+}
+
 impl AgentSession {
     pub fn new(id: String) -> Self {
         let current_directory = std::env::current_dir()
@@ -77,13 +195,26 @@ impl AgentSession {
             id,
             active: true,
             actions: Arc::new(Mutex::new(Vec::new())),
-            context: HashMap::new(),
+            context: Arc::new(Mutex::new(HashMap::new())),
             current_directory: Arc::new(Mutex::new(current_directory)),
+            last_used: Arc::new(Mutex::new(Instant::now())),
+            cancel_requested: Arc::new(AtomicBool::new(false)),
+            running: Arc::new(AtomicUsize::new(0)),
+            allowed_interpreters: Arc::new(Mutex::new(
+                DEFAULT_CODE_INTERPRETERS.iter().map(|name| name.to_string()).collect()
+            )),
+            shell: Arc::new(Mutex::new(None)),
             capabilities: vec![
                 "list_directory".to_string(),
                 "read_file".to_string(),
+                "read_structured_file".to_string(),
+                "read_image".to_string(),
                 "write_file".to_string(),
+                "touch_file".to_string(),
+                "create_archive".to_string(),
+                "extract_archive".to_string(),
                 "search_files".to_string(),
+                "find_duplicate_files".to_string(),
                 "open_file".to_string(),
                 "change_directory".to_string(),
                 "get_file_info".to_string(),
@@ -93,10 +224,69 @@ impl AgentSession {
                 "file_operation".to_string(),
                 "get_processes".to_string(),
                 "kill_process".to_string(),
+                "which".to_string(),
+                "open_url".to_string(),
+                "read_clipboard".to_string(),
+                "write_clipboard".to_string(),
+                "capture_screenshot".to_string(),
+                "git_status".to_string(),
+                "git_log".to_string(),
+                "git_diff".to_string(),
+                "run_code".to_string(),
+                "shell_send".to_string(),
+                "shell_close".to_string(),
             ],
         }
     }
     
+    /// Mark the session as just used so LRU eviction keeps it around
+    pub fn touch(&self) {
+        if let Ok(mut last_used) = self.last_used.lock() {
+            *last_used = Instant::now();
+        }
+    }
+
+    pub fn last_used(&self) -> Instant {
+        self.last_used.lock().map(|last_used| *last_used).unwrap_or_else(|_| Instant::now())
+    }
+
+    /// The directory set by `change_directory`, falling back to `"."`
+    pub fn current_directory(&self) -> String {
+        self.current_directory.lock()
+            .map(|dir| dir.clone())
+            .unwrap_or_else(|_| ".".to_string())
+    }
+
+    /// Resolve a relative path against the session's current directory; absolute paths pass through
+    pub fn resolve_path(&self, path: &str) -> String {
+        let path = std::path::Path::new(path);
+        if path.is_absolute() {
+            return path.to_string_lossy().to_string();
+        }
+
+        std::path::Path::new(&self.current_directory())
+            .join(path)
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// The latest `limit` actions (all of them when `None`), oldest first
+    pub fn recent_actions(&self, limit: Option<usize>) -> Vec<AgentAction> {
+        let actions = match self.actions.lock() {
+            Ok(actions) => actions,
+            Err(_) => return Vec::new(),
+        };
+
+        let skip = limit.map_or(0, |limit| actions.len().saturating_sub(limit));
+        actions[skip..].to_vec()
+    }
+
+    pub fn clear_actions(&self) {
+        if let Ok(mut actions) = self.actions.lock() {
+            actions.clear();
+        }
+    }
+
     pub fn get_capabilities() -> Vec<AgentCapability> {
         vec![
             AgentCapability {
@@ -106,9 +296,9 @@ impl AgentSession {
                     AgentParameter {
                         name: "path".to_string(),
                         parameter_type: "string".to_string(),
-                        description: "Directory path to list".to_string(),
+                        description: "Directory path to list; defaults to the session's current directory".to_string(),
                         required: false,
-                        default_value: Some(serde_json::Value::String(".".to_string())),
+                        default_value: None,
                     },
                     AgentParameter {
                         name: "recursive".to_string(),
@@ -132,6 +322,32 @@ impl AgentSession {
                     },
                 ],
             },
+            AgentCapability {
+                name: "read_structured_file".to_string(),
+                description: "Parse a CSV, TSV, JSON, TOML or YAML file into structured data".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Path to the data file; the extension picks the format".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "read_image".to_string(),
+                description: "Read an image file as a base64 data URL for vision models".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Image file path to read".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
             AgentCapability {
                 name: "write_file".to_string(),
                 description: "Write contents to a file".to_string(),
@@ -152,6 +368,66 @@ impl AgentSession {
                     },
                 ],
             },
+            AgentCapability {
+                name: "touch_file".to_string(),
+                description: "Create an empty file or update its modified time".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "File path to touch".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "create_archive".to_string(),
+                description: "Pack files and directories into a zip or tar.gz archive".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "paths".to_string(),
+                        parameter_type: "array".to_string(),
+                        description: "Files and directories to include".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                    AgentParameter {
+                        name: "destination".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Path of the archive to create".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                    AgentParameter {
+                        name: "format".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Archive format: zip or tar_gz".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::String("zip".to_string())),
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "extract_archive".to_string(),
+                description: "Extract a zip or tar.gz archive into a directory".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "archive".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Archive file to extract".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                    AgentParameter {
+                        name: "destination".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Directory to extract into".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
             AgentCapability {
                 name: "search_files".to_string(),
                 description: "Search for text patterns in files using regex".to_string(),
@@ -160,15 +436,22 @@ impl AgentSession {
                         name: "pattern".to_string(),
                         parameter_type: "string".to_string(),
                         description: "Regex pattern to search for".to_string(),
-                        required: true,
+                        required: false,
+                        default_value: None,
+                    },
+                    AgentParameter {
+                        name: "patterns".to_string(),
+                        parameter_type: "array".to_string(),
+                        description: "Several regex patterns to search for at once; each result names the one that matched".to_string(),
+                        required: false,
                         default_value: None,
                     },
                     AgentParameter {
                         name: "directory".to_string(),
                         parameter_type: "string".to_string(),
-                        description: "Directory to search in".to_string(),
+                        description: "Directory to search in; defaults to the session's current directory".to_string(),
                         required: false,
-                        default_value: Some(serde_json::Value::String(".".to_string())),
+                        default_value: None,
                     },
                     AgentParameter {
                         name: "file_extension".to_string(),
@@ -198,6 +481,61 @@ impl AgentSession {
                         required: false,
                         default_value: Some(serde_json::Value::Number(serde_json::Number::from(100))),
                     },
+                    AgentParameter {
+                        name: "file_timeout_ms".to_string(),
+                        parameter_type: "number".to_string(),
+                        description: "Skip any single file that takes longer than this to search".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Number(serde_json::Number::from(DEFAULT_SEARCH_FILE_TIMEOUT_MS))),
+                    },
+                    AgentParameter {
+                        name: "whole_word".to_string(),
+                        parameter_type: "boolean".to_string(),
+                        description: "Only match whole words".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Bool(false)),
+                    },
+                    AgentParameter {
+                        name: "fixed_string".to_string(),
+                        parameter_type: "boolean".to_string(),
+                        description: "Treat patterns as literal text instead of regex".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Bool(false)),
+                    },
+                    AgentParameter {
+                        name: "skip_ignored_directories".to_string(),
+                        parameter_type: "boolean".to_string(),
+                        description: "Skip .git, node_modules and target directories".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Bool(false)),
+                    },
+                    AgentParameter {
+                        name: "count_only".to_string(),
+                        parameter_type: "boolean".to_string(),
+                        description: "Return match counts per file instead of individual matches".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Bool(false)),
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "find_duplicate_files".to_string(),
+                description: "Find groups of files with identical contents".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "directory".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Directory to scan; defaults to the session's current directory".to_string(),
+                        required: false,
+                        default_value: None,
+                    },
+                    AgentParameter {
+                        name: "recursive".to_string(),
+                        parameter_type: "boolean".to_string(),
+                        description: "Whether to scan subdirectories".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Bool(true)),
+                    },
                 ],
             },
             AgentCapability {
@@ -205,31 +543,256 @@ impl AgentSession {
                 description: "Open a file with the default system application".to_string(),
                 parameters: vec![
                     AgentParameter {
-                        name: "path".to_string(),
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "File or directory path to open".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "open_url".to_string(),
+                description: "Open an http, https or mailto URL in the default application".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "url".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "URL to open".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "read_clipboard".to_string(),
+                description: "Read text from the system clipboard".to_string(),
+                parameters: vec![],
+            },
+            AgentCapability {
+                name: "write_clipboard".to_string(),
+                description: "Replace the system clipboard with text. The user confirms each write".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "text".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Text to copy to the clipboard".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "capture_screenshot".to_string(),
+                description: "Capture a display as a PNG data URL for vision models. The user confirms each capture".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "display".to_string(),
+                        parameter_type: "number".to_string(),
+                        description: "Display index; defaults to the primary display".to_string(),
+                        required: false,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "change_directory".to_string(),
+                description: "Change the current working directory for the agent".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Directory path to change to".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "which".to_string(),
+                description: "Find the absolute path of an executable on PATH".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "name".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Executable name to look up".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "git_status".to_string(),
+                description: "Show the branch, upstream and changed files of a git repository".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Directory inside the repository".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::String(".".to_string())),
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "git_log".to_string(),
+                description: "List recent commits of a git repository".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Directory inside the repository".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::String(".".to_string())),
+                    },
+                    AgentParameter {
+                        name: "limit".to_string(),
+                        parameter_type: "number".to_string(),
+                        description: "Maximum number of commits (up to 200)".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Number(20.into())),
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "git_diff".to_string(),
+                description: "Show uncommitted changes in a git repository".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "path".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Directory inside the repository".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::String(".".to_string())),
+                    },
+                    AgentParameter {
+                        name: "staged".to_string(),
+                        parameter_type: "boolean".to_string(),
+                        description: "Show staged changes instead of unstaged ones".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::Value::Bool(false)),
+                    },
+                    AgentParameter {
+                        name: "file".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Limit the diff to this file or directory".to_string(),
+                        required: false,
+                        default_value: None,
+                    },
+                ],
+            },
+            AgentCapability {
+                name: "run_code".to_string(),
+                description: "Run a code snippet with python, node or sh and capture its output. The user confirms each run".to_string(),
+                parameters: vec![
+                    AgentParameter {
+                        name: "language".to_string(),
+                        parameter_type: "string".to_string(),
+                        description: "Language of the snippet: python, javascript/node or sh".to_string(),
+                        required: true,
+                        default_value: None,
+                    },
+                    AgentParameter {
+                        name: "code".to_string(),
                         parameter_type: "string".to_string(),
-                        description: "File or directory path to open".to_string(),
+                        description: "Source code to run".to_string(),
                         required: true,
                         default_value: None,
                     },
+                    AgentParameter {
+                        name: "timeout_secs".to_string(),
+                        parameter_type: "number".to_string(),
+                        description: "Kill the snippet after this many seconds (at most 300)".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::json!(DEFAULT_RUN_CODE_TIMEOUT.as_secs())),
+                    },
                 ],
             },
             AgentCapability {
-                name: "change_directory".to_string(),
-                description: "Change the current working directory for the agent".to_string(),
+                name: "shell_send".to_string(),
+                description: "Send a line to a persistent shell and return the output it printed. The cwd, environment and variables carry over between calls".to_string(),
                 parameters: vec![
                     AgentParameter {
-                        name: "path".to_string(),
+                        name: "input".to_string(),
                         parameter_type: "string".to_string(),
-                        description: "Directory path to change to".to_string(),
+                        description: "Command line to send".to_string(),
                         required: true,
                         default_value: None,
                     },
+                    AgentParameter {
+                        name: "wait_ms".to_string(),
+                        parameter_type: "number".to_string(),
+                        description: "How long to wait for output in milliseconds (up to 30000); returns early once output goes quiet".to_string(),
+                        required: false,
+                        default_value: Some(serde_json::json!(DEFAULT_SHELL_WAIT_MS)),
+                    },
                 ],
             },
+            AgentCapability {
+                name: "shell_close".to_string(),
+                description: "Close the persistent shell, stopping anything still running in it".to_string(),
+                parameters: vec![],
+            },
         ]
     }
     
-    pub async fn execute_action(&self, action_type: &str, parameters: HashMap<String, serde_json::Value>) -> Result<AgentAction> {
+    /// Stop the action in progress. Search and recursive file operations
+    /// notice between files; other actions run to completion.
+    pub fn cancel(&self) {
+        self.cancel_requested.store(true, Ordering::SeqCst);
+    }
+    
+    /// Replace the interpreters `run_code` may use. Names must be among
+    /// `DEFAULT_CODE_INTERPRETERS`; an empty list disables `run_code`.
+    pub fn set_allowed_interpreters(&self, names: Vec<String>) -> Result<Vec<String>> {
+        let mut cleaned: Vec<String> = Vec::new();
+        for name in names {
+            let name = name.trim().to_ascii_lowercase();
+            if !DEFAULT_CODE_INTERPRETERS.contains(&name.as_str()) {
+                return Err(anyhow!("Unknown interpreter '{}'; expected one of: {}", name, DEFAULT_CODE_INTERPRETERS.join(", ")));
+            }
+            if !cleaned.contains(&name) {
+                cleaned.push(name);
+            }
+        }
+        
+        let mut allowed = self.allowed_interpreters.lock()
+            .map_err(|_| anyhow!("Interpreter allowlist is unavailable"))?;
+        *allowed = cleaned.clone();
+        Ok(cleaned)
+    }
+    
+    /// Whether an action is executing right now
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst) > 0
+    }
+    
+    fn is_cancel_requested(&self) -> bool {
+        self.cancel_requested.load(Ordering::SeqCst)
+    }
+    
+    /// Run one action. String parameters may reference earlier results with
+    /// `${context.key}` (or `${context.key.field.0}` to reach inside them);
+    /// `$${context.key}` is left as the literal `${context.key}`. A
+    /// successful result is saved under `store_as` when given.
+    pub async fn execute_action(
+        &self,
+        action_type: &str,
+        parameters: HashMap<String, serde_json::Value>,
+        store_as: Option<&str>,
+    ) -> Result<AgentAction> {
+        // A cancel only applies to work already running
+        self.cancel_requested.store(false, Ordering::SeqCst);
+        let _running = RunningAction::start(&self.running);
+        self.run_action(action_type, parameters, store_as).await
+    }
+    
+    async fn run_action(
+        &self,
+        action_type: &str,
+        parameters: HashMap<String, serde_json::Value>,
+        store_as: Option<&str>,
+    ) -> Result<AgentAction> {
         let mut action = AgentAction {
             action_type: action_type.to_string(),
             description: format!("Executing {}", action_type),
@@ -237,35 +800,42 @@ impl AgentSession {
             result: None,
             success: false,
             error_message: None,
+            cancelled: false,
         };
         
-        let result = match action_type {
-            "list_directory" => self.execute_list_directory(&parameters).await,
-            "read_file" => self.execute_read_file(&parameters).await,
-            "write_file" => self.execute_write_file(&parameters).await,
-            "search_files" => self.execute_search_files(&parameters).await,
-            "open_file" => self.execute_open_file(&parameters).await,
-            "change_directory" => self.execute_change_directory(&parameters).await,
-            "launch_application" => self.execute_launch_application(&parameters).await,
-            "get_installed_apps" => self.execute_get_installed_apps(&parameters).await,
-            "execute_command" => self.execute_command(&parameters).await,
-            "file_operation" => self.execute_file_operation(&parameters).await,
-            "get_processes" => self.execute_get_processes(&parameters).await,
-            "kill_process" => self.execute_kill_process(&parameters).await,
-            _ => Err(anyhow!("Unknown action type: {}", action_type)),
+        let result = match self.substitute_context(parameters) {
+            Ok(parameters) => {
+                action.parameters = parameters.clone();
+                self.dispatch_action(action_type, &parameters).await
+            }
+            Err(err) => Err(err),
         };
         
         match result {
             Ok(value) => {
+                if let Some(key) = store_as {
+                    if let Ok(mut context) = self.context.lock() {
+                        context.insert(key.to_string(), value.clone());
+                    }
+                }
                 action.result = Some(value);
                 action.success = true;
                 action.description = format!("Successfully executed {}", action_type);
             }
-            Err(err) => {
-                action.success = false;
-                action.error_message = Some(err.to_string());
-                action.description = format!("Failed to execute {}: {}", action_type, err);
-            }
+            Err(err) => match err.downcast::<Cancelled>() {
+                Ok(cancelled) => {
+                    action.success = false;
+                    action.cancelled = true;
+                    action.error_message = Some(cancelled.to_string());
+                    action.result = Some(cancelled.progress);
+                    action.description = format!("Cancelled {}", action_type);
+                }
+                Err(err) => {
+                    action.success = false;
+                    action.error_message = Some(err.to_string());
+                    action.description = format!("Failed to execute {}: {}", action_type, err);
+                }
+            },
         }
         
         if let Ok(mut actions) = self.actions.lock() {
@@ -274,16 +844,90 @@ impl AgentSession {
         Ok(action)
     }
     
+    async fn dispatch_action(&self, action_type: &str, parameters: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        match action_type {
+            "list_directory" => self.execute_list_directory(parameters).await,
+            "read_file" => self.execute_read_file(parameters).await,
+            "read_structured_file" => self.execute_read_structured_file(parameters).await,
+            "read_image" => self.execute_read_image(parameters).await,
+            "write_file" => self.execute_write_file(parameters).await,
+            "touch_file" => self.execute_touch_file(parameters).await,
+            "create_archive" => self.execute_create_archive(parameters).await,
+            "extract_archive" => self.execute_extract_archive(parameters).await,
+            "search_files" => self.execute_search_files(parameters).await,
+            "find_duplicate_files" => self.execute_find_duplicate_files(parameters).await,
+            "open_file" => self.execute_open_file(parameters).await,
+            "open_url" => self.execute_open_url(parameters).await,
+            "change_directory" => self.execute_change_directory(parameters).await,
+            "launch_application" => self.execute_launch_application(parameters).await,
+            "get_installed_apps" => self.execute_get_installed_apps(parameters).await,
+            "execute_command" => self.execute_command(parameters).await,
+            "file_operation" => self.execute_file_operation(parameters).await,
+            "get_processes" => self.execute_get_processes(parameters).await,
+            "kill_process" => self.execute_kill_process(parameters).await,
+            "which" => self.execute_which(parameters).await,
+            "read_clipboard" => self.execute_read_clipboard(parameters).await,
+            "write_clipboard" => self.execute_write_clipboard(parameters).await,
+            "capture_screenshot" => self.execute_capture_screenshot(parameters).await,
+            "git_status" => self.execute_git_status(parameters).await,
+            "git_log" => self.execute_git_log(parameters).await,
+            "git_diff" => self.execute_git_diff(parameters).await,
+            "run_code" => self.execute_run_code(parameters).await,
+            "shell_send" => self.execute_shell_send(parameters).await,
+            "shell_close" => self.execute_shell_close().await,
+            _ => Err(anyhow!("Unknown action type: {}", action_type)),
+        }
+    }
+    
+    /// Replace `${context.key}` references in parameters with stored values.
+    /// A parameter that is exactly one reference takes the stored value as-is,
+    /// otherwise the value is spliced into the surrounding text.
+    fn substitute_context(&self, parameters: HashMap<String, serde_json::Value>) -> Result<HashMap<String, serde_json::Value>> {
+        let context = self.context.lock()
+            .map_err(|_| anyhow!("Agent context is unavailable"))?
+            .clone();
+
+        parameters
+            .into_iter()
+            .map(|(name, value)| Ok((name, substitute_value(value, &context)?)))
+            .collect()
+    }
+    
+    /// Run actions in order, returning every result produced so far.
+    /// With `stop_on_error` the first failed step ends the run; a cancel
+    /// always ends it.
+    pub async fn execute_actions(&self, requests: Vec<ActionRequest>, stop_on_error: bool) -> Result<Vec<AgentAction>> {
+        self.cancel_requested.store(false, Ordering::SeqCst);
+        let _running = RunningAction::start(&self.running);
+        let mut results = Vec::with_capacity(requests.len());
+
+        for request in requests {
+            if self.is_cancel_requested() {
+                break;
+            }
+            let action = self.run_action(&request.action_type, request.parameters, request.store_as.as_deref()).await?;
+            let failed = !action.success;
+            results.push(action);
+
+            if failed && stop_on_error {
+                break;
+            }
+        }
+
+        Ok(results)
+    }
+    
     async fn execute_list_directory(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
         let path = params.get("path")
             .and_then(|v| v.as_str())
-            .unwrap_or(".");
+            .map(|path| self.resolve_path(path))
+            .unwrap_or_else(|| self.current_directory());
         
         let recursive = params.get("recursive")
             .and_then(|v| v.as_bool())
             .unwrap_or(false);
         
-        let contents = read_directory_contents(path, recursive)?;
+        let contents = read_directory_contents(&path, recursive)?;
         Ok(serde_json::to_value(contents)?)
     }
     
@@ -291,11 +935,31 @@ impl AgentSession {
         let path = params.get("path")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("Missing required parameter: path"))?;
+        let path = self.resolve_path(path);
         
-        let contents = read_file_contents(path)?;
+        let contents = read_file_contents(&path)?;
         Ok(serde_json::Value::String(contents))
     }
     
+    async fn execute_read_structured_file(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let path = params.get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: path"))?;
+        let path = self.resolve_path(path);
+        
+        read_structured_file(&path)
+    }
+    
+    async fn execute_read_image(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let path = params.get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: path"))?;
+        let path = self.resolve_path(path);
+        
+        let data_url = read_image_as_data_url(&path)?;
+        Ok(serde_json::Value::String(data_url))
+    }
+    
     async fn execute_write_file(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
         let path = params.get("path")
             .and_then(|v| v.as_str())
@@ -304,19 +968,111 @@ impl AgentSession {
         let content = params.get("content")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("Missing required parameter: content"))?;
+        let path = self.resolve_path(path);
+        
+        // Check permission level against the resolved path
+        let mut checked_params = params.clone();
+        checked_params.insert("path".to_string(), serde_json::json!(path));
+        let permission = check_permission_level("write_file", &checked_params);
+        if permission.level == PermissionLevel::Dangerous {
+            return Err(anyhow!("Writing this file requires explicit user permission: {}", path));
+        }
         
-        write_file_contents(path, content)?;
-        Ok(serde_json::Value::String(format!("Successfully wrote to {}", path)))
+        let report = write_file_with_diff(&path, content)?;
+        Ok(serde_json::to_value(report)?)
+    }
+    
+    async fn execute_touch_file(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let path = params.get("path")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: path"))?;
+        let path = self.resolve_path(path);
+        
+        // Check permission level against the resolved path
+        let mut checked_params = params.clone();
+        checked_params.insert("path".to_string(), serde_json::json!(path));
+        let permission = check_permission_level("write_file", &checked_params);
+        if permission.level == PermissionLevel::Dangerous {
+            return Err(anyhow!("Writing this file requires explicit user permission: {}", path));
+        }
+        
+        touch_file(&path)?;
+        Ok(serde_json::Value::String(format!("Touched {}", path)))
+    }
+    
+    async fn execute_create_archive(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let paths: Vec<String> = params.get("paths")
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow!("Missing required parameter: paths"))?
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(|path| self.resolve_path(path))
+            .collect();
+        
+        let destination = params.get("destination")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: destination"))?;
+        let destination = self.resolve_path(destination);
+        
+        let format: ArchiveFormat = params.get("format")
+            .cloned()
+            .map(serde_json::from_value)
+            .transpose()
+            .map_err(|_| anyhow!("Invalid format, expected zip or tar_gz"))?
+            .unwrap_or(ArchiveFormat::Zip);
+        
+        self.check_archive_destination(params, &destination)?;
+        
+        let summary = create_archive(&paths, &destination, format)?;
+        Ok(serde_json::to_value(summary)?)
+    }
+    
+    async fn execute_extract_archive(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let archive = params.get("archive")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: archive"))?;
+        let archive = self.resolve_path(archive);
+        
+        let destination = params.get("destination")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: destination"))?;
+        let destination = self.resolve_path(destination);
+        
+        self.check_archive_destination(params, &destination)?;
+        
+        let summary = extract_archive(&archive, &destination)?;
+        Ok(serde_json::to_value(summary)?)
+    }
+    
+    fn check_archive_destination(&self, params: &HashMap<String, serde_json::Value>, destination: &str) -> Result<()> {
+        let mut checked_params = params.clone();
+        checked_params.insert("path".to_string(), serde_json::json!(destination));
+        let permission = check_permission_level("write_file", &checked_params);
+        if permission.level == PermissionLevel::Dangerous {
+            return Err(anyhow!("Writing to this location requires explicit user permission: {}", destination));
+        }
+        Ok(())
     }
     
     async fn execute_search_files(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
-        let pattern = params.get("pattern")
+        let mut patterns: Vec<String> = params.get("pattern")
             .and_then(|v| v.as_str())
-            .ok_or_else(|| anyhow!("Missing required parameter: pattern"))?;
+            .map(|pattern| pattern.to_string())
+            .into_iter()
+            .collect();
+        
+        if let Some(extra) = params.get("patterns").and_then(|v| v.as_array()) {
+            patterns.extend(extra.iter().filter_map(|v| v.as_str()).map(|pattern| pattern.to_string()));
+        }
+        
+        if patterns.is_empty() {
+            return Err(anyhow!("Missing required parameter: pattern or patterns"));
+        }
         
         let directory = params.get("directory")
             .and_then(|v| v.as_str())
-            .unwrap_or(".");
+            .map(|directory| self.resolve_path(directory))
+            .unwrap_or_else(|| self.current_directory());
         
         let file_extension = params.get("file_extension")
             .and_then(|v| v.as_str());
@@ -333,33 +1089,96 @@ impl AgentSession {
             .and_then(|v| v.as_u64())
             .map(|v| v as usize);
         
+        let file_timeout_ms = params.get("file_timeout_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_SEARCH_FILE_TIMEOUT_MS);
+        
+        let count_only = params.get("count_only")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        
+        let whole_word = params.get("whole_word")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        
+        let fixed_string = params.get("fixed_string")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        
+        let skip_ignored_directories = params.get("skip_ignored_directories")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        
+        let matcher = SearchMatcher::new(&patterns, SearchOptions { case_sensitive, whole_word, fixed_string })?;
+        
+        if count_only {
+            let counts = count_matches_in_files(
+                &directory,
+                &matcher,
+                file_extension,
+                recursive,
+                skip_ignored_directories,
+                Some(Duration::from_millis(file_timeout_ms)),
+                Some(&self.cancel_requested),
+            )?;
+            
+            return Ok(serde_json::to_value(counts)?);
+        }
+        
         let results = search_in_files(
-            directory,
-            pattern,
+            &directory,
+            &matcher,
             file_extension,
-            case_sensitive,
             recursive,
+            skip_ignored_directories,
             max_results,
+            Some(Duration::from_millis(file_timeout_ms)),
+            Some(&self.cancel_requested),
         )?;
         
         Ok(serde_json::to_value(results)?)
     }
     
+    async fn execute_find_duplicate_files(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let directory = params.get("directory")
+            .and_then(|v| v.as_str())
+            .map(|directory| self.resolve_path(directory))
+            .unwrap_or_else(|| self.current_directory());
+        
+        let recursive = params.get("recursive")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(true);
+        
+        let groups = find_duplicate_files(&directory, recursive, Some(&self.cancel_requested), |_| {})?;
+        Ok(serde_json::to_value(groups)?)
+    }
+    
     async fn execute_open_file(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
         let path = params.get("path")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("Missing required parameter: path"))?;
+        let path = self.resolve_path(path);
         
-        open_with_default_app(path)?;
+        open_with_default_app(&path)?;
         Ok(serde_json::Value::String(format!("Opened {} with default application", path)))
     }
     
+    async fn execute_open_url(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let url = params.get("url")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: url"))?;
+        
+        open_url(url)?;
+        Ok(serde_json::Value::String(format!("Opened {}", url)))
+    }
+    
     async fn execute_change_directory(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
         let path = params.get("path")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("Missing required parameter: path"))?;
         
-        let path = std::path::Path::new(path);
+        let path = self.resolve_path(path);
+        let path = std::path::Path::new(&path);
         if !path.exists() || !path.is_dir() {
             return Err(anyhow!("Directory does not exist: {}", path.display()));
         }
@@ -408,11 +1227,8 @@ impl AgentSession {
         
         let working_dir = params.get("working_directory")
             .and_then(|v| v.as_str())
-            .map(|s| s.to_string())
-            .or_else(|| {
-                self.current_directory.lock().ok()
-                    .map(|dir| dir.clone())
-            });
+            .map(|dir| self.resolve_path(dir))
+            .or_else(|| Some(self.current_directory()));
         
         // Check permission level
         let permission = check_permission_level("execute_command", params);
@@ -420,10 +1236,154 @@ impl AgentSession {
             return Err(anyhow!("Command requires explicit user permission: {}", command));
         }
         
-        let result = execute_terminal_command(command, working_dir.as_deref())?;
+        let max_output_bytes = params.get("max_output_bytes")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize);
+        
+        let shell = params.get("shell")
+            .and_then(|v| v.as_str());
+        
+        let result = execute_terminal_command(command, working_dir.as_deref(), max_output_bytes, shell)?;
+        Ok(serde_json::to_value(result)?)
+    }
+    
+    async fn execute_run_code(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let language = params.get("language")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: language"))?;
+        
+        let code = params.get("code")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: code"))?;
+        
+        require_confirmation(&check_permission_level("run_code", params), params)?;
+        
+        let timeout = params.get("timeout_secs")
+            .and_then(|v| v.as_u64())
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_RUN_CODE_TIMEOUT)
+            .min(MAX_RUN_CODE_TIMEOUT);
+        
+        let max_output_bytes = params.get("max_output_bytes")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize);
+        
+        let allowed = self.allowed_interpreters.lock()
+            .map_err(|_| anyhow!("Interpreter allowlist is unavailable"))?
+            .clone();
+        let working_dir = self.current_directory();
+        let (language, code) = (language.to_string(), code.to_string());
+        let cancel = self.cancel_requested.clone();
+        
+        // The interpreter can run for minutes, so wait on it off the async runtime
+        let result = tokio::task::spawn_blocking(move || {
+            run_code(&language, &code, &allowed, Some(&working_dir), timeout, max_output_bytes, Some(&cancel))
+        })
+        .await??;
         Ok(serde_json::to_value(result)?)
     }
     
+    async fn execute_shell_send(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let input = params.get("input")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: input"))?;
+        
+        // Input is run by the shell, so it gets the same screening as execute_command
+        let command_params = HashMap::from([("command".to_string(), serde_json::json!(input))]);
+        if check_permission_level("execute_command", &command_params).level == PermissionLevel::Dangerous {
+            return Err(anyhow!("Command requires explicit user permission: {}", input));
+        }
+        
+        let wait = params.get("wait_ms")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(DEFAULT_SHELL_WAIT_MS)
+            .min(MAX_SHELL_WAIT_MS);
+        
+        let output = {
+            let mut shell = self.shell.lock()
+                .map_err(|_| anyhow!("Agent shell is unavailable"))?;
+            
+            // Start a fresh shell on first use or after the last one exited
+            if shell.as_mut().is_none_or(|shell| shell.session.has_exited()) {
+                *shell = Some(self.spawn_shell()?);
+            }
+            let shell = shell.as_mut().expect("shell was just started");
+            
+            // Drop output nobody collected so the reply only covers this input
+            if let Ok(mut output) = shell.output.lock() {
+                *output = ShellOutput::default();
+            }
+            shell.session.send(input)?;
+            shell.output.clone()
+        };
+        
+        let started = Instant::now();
+        let deadline = started + Duration::from_millis(wait);
+        while Instant::now() < deadline && !self.is_cancel_requested() {
+            let quiet = output.lock().ok()
+                .and_then(|output| output.last_output)
+                .is_some_and(|last| last.elapsed() >= SHELL_QUIET_PERIOD);
+            if quiet {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        
+        let (stdout, stderr) = {
+            let mut output = output.lock()
+                .map_err(|_| anyhow!("Agent shell output is unavailable"))?;
+            (std::mem::take(&mut output.stdout), std::mem::take(&mut output.stderr))
+        };
+        
+        // A cancel stops whatever the shell is running
+        if self.is_cancel_requested() {
+            self.close_shell();
+            return Err(Cancelled { progress: serde_json::json!({ "stdout": stdout, "stderr": stderr }) }.into());
+        }
+        
+        let exited = self.shell.lock().ok()
+            .and_then(|mut shell| shell.as_mut().map(|shell| shell.session.has_exited()))
+            .unwrap_or(true);
+        
+        Ok(serde_json::json!({
+            "stdout": stdout,
+            "stderr": stderr,
+            "exited": exited,
+        }))
+    }
+    
+    async fn execute_shell_close(&self) -> Result<serde_json::Value> {
+        Ok(serde_json::json!({ "closed": self.close_shell() }))
+    }
+    
+    fn spawn_shell(&self) -> Result<AgentShell> {
+        let output = Arc::new(Mutex::new(ShellOutput::default()));
+        let sink = output.clone();
+        
+        let session = ShellSession::spawn(
+            Some(&self.current_directory()),
+            move |stream, data| {
+                if let Ok(mut output) = sink.lock() {
+                    match stream {
+                        ShellStream::Stdout => output.stdout.push_str(&data),
+                        ShellStream::Stderr => output.stderr.push_str(&data),
+                    }
+                    output.last_output = Some(Instant::now());
+                }
+            },
+            || {},
+        )?;
+        
+        Ok(AgentShell { session, output })
+    }
+    
+    /// Kill the session's shell, if one is open
+    fn close_shell(&self) -> bool {
+        // Dropping the shell kills it, so do that outside the lock
+        let closed = self.shell.lock().ok().and_then(|mut shell| shell.take());
+        closed.is_some()
+    }
+    
     async fn execute_file_operation(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
         let operation_type = params.get("operation_type")
             .and_then(|v| v.as_str())
@@ -431,11 +1391,12 @@ impl AgentSession {
         
         let source = params.get("source")
             .and_then(|v| v.as_str())
+            .map(|source| self.resolve_path(source))
             .ok_or_else(|| anyhow!("Missing required parameter: source"))?;
         
         let destination = params.get("destination")
             .and_then(|v| v.as_str())
-            .map(String::from);
+            .map(|destination| self.resolve_path(destination));
         
         let recursive = params.get("recursive")
             .and_then(|v| v.as_bool())
@@ -450,14 +1411,25 @@ impl AgentSession {
             _ => return Err(anyhow!("Invalid operation type: {}", operation_type)),
         };
         
+        // Check permission level against the resolved paths
+        let mut checked_params = params.clone();
+        checked_params.insert("source".to_string(), serde_json::json!(source));
+        if let Some(destination) = &destination {
+            checked_params.insert("destination".to_string(), serde_json::json!(destination));
+        }
+        let permission = check_permission_level("file_operation", &checked_params);
+        if permission.level == PermissionLevel::Dangerous {
+            return Err(anyhow!("{} requires explicit user permission", permission.description));
+        }
+        
         let operation = FileSystemOperation {
             operation_type: file_op_type,
-            source: source.to_string(),
+            source,
             destination,
             recursive,
         };
         
-        let result = perform_file_operation(&operation)?;
+        let result = perform_file_operation(&operation, Some(&self.cancel_requested))?;
         Ok(serde_json::Value::String(result))
     }
     
@@ -481,4 +1453,214 @@ impl AgentSession {
         kill_process(pid)?;
         Ok(serde_json::Value::String(format!("Successfully terminated process with PID: {}", pid)))
     }
+    
+    async fn execute_which(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let name = params.get("name")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: name"))?;
+        
+        let path = find_executable(name)?;
+        Ok(serde_json::to_value(path)?)
+    }
+    
+    // The git actions only read repository state, so they need no permission check
+    fn git_directory(&self, params: &HashMap<String, serde_json::Value>) -> String {
+        params.get("path")
+            .and_then(|v| v.as_str())
+            .map(|path| self.resolve_path(path))
+            .unwrap_or_else(|| self.current_directory())
+    }
+    
+    async fn execute_git_status(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let status = git_status(&self.git_directory(params))?;
+        Ok(serde_json::to_value(status)?)
+    }
+    
+    async fn execute_git_log(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let limit = params.get("limit")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(20) as usize;
+        
+        let commits = git_log(&self.git_directory(params), limit)?;
+        Ok(serde_json::to_value(commits)?)
+    }
+    
+    async fn execute_git_diff(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let staged = params.get("staged")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let file = params.get("file").and_then(|v| v.as_str());
+        
+        let diff = git_diff(&self.git_directory(params), staged, file)?;
+        Ok(serde_json::to_value(diff)?)
+    }
+    
+    async fn execute_read_clipboard(&self, _params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let text = read_clipboard_text()?;
+        Ok(serde_json::Value::String(text))
+    }
+    
+    async fn execute_write_clipboard(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let text = params.get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Missing required parameter: text"))?;
+        
+        require_confirmation(&check_permission_level("write_clipboard", params), params)?;
+        
+        write_clipboard_text(text)?;
+        Ok(serde_json::Value::String("Copied text to clipboard".to_string()))
+    }
+    
+    async fn execute_capture_screenshot(&self, params: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        let display = params.get("display")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize);
+        
+        require_confirmation(&check_permission_level("capture_screenshot", params), params)?;
+        
+        let data_url = capture_screenshot_data_url(display)?;
+        Ok(serde_json::Value::String(data_url))
+    }
+}
+
+/// Drop least recently used sessions until there is room for `incoming` more
+pub fn evict_lru_sessions(sessions: &mut HashMap<String, AgentSession>, incoming: usize) {
+    while !sessions.is_empty() && sessions.len() + incoming > MAX_AGENT_SESSIONS {
+        let oldest = sessions
+            .iter()
+            .min_by_key(|(_, session)| session.last_used())
+            .map(|(id, _)| id.clone());
+
+        match oldest {
+            Some(id) => {
+                sessions.remove(&id);
+            }
+            None => break,
+        }
+    }
+}
+
+/// Moderate actions only run once the user has approved them: the frontend
+/// shows the permission request, then resends the action with
+/// `confirmed: true`. Dangerous ones are always refused.
+fn require_confirmation(permission: &OperationPermission, params: &HashMap<String, serde_json::Value>) -> Result<()> {
+    match permission.level {
+        PermissionLevel::Safe => Ok(()),
+        PermissionLevel::Moderate if params.get("confirmed").and_then(|v| v.as_bool()) == Some(true) => Ok(()),
+        PermissionLevel::Moderate => Err(anyhow!("{} needs the user's confirmation; resend it with confirmed: true once they approve", permission.description)),
+        PermissionLevel::Dangerous => Err(anyhow!("{} requires explicit user permission", permission.description)),
+    }
+}
+
+fn substitute_value(value: serde_json::Value, context: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+    match value {
+        serde_json::Value::String(text) => substitute_string(&text, context),
+        serde_json::Value::Array(items) => Ok(serde_json::Value::Array(
+            items.into_iter()
+                .map(|item| substitute_value(item, context))
+                .collect::<Result<_>>()?,
+        )),
+        serde_json::Value::Object(fields) => Ok(serde_json::Value::Object(
+            fields.into_iter()
+                .map(|(key, item)| Ok((key, substitute_value(item, context)?)))
+                .collect::<Result<_>>()?,
+        )),
+        other => Ok(other),
+    }
+}
+
+/// A `${context.path}` reference, or an escaped `$${context.path}`
+static CONTEXT_REFERENCE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\$?\$\{context\.([A-Za-z0-9_\-]+(?:\.[A-Za-z0-9_\-]+)*)\}").expect("valid context reference pattern")
+});
+
+fn substitute_string(text: &str, context: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+    let mut references = CONTEXT_REFERENCE.captures_iter(text).peekable();
+    let Some(first) = references.peek() else {
+        return Ok(serde_json::Value::String(text.to_string()));
+    };
+    if first[0].len() == text.len() && !text.starts_with("$$") {
+        return lookup_context(&first[1], context).cloned();
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut last_end = 0;
+    for captures in references {
+        let whole = captures.get(0).expect("capture 0 is always present");
+        output.push_str(&text[last_end..whole.start()]);
+        if let Some(escaped) = whole.as_str().strip_prefix('$').filter(|rest| rest.starts_with('$')) {
+            output.push_str(escaped);
+        } else {
+            match lookup_context(&captures[1], context)? {
+                serde_json::Value::String(value) => output.push_str(value),
+                value => output.push_str(&value.to_string()),
+            }
+        }
+        last_end = whole.end();
+    }
+    output.push_str(&text[last_end..]);
+
+    Ok(serde_json::Value::String(output))
+}
+
+/// Resolve a dotted path like `listing.files.0.path` against the context
+fn lookup_context<'a>(path: &str, context: &'a HashMap<String, serde_json::Value>) -> Result<&'a serde_json::Value> {
+    let mut segments = path.split('.');
+    let key = segments.next().unwrap_or_default();
+    let mut value = context.get(key)
+        .ok_or_else(|| anyhow!("Unknown context key: {}", key))?;
+
+    for segment in segments {
+        value = match value {
+            serde_json::Value::Array(items) => segment.parse::<usize>().ok().and_then(|index| items.get(index)),
+            serde_json::Value::Object(fields) => fields.get(segment),
+            _ => None,
+        }
+        .ok_or_else(|| anyhow!("Context path not found: {}", path))?;
+    }
+
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn params(entries: &[(&str, serde_json::Value)]) -> HashMap<String, serde_json::Value> {
+        entries.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
+
+    #[tokio::test]
+    async fn relative_paths_follow_change_directory() {
+        let root = std::env::temp_dir().join(format!("chatme-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("project")).unwrap();
+        std::fs::write(root.join("project").join("notes.txt"), "inside project").unwrap();
+
+        let session = AgentSession::new("cd-test".to_string());
+        let into_root = session.execute_action("change_directory", params(&[("path", json!(root.to_string_lossy()))]), None).await.unwrap();
+        let into_project = session.execute_action("change_directory", params(&[("path", json!("project"))]), None).await.unwrap();
+        let read = session.execute_action("read_file", params(&[("path", json!("notes.txt"))]), None).await.unwrap();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert!(into_root.success && into_project.success, "{:?} {:?}", into_root.error_message, into_project.error_message);
+        assert_eq!(session.current_directory(), root.join("project").to_string_lossy());
+        assert_eq!(read.result, Some(json!("inside project")), "{:?}", read.error_message);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn shell_state_persists_between_sends() {
+        let session = AgentSession::new("shell-test".to_string());
+        let wait = ("wait_ms", json!(5_000));
+
+        let set = session.execute_action("shell_send", params(&[("input", json!("GREETING=hello; echo set")), wait.clone()]), None).await.unwrap();
+        let echo = session.execute_action("shell_send", params(&[("input", json!("echo \"$GREETING shell\"")), wait]), None).await.unwrap();
+        let close = session.execute_action("shell_close", HashMap::new(), None).await.unwrap();
+
+        assert_eq!(set.result.as_ref().map(|result| result["stdout"].clone()), Some(json!("set\n")), "{:?}", set.error_message);
+        assert_eq!(echo.result.as_ref().map(|result| result["stdout"].clone()), Some(json!("hello shell\n")), "{:?}", echo.error_message);
+        assert_eq!(close.result, Some(json!({ "closed": true })));
+        assert!(session.shell.lock().unwrap().is_none());
+    }
 }
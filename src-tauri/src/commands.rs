@@ -1,19 +1,30 @@
-use crate::database::Database;
+use crate::database::{Database, StreamInterrupted, CONTINUE_PROMPT};
 use crate::models::*;
 use crate::file_operations::{
-    open_with_default_app, read_directory_contents, search_in_files, 
-    read_file_contents, write_file_contents, DirectoryContents, SearchResult
+    open_with_default_app, open_url as open_url_in_browser, read_directory_contents, search_in_files, 
+    read_file_contents, write_file_contents, write_file_with_diff, read_image_as_data_url as read_image_data_url,
+    load_audio_input, replace_in_files as replace_text_in_files, count_matches_in_files,
+    read_file_tail as read_text_file_tail, follow_file_lines, touch_file as touch_path,
+    create_archive as create_archive_file, extract_archive as extract_archive_file,
+    preview_file as preview_file_contents, read_structured_file as parse_structured_file, get_file_tree as build_file_tree,
+    acquire_directory_watch, release_directory_watch, release_owner_watches, search_in_files_incremental, DirectoryWatch, DuplicateGroup, FilePreview, TreeNode, ArchiveFormat, ArchiveSummary, DirectoryContents, WriteReport, SearchMatcher, SearchOptions, SearchOutput, ReplaceResult
 };
-use crate::agentic::{AgentSession, AgentAction, AgentCapability};
+use crate::model_capabilities::{context_window_tokens, max_output_tokens, supports_vision};
+use crate::agentic::{evict_lru_sessions, ActionRequest, AgentSession, AgentAction, AgentCapability};
 use crate::system_operations::{
     launch_application, get_installed_applications, execute_terminal_command,
     perform_file_operation, get_running_processes, kill_process, check_permission_level,
-    FileSystemOperation, FileOperationType, PermissionLevel, AppInfo, CommandResult, ProcessInfo
+    get_environment_info, find_executable, read_clipboard_text, write_clipboard_text,
+    capture_screenshot_data_url, list_displays as list_system_displays, DisplayInfo,
+    FileSystemOperation, FileOperationType, PermissionLevel, AppInfo, CommandResult, ProcessInfo, EnvInfo,
+    ShellSession, MAX_SHELL_SESSIONS, detect_default_shell as detect_shell
 };
-use tauri::{State, Emitter};
+use tauri::{State, Emitter, Manager};
 use serde_json::json;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 #[tauri::command]
 pub async fn create_chat(db: State<'_, Database>, request: CreateChatRequest) -> Result<Chat, String> {
@@ -22,14 +33,50 @@ pub async fn create_chat(db: State<'_, Database>, request: CreateChatRequest) ->
         .map_err(|e| e.to_string())
 }
 
+/// Parse the optional `timezone` argument of the fetch commands
+fn requested_timezone(timezone: Option<&str>) -> Result<Option<chrono_tz::Tz>, String> {
+    timezone.map(parse_timezone).transpose().map_err(|e| e.to_string())
+}
+
+/// With a `timezone` (an IANA name), each chat also gets `created_at_local`
+#[tauri::command]
+pub async fn get_chats(db: State<'_, Database>, timezone: Option<String>) -> Result<Vec<ChatWithLastMessage>, String> {
+    let timezone = requested_timezone(timezone.as_deref())?;
+    let mut chats = db.get_chats().await.map_err(|e| e.to_string())?;
+
+    if let Some(timezone) = timezone {
+        for chat in &mut chats {
+            chat.created_at_local = Some(to_local_rfc3339(&chat.created_at, timezone));
+        }
+    }
+
+    Ok(chats)
+}
+
 #[tauri::command]
-pub async fn get_chats(db: State<'_, Database>) -> Result<Vec<ChatWithLastMessage>, String> {
-    db.get_chats().await.map_err(|e| e.to_string())
+pub async fn get_recent_chats(db: State<'_, Database>, limit: i64, timezone: Option<String>) -> Result<Vec<ChatWithLastMessage>, String> {
+    let timezone = requested_timezone(timezone.as_deref())?;
+    let mut chats = db.get_recent_chats(limit).await.map_err(|e| e.to_string())?;
+
+    if let Some(timezone) = timezone {
+        for chat in &mut chats {
+            chat.created_at_local = Some(to_local_rfc3339(&chat.created_at, timezone));
+        }
+    }
+
+    Ok(chats)
 }
 
 #[tauri::command]
-pub async fn get_chat(db: State<'_, Database>, chat_id: String) -> Result<Option<Chat>, String> {
-    db.get_chat(&chat_id).await.map_err(|e| e.to_string())
+pub async fn get_chat(db: State<'_, Database>, chat_id: String, timezone: Option<String>) -> Result<Option<Chat>, String> {
+    let timezone = requested_timezone(timezone.as_deref())?;
+    let mut chat = db.get_chat(&chat_id).await.map_err(|e| e.to_string())?;
+
+    if let (Some(chat), Some(timezone)) = (chat.as_mut(), timezone) {
+        chat.created_at_local = Some(to_local_rfc3339(&chat.created_at, timezone));
+    }
+
+    Ok(chat)
 }
 
 #[tauri::command]
@@ -38,29 +85,194 @@ pub async fn update_chat(
     chat_id: String,
     request: UpdateChatRequest,
 ) -> Result<Chat, String> {
-    db.update_chat(&chat_id, request.title, request.api_config_id)
+    db.update_chat(&chat_id, request)
         .await
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn reorder_chats(db: State<'_, Database>, ordered_ids: Vec<String>) -> Result<(), String> {
+    db.reorder_chats(&ordered_ids).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn fork_with_summary(db: State<'_, Database>, chat_id: String) -> Result<Chat, String> {
+    db.fork_with_summary(&chat_id).await.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn delete_chat(db: State<'_, Database>, chat_id: String) -> Result<(), String> {
     db.delete_chat(&chat_id).await.map_err(|e| e.to_string())
 }
 
+/// Check the database for damage; any found is rebuilt on the next launch.
+/// Also reports what was recovered if this launch already rebuilt it.
+#[tauri::command]
+pub async fn check_database_integrity(db: State<'_, Database>) -> Result<IntegrityReport, String> {
+    db.check_integrity().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn backfill_last_messages(db: State<'_, Database>) -> Result<u64, String> {
+    db.backfill_last_messages().await.map_err(|e| e.to_string())
+}
+
+/// Pinned chats survive `delete_chats_older_than` unless it's told otherwise
+#[tauri::command]
+pub async fn set_chat_pinned(db: State<'_, Database>, chat_id: String, pinned: bool) -> Result<Chat, String> {
+    db.set_chat_pinned(&chat_id, pinned).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_chats_older_than(
+    db: State<'_, Database>,
+    days: i64,
+    include_pinned: bool,
+) -> Result<u64, String> {
+    db.delete_chats_older_than(days, include_pinned).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn export_chat(db: State<'_, Database>, chat_id: String) -> Result<ChatExport, String> {
+    db.export_chat(&chat_id).await.map_err(|e| e.to_string())
+}
+
+/// Back up every chat to `path`; returns the path written
+#[tauri::command]
+pub async fn export_all_chats(db: State<'_, Database>, path: String, format: ExportFormat) -> Result<String, String> {
+    db.export_all_chats(std::path::Path::new(&path), format)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(path)
+}
+
+#[tauri::command]
+pub async fn global_search(
+    db: State<'_, Database>,
+    query: String,
+    limit: Option<usize>,
+) -> Result<Vec<GlobalHit>, String> {
+    db.global_search(&query, limit.unwrap_or(50))
+        .await
+        .map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn create_message(
     db: State<'_, Database>,
     request: CreateMessageRequest,
 ) -> Result<Message, String> {
+    if request.skip_if_duplicate {
+        return db.create_message_unless_duplicate(request.chat_id, request.content, request.role, request.images)
+            .await
+            .map_err(|e| e.to_string());
+    }
+
     db.create_message(request.chat_id, request.content, request.role, request.images)
         .await
         .map_err(|e| e.to_string())
 }
 
+/// Insert a batch of messages into one chat at once, e.g. for imports
+#[tauri::command]
+pub async fn create_messages(
+    db: State<'_, Database>,
+    chat_id: String,
+    messages: Vec<NewMessage>,
+) -> Result<Vec<Message>, String> {
+    db.create_messages(&chat_id, messages)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_messages(
+    db: State<'_, Database>,
+    chat_id: String,
+    include_metrics: Option<bool>,
+    timezone: Option<String>,
+) -> Result<Vec<Message>, String> {
+    let timezone = requested_timezone(timezone.as_deref())?;
+    let mut messages = db.get_messages(&chat_id).await.map_err(|e| e.to_string())?;
+
+    if include_metrics.unwrap_or(false) {
+        for message in &mut messages {
+            message.metrics = Some(MessageMetrics::from_content(&message.content));
+        }
+    }
+
+    if let Some(timezone) = timezone {
+        for message in &mut messages {
+            message.created_at_local = Some(to_local_rfc3339(&message.created_at, timezone));
+        }
+    }
+
+    Ok(messages)
+}
+
+#[tauri::command]
+pub async fn get_message_metrics(db: State<'_, Database>, message_id: String) -> Result<MessageMetrics, String> {
+    let message = db.get_message(&message_id).await.map_err(|e| e.to_string())?;
+    let message = message.ok_or("Message not found")?;
+
+    Ok(MessageMetrics::from_content(&message.content))
+}
+
+/// Render a message for the clipboard as markdown, plain text or HTML
+#[tauri::command]
+pub async fn format_message(db: State<'_, Database>, message_id: String, format: MessageFormat) -> Result<String, String> {
+    let message = db.get_message(&message_id).await.map_err(|e| e.to_string())?;
+    let message = message.ok_or("Message not found")?;
+
+    Ok(format.render(&message.content))
+}
+
+/// Write a message's `block_index`-th code block (0-based) to `path`. A path
+/// without an extension gets one from the block's language tag. Returns the
+/// path written.
+#[tauri::command]
+pub async fn save_code_block(
+    db: State<'_, Database>,
+    message_id: String,
+    block_index: usize,
+    path: String,
+) -> Result<String, String> {
+    let message = db.get_message(&message_id).await.map_err(|e| e.to_string())?;
+    let message = message.ok_or("Message not found")?;
+
+    let blocks = extract_code_blocks(&message.content);
+    let block = blocks.get(block_index)
+        .ok_or_else(|| format!("Code block {} not found; the message has {}", block_index, blocks.len()))?;
+
+    let mut target = std::path::PathBuf::from(&path);
+    if target.extension().is_none() {
+        if let Some(extension) = block.default_extension() {
+            target.set_extension(extension);
+        }
+    }
+    let target = target.to_string_lossy().to_string();
+
+    let mut params = HashMap::new();
+    params.insert("path".to_string(), json!(target));
+    if check_permission_level("write_file", &params).level == PermissionLevel::Dangerous {
+        return Err(format!("Writing this file requires explicit user permission: {}", target));
+    }
+
+    write_file_contents(&target, &block.code).map_err(|e| e.to_string())?;
+    Ok(target)
+}
+
 #[tauri::command]
-pub async fn get_messages(db: State<'_, Database>, chat_id: String) -> Result<Vec<Message>, String> {
-    db.get_messages(&chat_id).await.map_err(|e| e.to_string())
+pub async fn get_message(db: State<'_, Database>, message_id: String, timezone: Option<String>) -> Result<Option<Message>, String> {
+    let timezone = requested_timezone(timezone.as_deref())?;
+    let mut message = db.get_message(&message_id).await.map_err(|e| e.to_string())?;
+
+    if let (Some(message), Some(timezone)) = (message.as_mut(), timezone) {
+        message.created_at_local = Some(to_local_rfc3339(&message.created_at, timezone));
+    }
+
+    Ok(message)
 }
 
 #[tauri::command]
@@ -70,6 +282,91 @@ pub async fn delete_message(db: State<'_, Database>, message_id: String) -> Resu
         .map_err(|e| e.to_string())
 }
 
+#[tauri::command]
+pub async fn delete_messages(db: State<'_, Database>, message_ids: Vec<String>) -> Result<u64, String> {
+    db.delete_messages(&message_ids)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_usage_summary(
+    db: State<'_, Database>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Vec<ProviderUsage>, String> {
+    db.get_usage_summary(since).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn deduplicate_chat(db: State<'_, Database>, chat_id: String) -> Result<u64, String> {
+    db.deduplicate_chat(&chat_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Merge consecutive same-role messages. This rewrites history, so the
+/// caller has to pass `confirm` after asking the user.
+#[tauri::command]
+pub async fn compact_chat(
+    db: State<'_, Database>,
+    chat_id: String,
+    confirm: bool,
+) -> Result<u64, String> {
+    if !confirm {
+        return Err("Compacting permanently merges messages; confirm to continue".to_string());
+    }
+
+    db.compact_chat(&chat_id).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_message_role(
+    db: State<'_, Database>,
+    message_id: String,
+    role: MessageRole,
+) -> Result<Message, String> {
+    db.set_message_role(&message_id, role)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn copy_message_to_chat(
+    db: State<'_, Database>,
+    message_id: String,
+    target_chat_id: String,
+) -> Result<Message, String> {
+    db.copy_message_to_chat(&message_id, &target_chat_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_chat_messages(db: State<'_, Database>, chat_id: String) -> Result<u64, String> {
+    db.clear_chat_messages(&chat_id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn flag_message(db: State<'_, Database>, message_id: String, flag: MessageFlag) -> Result<(), String> {
+    db.flag_message(&message_id, flag)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unflag_message(db: State<'_, Database>, message_id: String, flag: MessageFlag) -> Result<(), String> {
+    db.unflag_message(&message_id, flag)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_flagged_messages(db: State<'_, Database>, chat_id: String) -> Result<Vec<FlaggedMessage>, String> {
+    db.get_flagged_messages(&chat_id).await.map_err(|e| e.to_string())
+}
+
 // API Configuration commands
 #[tauri::command]
 pub async fn create_api_config(
@@ -108,47 +405,238 @@ pub async fn update_api_config(
 }
 
 #[tauri::command]
-pub async fn delete_api_config(db: State<'_, Database>, config_id: String) -> Result<(), String> {
-    db.delete_api_config(&config_id)
+pub async fn set_default_api_config(db: State<'_, Database>, config_id: String) -> Result<ApiConfig, String> {
+    db.set_default_api_config(&config_id)
         .await
         .map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn send_ai_message(
+pub async fn export_api_configs(db: State<'_, Database>, include_keys: Option<bool>) -> Result<String, String> {
+    db.export_api_configs(include_keys.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn import_api_configs(
     db: State<'_, Database>,
-    chat_id: String,
-    user_message: String,
-) -> Result<Message, String> {
-    // Get the chat to find its API config
-    let chat = db.get_chat(&chat_id).await.map_err(|e| e.to_string())?;
-    let chat = chat.ok_or("Chat not found")?;
+    json: String,
+    overwrite: Option<bool>,
+) -> Result<ApiConfigImportSummary, String> {
+    db.import_api_configs(&json, overwrite.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    // Get API config (use chat's config or default)
-    let api_config = if let Some(config_id) = &chat.api_config_id {
-        db.get_api_config(config_id).await.map_err(|e| e.to_string())?
-    } else {
-        db.get_default_api_config().await.map_err(|e| e.to_string())?
-    };
+#[tauri::command]
+pub async fn get_app_settings(db: State<'_, Database>) -> Result<AppSettings, String> {
+    db.get_app_settings().await.map_err(|e| e.to_string())
+}
 
-    let api_config = api_config.ok_or("No API configuration found")?;
+#[tauri::command]
+pub async fn update_app_settings(db: State<'_, Database>, request: UpdateAppSettingsRequest) -> Result<AppSettings, String> {
+    db.update_app_settings(request)
+        .await
+        .map_err(|e| e.to_string())
+}
 
-    // Create user message
-    let _user_msg = db.create_message(chat_id.clone(), user_message.clone(), MessageRole::User, None)
+#[tauri::command]
+pub async fn get_preference(db: State<'_, Database>, key: String) -> Result<Option<serde_json::Value>, String> {
+    db.get_preference(&key).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn set_preference(db: State<'_, Database>, key: String, value: serde_json::Value) -> Result<(), String> {
+    db.set_preference(&key, &value)
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| e.to_string())
+}
 
-    // Get recent messages for context
-    let messages = db.get_messages(&chat_id).await.map_err(|e| e.to_string())?;
-    
-    // Convert to chat format (take last 10 messages for context)
-    let chat_messages: Vec<ChatMessage> = messages
+/// Slash commands `send_ai_message` expands, by name without the leading `/`
+#[tauri::command]
+pub async fn get_slash_commands(db: State<'_, Database>) -> Result<HashMap<String, String>, String> {
+    db.get_slash_commands().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn create_prompt_template(db: State<'_, Database>, name: String, body: String) -> Result<PromptTemplate, String> {
+    db.create_prompt_template(&name, &body).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_prompt_templates(db: State<'_, Database>) -> Result<Vec<PromptTemplate>, String> {
+    db.get_prompt_templates().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_prompt_template(db: State<'_, Database>, template_id: String) -> Result<bool, String> {
+    db.delete_prompt_template(&template_id).await.map_err(|e| e.to_string())
+}
+
+/// Fill in a template's `{{name}}` placeholders, failing with the names of any left without a value
+#[tauri::command]
+pub async fn render_template(
+    db: State<'_, Database>,
+    template_id: String,
+    vars: HashMap<String, String>,
+) -> Result<String, String> {
+    let template = db.get_prompt_template(&template_id).await.map_err(|e| e.to_string())?;
+    let template = template.ok_or("Prompt template not found")?;
+
+    template.render(&vars).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn get_all_preferences(db: State<'_, Database>) -> Result<HashMap<String, serde_json::Value>, String> {
+    db.get_all_preferences().await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn delete_api_config(
+    db: State<'_, Database>,
+    config_id: String,
+    force: Option<bool>,
+) -> Result<u64, String> {
+    db.delete_api_config(&config_id, force.unwrap_or(false))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Reply budget assumed when a config leaves `max_tokens` unset
+const DEFAULT_RESPONSE_TOKENS: usize = 1000;
+
+/// Rough per-image prompt cost; providers bill a high-detail image at several hundred tokens
+const ESTIMATED_IMAGE_TOKENS: usize = 765;
+
+/// Per-message framing (role markers and separators) added by chat formats
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// Most messages the `Full` strategy sends when the model's context window is unknown
+const FULL_CONTEXT_MAX_MESSAGES: usize = 200;
+
+/// Estimated prompt cost of one message, images included when they'll be sent
+fn estimated_message_tokens(msg: &Message, include_images: bool) -> usize {
+    let images = if include_images { msg.images.as_ref().map_or(0, Vec::len) } else { 0 };
+    MessageMetrics::from_content(&msg.content).estimated_tokens
+        + images * ESTIMATED_IMAGE_TOKENS
+        + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// Reply budget to leave room for, mirroring the clamping applied when the request is sent
+fn reserved_response_tokens(config: &ApiConfig) -> usize {
+    let reserved = config.max_tokens
+        .map_or(DEFAULT_RESPONSE_TOKENS, |max_tokens| max_tokens.max(0) as usize);
+    match max_output_tokens(&config.model) {
+        Some(limit) => reserved.min(limit as usize),
+        None => reserved,
+    }
+}
+
+/// Start of the longest run of latest messages that fits in `budget`
+/// estimated tokens. The latest message is always kept.
+fn newest_within_budget(messages: &[Message], budget: usize, include_images: bool) -> usize {
+    let mut used = 0;
+    let mut start = messages.len();
+    for (index, msg) in messages.iter().enumerate().rev() {
+        used += estimated_message_tokens(msg, include_images);
+        if used > budget && start < messages.len() {
+            break;
+        }
+        start = index;
+    }
+    start
+}
+
+/// Whether images should be sent to `model`: the capability table first,
+/// then Ollama's model metadata. Unknown models, or an Ollama server that
+/// can't be asked, count as text-only, the same answer the UI gets from
+/// `model_supports_vision`.
+async fn vision_support(db: &Database, provider: &ApiProvider, model: &str) -> anyhow::Result<bool> {
+    if let Some(supported) = supports_vision(model) {
+        return Ok(supported);
+    }
+
+    match provider {
+        ApiProvider::Ollama => db.ollama_supports_vision(model).await,
+        _ => Ok(false),
+    }
+}
+
+fn has_images(messages: &[Message]) -> bool {
+    messages.iter().any(|msg| msg.images.as_ref().is_some_and(|images| !images.is_empty()))
+}
+
+/// Index of the first message sent verbatim under the config's context
+/// strategy. Everything before it is left out, or summarized for `Summarized`.
+fn context_start(chat: &Chat, messages: &[Message], config: &ApiConfig, include_images: bool) -> usize {
+    match config.context_strategy {
+        ContextStrategy::RecentN(count) => messages.len().saturating_sub(count),
+        ContextStrategy::Summarized => {
+            // Move the cut in whole chunks so the stored summary is only
+            // extended once every SUMMARY_CHUNK_MESSAGES messages
+            let start = messages.len().saturating_sub(DEFAULT_CONTEXT_MESSAGES);
+            start - start % SUMMARY_CHUNK_MESSAGES
+        }
+        ContextStrategy::TokenBudget(budget) => newest_within_budget(messages, budget, include_images),
+        ContextStrategy::Full => match context_window_tokens(&config.model) {
+            Some(limit) => {
+                let pinned = chat.pinned_context.as_deref().map_or(0, |context| {
+                    MessageMetrics::from_content(context).estimated_tokens + MESSAGE_OVERHEAD_TOKENS
+                });
+                let budget = (limit as usize).saturating_sub(reserved_response_tokens(config) + pinned);
+                newest_within_budget(messages, budget, include_images)
+            }
+            None => messages.len().saturating_sub(FULL_CONTEXT_MAX_MESSAGES),
+        },
+    }
+}
+
+/// Convert stored messages into the provider chat format, choosing which to
+/// send by the config's context strategy and expanding attached images into
+/// vision content parts. Images are dropped unless `vision_support` says
+/// the model accepts them. The chat's pinned context leads as a system message and is never
+/// trimmed; a `Summarized` context follows it with the summary of older
+/// messages, or goes without if summarizing fails.
+async fn build_chat_context(db: &Database, chat: &Chat, messages: &[Message], config: &ApiConfig) -> Vec<ChatMessage> {
+    // Only ask about vision when there are images, which spares Ollama a lookup per send
+    let include_images = has_images(messages) && vision_support(db, &config.provider, &config.model).await.unwrap_or_else(|e| {
+        eprintln!("Failed to check whether {} accepts images: {}", config.model, e);
+        false
+    });
+    let (earlier, recent) = messages.split_at(context_start(chat, messages, config, include_images));
+
+    if !include_images && has_images(recent) {
+        eprintln!("Model {} does not support images; sending text only", config.model);
+    }
+
+    let summary = match config.context_strategy {
+        ContextStrategy::Summarized if !earlier.is_empty() => {
+            match db.summarize_earlier_messages(&chat.id, config, earlier).await {
+                Ok(summary) => Some(summary),
+                Err(e) => {
+                    eprintln!("Failed to summarize earlier messages; sending recent ones only: {}", e);
+                    None
+                }
+            }
+        }
+        _ => None,
+    };
+
+    let pinned = chat.pinned_context.iter().map(|context| ChatMessage {
+        role: "system".to_string(),
+        content: json!(context),
+    });
+
+    let summary = summary.map(|summary| ChatMessage {
+        role: "system".to_string(),
+        content: json!(format!("Summary of the earlier conversation:\n\n{}", summary)),
+    });
+
+    let history = recent
         .iter()
-        .rev()
-        .take(10)
-        .rev()
         .map(|msg| {
-            let content = if let Some(images) = &msg.images {
+            let content = if let Some(images) = msg.images.as_ref().filter(|_| include_images) {
                 if !images.is_empty() {
                     // Create vision format with text and images
                     let mut content_array = vec![];
@@ -180,7 +668,7 @@ pub async fn send_ai_message(
                 // No images, just text
                 json!(msg.content)
             };
-
+    
             ChatMessage {
                 role: match msg.role {
                     MessageRole::User => "user".to_string(),
@@ -188,103 +676,116 @@ pub async fn send_ai_message(
                 },
                 content,
             }
-        })
-        .collect();
+        });
 
-    // Send to LLM
-    let ai_response = db.send_chat_completion(&api_config, chat_messages)
-        .await
-        .map_err(|e| e.to_string())?;
+    pinned.chain(summary).chain(history).collect()
+}
 
-    // Create assistant message
-    let assistant_msg = db.create_message(chat_id, ai_response, MessageRole::Assistant, None)
-        .await
-        .map_err(|e| e.to_string())?;
+/// Estimate whether sending the chat with `config_id` fits the model's
+/// context window, so the UI can offer to trim or summarize first. The
+/// chat's overrides apply on top of the config, as they do when sending.
+#[tauri::command]
+pub async fn check_context_fit(
+    db: State<'_, Database>,
+    chat_id: String,
+    config_id: String,
+) -> Result<ContextFit, String> {
+    let chat = db.get_chat(&chat_id).await.map_err(|e| e.to_string())?;
+    let chat = chat.ok_or("Chat not found")?;
 
-    Ok(assistant_msg)
+    let api_config = db.get_api_config(&config_id).await.map_err(|e| e.to_string())?;
+    let api_config = chat.apply_overrides(api_config.ok_or("API configuration not found")?);
+
+    let messages = db.get_messages(&chat_id).await.map_err(|e| e.to_string())?;
+    let include_images = has_images(&messages) && vision_support(&db, &api_config.provider, &api_config.model).await.unwrap_or(false);
+    let (earlier, recent) = messages.split_at(context_start(&chat, &messages, &api_config, include_images));
+
+    // Only a summary that already exists is counted; this never asks the model for one
+    let summary_tokens = match api_config.context_strategy {
+        ContextStrategy::Summarized if !earlier.is_empty() => db.context_summary(&chat_id, earlier).await
+            .map_err(|e| e.to_string())?
+            .map_or(0, |(_, summary)| MessageMetrics::from_content(&summary).estimated_tokens + MESSAGE_OVERHEAD_TOKENS),
+        _ => 0,
+    };
+
+    let prompt_tokens: usize = recent
+        .iter()
+        .map(|msg| estimated_message_tokens(msg, include_images))
+        .sum::<usize>()
+        + summary_tokens
+        + chat.pinned_context.as_deref().map_or(0, |context| {
+            MessageMetrics::from_content(context).estimated_tokens + MESSAGE_OVERHEAD_TOKENS
+        });
+
+    let reserved_response_tokens = reserved_response_tokens(&api_config);
+
+    let context_limit = context_window_tokens(&api_config.model).map(|limit| limit as usize);
+    let headroom = context_limit
+        .map(|limit| limit as i64 - prompt_tokens as i64 - reserved_response_tokens as i64);
+
+    Ok(ContextFit {
+        prompt_tokens,
+        context_limit,
+        reserved_response_tokens,
+        headroom,
+        fits: headroom.is_none_or(|headroom| headroom >= 0),
+    })
 }
 
+/// Store the user's message and get the model's reply. With `stream` the
+/// reply arrives through `streaming_*` events as it's generated; either way
+/// the stored assistant message is returned.
 #[tauri::command]
-pub async fn send_ai_message_streaming(
+pub async fn send_ai_message(
     window: tauri::Window,
     db: State<'_, Database>,
     chat_id: String,
     user_message: String,
     images: Option<Vec<String>>,
-) -> Result<String, String> {
+    stream: Option<bool>,
+) -> Result<Message, String> {
+    let stream = stream.unwrap_or(false);
+
     // Get the chat to find its API config
     let chat = db.get_chat(&chat_id).await.map_err(|e| e.to_string())?;
     let chat = chat.ok_or("Chat not found")?;
 
     // Get API config (use chat's config or default)
-    let api_config = if let Some(config_id) = &chat.api_config_id {
-        db.get_api_config(config_id).await.map_err(|e| e.to_string())?
-    } else {
-        db.get_default_api_config().await.map_err(|e| e.to_string())?
-    };
+    let api_config = db.get_chat_api_config(&chat).await.map_err(|e| e.to_string())?;
 
     let api_config = api_config.ok_or("No API configuration found")?;
 
+    // Expand a leading slash command into its full prompt
+    let user_message = db.expand_slash_commands(&user_message)
+        .await
+        .map_err(|e| e.to_string())?
+        .unwrap_or(user_message);
+
     // Create user message
-    let user_msg = db.create_message(chat_id.clone(), user_message.clone(), MessageRole::User, images)
+    let user_msg = db.create_message(chat_id.clone(), user_message, MessageRole::User, images)
         .await
         .map_err(|e| e.to_string())?;
 
-    // Emit user message to frontend
-    window.emit("message_created", &user_msg).map_err(|e| e.to_string())?;
+    if stream {
+        // Emit user message to frontend
+        window.emit("message_created", &user_msg).map_err(|e| e.to_string())?;
+    }
 
     // Get recent messages for context
     let messages = db.get_messages(&chat_id).await.map_err(|e| e.to_string())?;
     
     // Convert to chat format (take last 10 messages for context)
-    let chat_messages: Vec<ChatMessage> = messages
-        .iter()
-        .rev()
-        .take(10)
-        .rev()
-        .map(|msg| {
-            let content = if let Some(images) = &msg.images {
-                if !images.is_empty() {
-                    // Create vision format with text and images
-                    let mut content_array = vec![];
-                    
-                    // Add text content if present
-                    if !msg.content.is_empty() {
-                        content_array.push(json!({
-                            "type": "text",
-                            "text": msg.content
-                        }));
-                    }
-                    
-                    // Add images
-                    for image in images {
-                        content_array.push(json!({
-                            "type": "image_url",
-                            "image_url": {
-                                "url": image
-                            }
-                        }));
-                    }
-                    
-                    json!(content_array)
-                } else {
-                    // No images, just text
-                    json!(msg.content)
-                }
-            } else {
-                // No images, just text
-                json!(msg.content)
-            };
+    let chat_messages = build_chat_context(&db, &chat, &messages, &api_config).await;
 
-            ChatMessage {
-                role: match msg.role {
-                    MessageRole::User => "user".to_string(),
-                    MessageRole::Assistant => "assistant".to_string(),
-                },
-                content,
-            }
-        })
-        .collect();
+    if !stream {
+        let ai_response = db.send_chat_completion_with_reasoning(&api_config, chat_messages).await;
+        db.record_send_outcome(&chat_id, &api_config, &ai_response);
+        let ai_response = ai_response.map_err(|e| e.to_string())?;
+
+        return db.create_assistant_message(chat_id, ai_response, None)
+            .await
+            .map_err(|e| e.to_string());
+    }
 
     // Create a placeholder assistant message for streaming
     let assistant_msg_id = uuid::Uuid::new_v4().to_string();
@@ -296,21 +797,207 @@ pub async fn send_ai_message_streaming(
     })).map_err(|e| e.to_string())?;
 
     // Send to LLM with streaming
-    let ai_response = db.send_chat_completion_streaming(&api_config, chat_messages, &window, &assistant_msg_id, &chat_id)
-        .await
-        .map_err(|e| e.to_string())?;
+    let ai_response = match db.send_chat_completion_streaming(&api_config, chat_messages, &window, &assistant_msg_id, &chat_id).await {
+        Ok(response) => response,
+        Err(e) => return Err(keep_partial_reply(&window, &db, &chat_id, None, e).await),
+    };
 
     // Create final assistant message in database
-    let assistant_msg = db.create_message(chat_id, ai_response, MessageRole::Assistant, None)
+    let assistant_msg = db.create_assistant_message(chat_id, ai_response, None)
         .await
         .map_err(|e| e.to_string())?;
 
     // Emit final message created event
     window.emit("final_message_created", &assistant_msg).map_err(|e| e.to_string())?;
 
+    Ok(assistant_msg)
+}
+
+/// Ask for a reply again after `send_ai_message` stored the user message but
+/// the request failed. Only the latest message of a chat can be retried,
+/// since the new reply is appended after it.
+#[tauri::command]
+pub async fn retry_message(
+    db: State<'_, Database>,
+    message_id: String,
+) -> Result<Message, String> {
+    let message = db.get_message(&message_id).await.map_err(|e| e.to_string())?;
+    let message = message.ok_or("Message not found")?;
+
+    if !matches!(message.role, MessageRole::User) {
+        return Err("Only user messages can be retried; regenerate assistant replies instead".to_string());
+    }
+
+    let chat = db.get_chat(&message.chat_id).await.map_err(|e| e.to_string())?;
+    let chat = chat.ok_or("Chat not found")?;
+
+    let api_config = db.get_chat_api_config(&chat).await.map_err(|e| e.to_string())?;
+    let api_config = api_config.ok_or("No API configuration found")?;
+
+    let messages = db.get_messages(&message.chat_id).await.map_err(|e| e.to_string())?;
+    if messages.last().map(|msg| msg.id.as_str()) != Some(message_id.as_str()) {
+        return Err("Only the latest message in a chat can be retried".to_string());
+    }
+
+    let chat_messages = build_chat_context(&db, &chat, &messages, &api_config).await;
+
+    let ai_response = db.send_chat_completion_with_reasoning(&api_config, chat_messages).await;
+    db.record_send_outcome(&message.chat_id, &api_config, &ai_response);
+    let ai_response = ai_response.map_err(|e| e.to_string())?;
+
+    let assistant_msg = db.create_assistant_message(message.chat_id, ai_response, None)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(assistant_msg)
+}
+
+#[tauri::command]
+pub async fn regenerate_with_config(
+    window: tauri::Window,
+    db: State<'_, Database>,
+    chat_id: String,
+    config_id: String,
+) -> Result<String, String> {
+    let chat = db.get_chat(&chat_id).await.map_err(|e| e.to_string())?;
+    let chat = chat.ok_or("Chat not found")?;
+
+    // Use the requested config without touching the chat's own configuration;
+    // the chat's overrides still apply, as check_context_fit assumes
+    let api_config = db.get_api_config(&config_id).await.map_err(|e| e.to_string())?;
+    let api_config = chat.apply_overrides(api_config.ok_or("API configuration not found")?);
+
+    let mut messages = db.get_messages(&chat_id).await.map_err(|e| e.to_string())?;
+
+    // Answer the latest prompt again rather than continuing after the old reply
+    while matches!(messages.last(), Some(msg) if matches!(msg.role, MessageRole::Assistant)) {
+        messages.pop();
+    }
+
+    if messages.is_empty() {
+        return Err("No user message to regenerate a response for".to_string());
+    }
+
+    let chat_messages = build_chat_context(&db, &chat, &messages, &api_config).await;
+
+    let assistant_msg_id = uuid::Uuid::new_v4().to_string();
+
+    window.emit("streaming_start", json!({
+        "message_id": assistant_msg_id,
+        "chat_id": chat_id
+    })).map_err(|e| e.to_string())?;
+
+    let ai_response = match db.send_chat_completion_streaming(&api_config, chat_messages, &window, &assistant_msg_id, &chat_id).await {
+        Ok(response) => response,
+        Err(e) => return Err(keep_partial_reply(&window, &db, &chat_id, Some(config_id), e).await),
+    };
+
+    // Tag the reply with the config that produced it
+    let assistant_msg = db.create_assistant_message(chat_id, ai_response, Some(config_id))
+        .await
+        .map_err(|e| e.to_string())?;
+
+    window.emit("final_message_created", &assistant_msg).map_err(|e| e.to_string())?;
+
     Ok(assistant_msg.id)
 }
 
+/// Structured details of the provider error behind the last failed send in
+/// a chat, e.g. to tell an invalid key from a rate limit
+#[tauri::command]
+pub async fn get_last_api_error(db: State<'_, Database>, chat_id: String) -> Result<Option<ApiErrorDetails>, String> {
+    Ok(db.last_api_error(&chat_id))
+}
+
+/// Save whatever arrived before a stream failed so it isn't lost, then
+/// return the failure for the caller to report
+async fn keep_partial_reply(
+    window: &tauri::Window,
+    db: &Database,
+    chat_id: &str,
+    api_config_id: Option<String>,
+    error: anyhow::Error,
+) -> String {
+    let interrupted = match error.downcast::<StreamInterrupted>() {
+        Ok(interrupted) => interrupted,
+        Err(error) => return error.to_string(),
+    };
+
+    if !interrupted.partial.content.is_empty() {
+        match db.create_assistant_message(chat_id.to_string(), interrupted.partial, api_config_id).await {
+            Ok(assistant_msg) => {
+                let _ = window.emit("final_message_created", &assistant_msg);
+            }
+            Err(e) => eprintln!("Failed to save partial reply: {}", e),
+        }
+    }
+
+    interrupted.source.to_string()
+}
+
+/// Extend a reply that was cut off by `max_tokens` instead of regenerating it.
+///
+/// Only a last assistant message whose `finish_reason` is `length` can be
+/// continued. The continuation is appended to it and returned; if that is
+/// cut off too, the message can be continued again.
+#[tauri::command]
+pub async fn continue_response(
+    window: tauri::Window,
+    db: State<'_, Database>,
+    chat_id: String,
+) -> Result<String, String> {
+    let chat = db.get_chat(&chat_id).await.map_err(|e| e.to_string())?;
+    let chat = chat.ok_or("Chat not found")?;
+
+    let api_config = db.get_chat_api_config(&chat).await.map_err(|e| e.to_string())?;
+    let api_config = api_config.ok_or("No API configuration found")?;
+
+    let messages = db.get_messages(&chat_id).await.map_err(|e| e.to_string())?;
+    let last_message = messages.last()
+        .filter(|msg| matches!(msg.role, MessageRole::Assistant))
+        .ok_or("The last message is not an assistant response")?;
+    if last_message.finish_reason.as_deref() != Some("length") {
+        return Err("The last response wasn't cut off by the token limit".to_string());
+    }
+
+    let mut chat_messages = build_chat_context(&db, &chat, &messages, &api_config).await;
+    chat_messages.push(ChatMessage {
+        role: "user".to_string(),
+        content: json!(CONTINUE_PROMPT),
+    });
+
+    let continuation = db.send_chat_completion_with_reasoning(&api_config, chat_messages).await;
+    db.record_send_outcome(&chat_id, &api_config, &continuation);
+    let continuation = continuation.map_err(|e| e.to_string())?;
+
+    let updated = db.append_to_message(&last_message.id, &continuation.content, continuation.finish_reason.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    window.emit("message_updated", &updated).map_err(|e| e.to_string())?;
+
+    Ok(continuation.content)
+}
+
+/// Per-provider defaults and requirements for building the settings form
+#[tauri::command]
+pub async fn get_provider_metadata() -> Result<Vec<ProviderMeta>, String> {
+    Ok(ApiProvider::ALL.iter().map(ApiProvider::metadata).collect())
+}
+
+/// Whether the UI should offer image attachments for `model`. Falls back to
+/// Ollama's model metadata for models missing from the capability table;
+/// other unknown models are reported as text-only, and the send paths drop
+/// their images.
+#[tauri::command]
+pub async fn model_supports_vision(
+    db: State<'_, Database>,
+    provider: ApiProvider,
+    model: String,
+) -> Result<bool, String> {
+    vision_support(&db, &provider, &model).await.map_err(|e| e.to_string())
+}
+
 // File Operations Commands
 #[tauri::command]
 pub async fn open_file_with_default_app(file_path: String) -> Result<String, String> {
@@ -319,6 +1006,11 @@ pub async fn open_file_with_default_app(file_path: String) -> Result<String, Str
     Ok(format!("Opened {} with default application", file_path))
 }
 
+#[tauri::command]
+pub async fn open_url(url: String) -> Result<(), String> {
+    open_url_in_browser(&url).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn read_directory(
     directory_path: String,
@@ -329,35 +1021,358 @@ pub async fn read_directory(
 }
 
 #[tauri::command]
-pub async fn search_files(
-    directory_path: String,
-    pattern: String,
-    file_extension: Option<String>,
-    case_sensitive: Option<bool>,
-    recursive: Option<bool>,
-    max_results: Option<usize>,
-) -> Result<Vec<SearchResult>, String> {
-    search_in_files(
-        &directory_path,
-        &pattern,
-        file_extension.as_deref(),
-        case_sensitive.unwrap_or(false),
-        recursive.unwrap_or(true),
-        max_results,
-    )
-    .map_err(|e| e.to_string())
+#[allow(clippy::too_many_arguments)]
+pub async fn search_files(
+    directory_path: String,
+    pattern: Option<String>,
+    patterns: Option<Vec<String>>,
+    file_extension: Option<String>,
+    case_sensitive: Option<bool>,
+    recursive: Option<bool>,
+    max_results: Option<usize>,
+    file_timeout_ms: Option<u64>,
+    count_only: Option<bool>,
+    whole_word: Option<bool>,
+    fixed_string: Option<bool>,
+    skip_ignored_directories: Option<bool>,
+) -> Result<SearchOutput, String> {
+    // `pattern` is kept for existing callers; both forms may be combined
+    let patterns: Vec<String> = pattern.into_iter().chain(patterns.unwrap_or_default()).collect();
+    let options = SearchOptions {
+        case_sensitive: case_sensitive.unwrap_or(false),
+        whole_word: whole_word.unwrap_or(false),
+        fixed_string: fixed_string.unwrap_or(false),
+    };
+    let matcher = SearchMatcher::new(&patterns, options)
+        .map_err(|e| e.to_string())?;
+    let file_timeout = file_timeout_ms.map(Duration::from_millis);
+    let skip_ignored_directories = skip_ignored_directories.unwrap_or(false);
+    
+    if count_only.unwrap_or(false) {
+        return count_matches_in_files(
+            &directory_path,
+            &matcher,
+            file_extension.as_deref(),
+            recursive.unwrap_or(true),
+            skip_ignored_directories,
+            file_timeout,
+            None,
+        )
+        .map(SearchOutput::Counts)
+        .map_err(|e| e.to_string());
+    }
+    
+    search_in_files(
+        &directory_path,
+        &matcher,
+        file_extension.as_deref(),
+        recursive.unwrap_or(true),
+        skip_ignored_directories,
+        max_results,
+        file_timeout,
+        None,
+    )
+    .map(SearchOutput::Matches)
+    .map_err(|e| e.to_string())
+}
+
+/// Cancel flags for `search_files_streaming` runs, keyed by search id
+#[derive(Default)]
+pub struct ActiveSearches(pub Mutex<HashMap<String, Arc<AtomicBool>>>);
+
+/// Search like `search_files`, but report matches as they're found. Returns
+/// a search id at once; each searched file with matches emits `search_hit`
+/// with its results, and the run ends with `search_complete` carrying the
+/// totals. `cancel_search` stops it early.
+#[allow(clippy::too_many_arguments)]
+#[tauri::command]
+pub async fn search_files_streaming(
+    window: tauri::Window,
+    searches: State<'_, ActiveSearches>,
+    directory_path: String,
+    patterns: Vec<String>,
+    file_extension: Option<String>,
+    case_sensitive: Option<bool>,
+    recursive: Option<bool>,
+    max_results: Option<usize>,
+    file_timeout_ms: Option<u64>,
+    whole_word: Option<bool>,
+    fixed_string: Option<bool>,
+    skip_ignored_directories: Option<bool>,
+) -> Result<String, String> {
+    let options = SearchOptions {
+        case_sensitive: case_sensitive.unwrap_or(false),
+        whole_word: whole_word.unwrap_or(false),
+        fixed_string: fixed_string.unwrap_or(false),
+    };
+    let matcher = SearchMatcher::new(&patterns, options)
+        .map_err(|e| e.to_string())?;
+    if !std::path::Path::new(&directory_path).is_dir() {
+        return Err(format!("Invalid directory path: {}", directory_path));
+    }
+
+    let search_id = uuid::Uuid::new_v4().to_string();
+    let cancel = Arc::new(AtomicBool::new(false));
+    searches.0.lock().map_err(|e| e.to_string())?.insert(search_id.clone(), cancel.clone());
+
+    let id = search_id.clone();
+    std::thread::spawn(move || {
+        let hit_window = window.clone();
+        let hit_id = id.clone();
+        let outcome = search_in_files_incremental(
+            &directory_path,
+            &matcher,
+            file_extension.as_deref(),
+            recursive.unwrap_or(true),
+            skip_ignored_directories.unwrap_or(false),
+            max_results,
+            file_timeout_ms.map(Duration::from_millis),
+            &cancel,
+            |results| {
+                let _ = hit_window.emit("search_hit", json!({
+                    "search_id": hit_id,
+                    "results": results
+                }));
+            },
+        );
+
+        if let Ok(mut searches) = window.state::<ActiveSearches>().0.lock() {
+            searches.remove(&id);
+        }
+
+        let _ = match outcome {
+            Ok(summary) => window.emit("search_complete", json!({
+                "search_id": id,
+                "summary": summary
+            })),
+            Err(e) => window.emit("search_complete", json!({
+                "search_id": id,
+                "error": e.to_string()
+            })),
+        };
+    });
+
+    Ok(search_id)
+}
+
+/// Stop a `search_files_streaming` run; it still ends with `search_complete`
+#[tauri::command]
+pub async fn cancel_search(searches: State<'_, ActiveSearches>, search_id: String) -> Result<bool, String> {
+    let searches = searches.0.lock().map_err(|e| e.to_string())?;
+
+    Ok(searches.get(&search_id)
+        .map(|cancel| cancel.store(true, Ordering::Relaxed))
+        .is_some())
+}
+
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn replace_in_files(
+    directory_path: String,
+    pattern: String,
+    replacement: String,
+    file_extension: Option<String>,
+    case_sensitive: Option<bool>,
+    recursive: Option<bool>,
+    skip_ignored_directories: Option<bool>,
+    dry_run: Option<bool>,
+) -> Result<Vec<ReplaceResult>, String> {
+    replace_text_in_files(
+        &directory_path,
+        &pattern,
+        &replacement,
+        file_extension.as_deref(),
+        case_sensitive.unwrap_or(false),
+        recursive.unwrap_or(true),
+        skip_ignored_directories.unwrap_or(false),
+        dry_run.unwrap_or(false),
+    )
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn read_file(file_path: String) -> Result<String, String> {
+    read_file_contents(&file_path).map_err(|e| e.to_string())
+}
+
+/// Groups of identical files under `directory`. Large trees emit
+/// `duplicate_scan_progress` events while they're scanned.
+#[tauri::command]
+pub async fn find_duplicate_files(
+    window: tauri::Window,
+    directory: String,
+    recursive: Option<bool>,
+) -> Result<Vec<DuplicateGroup>, String> {
+    crate::file_operations::find_duplicate_files(&directory, recursive.unwrap_or(true), None, |progress| {
+        let _ = window.emit("duplicate_scan_progress", json!({
+            "directory": directory,
+            "progress": progress
+        }));
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Nested view of a directory for tree-style explorers; `max_depth` defaults to 3
+#[tauri::command]
+pub async fn get_file_tree(path: String, max_depth: Option<usize>) -> Result<TreeNode, String> {
+    build_file_tree(&path, max_depth.unwrap_or(3)).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn preview_file(path: String) -> Result<FilePreview, String> {
+    preview_file_contents(&path).map_err(|e| e.to_string())
+}
+
+/// Parse a CSV, TSV, JSON, TOML or YAML file into JSON
+#[tauri::command]
+pub async fn read_structured_file(path: String) -> Result<serde_json::Value, String> {
+    parse_structured_file(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn read_file_tail(path: String, lines: usize) -> Result<Vec<String>, String> {
+    read_text_file_tail(&path, lines).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn follow_file(
+    window: tauri::Window,
+    followers: State<'_, Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    path: String,
+) -> Result<(), String> {
+    if !std::path::Path::new(&path).is_file() {
+        return Err(format!("File does not exist: {}", path));
+    }
+    
+    let stop = Arc::new(AtomicBool::new(false));
+    {
+        let mut followers = followers.lock().map_err(|e| e.to_string())?;
+        if followers.contains_key(&path) {
+            return Err(format!("Already following {}", path));
+        }
+        followers.insert(path.clone(), stop.clone());
+    }
+    
+    tauri::async_runtime::spawn(async move {
+        let event_path = path.clone();
+        let result = follow_file_lines(&path, stop.clone(), |line| {
+            let _ = window.emit("file_line", json!({
+                "path": event_path,
+                "line": line
+            }));
+        })
+        .await;
+        
+        if let Err(e) = result {
+            // Drop our entry so the path can be followed again
+            let followers = window.state::<Mutex<HashMap<String, Arc<AtomicBool>>>>();
+            if let Ok(mut followers) = followers.lock() {
+                if followers.get(&path).is_some_and(|current| Arc::ptr_eq(current, &stop)) {
+                    followers.remove(&path);
+                }
+            }
+            
+            let _ = window.emit("file_follow_error", json!({
+                "path": path,
+                "error": e.to_string()
+            }));
+        }
+    });
+    
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn unfollow_file(
+    followers: State<'_, Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    path: String,
+) -> Result<bool, String> {
+    let mut followers = followers.lock().map_err(|e| e.to_string())?;
+    
+    match followers.remove(&path) {
+        Some(stop) => {
+            stop.store(true, Ordering::Relaxed);
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Watch a directory on behalf of `owner_id` (a chat or view), emitting
+/// `directory_changed` events. Owners watching the same directory share one
+/// watcher, which stops once the last of them unwatches it.
+#[tauri::command]
+pub async fn watch_directory(
+    window: tauri::Window,
+    watches: State<'_, Mutex<HashMap<String, DirectoryWatch>>>,
+    path: String,
+    owner_id: String,
+    recursive: Option<bool>,
+) -> Result<String, String> {
+    let mut watches = watches.lock().map_err(|e| e.to_string())?;
+    let event_path = path.clone();
+
+    acquire_directory_watch(&mut watches, &path, &owner_id, recursive.unwrap_or(false), move |change| {
+        match change {
+            Ok(change) => {
+                let _ = window.emit("directory_changed", change);
+            }
+            Err(e) => {
+                let _ = window.emit("directory_watch_error", json!({
+                    "path": event_path,
+                    "error": e.to_string()
+                }));
+            }
+        }
+    })
+    .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn unwatch_directory(
+    watches: State<'_, Mutex<HashMap<String, DirectoryWatch>>>,
+    path: String,
+    owner_id: String,
+) -> Result<bool, String> {
+    let mut watches = watches.lock().map_err(|e| e.to_string())?;
+    Ok(release_directory_watch(&mut watches, &path, &owner_id))
+}
+
+/// Release every directory `owner_id` watches, e.g. when its chat closes.
+/// Returns how many watches it held.
+#[tauri::command]
+pub async fn unwatch_all(
+    watches: State<'_, Mutex<HashMap<String, DirectoryWatch>>>,
+    owner_id: String,
+) -> Result<usize, String> {
+    let mut watches = watches.lock().map_err(|e| e.to_string())?;
+    Ok(release_owner_watches(&mut watches, &owner_id))
+}
+
+#[tauri::command]
+pub async fn read_image_as_data_url(path: String) -> Result<String, String> {
+    read_image_data_url(&path).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn write_file(file_path: String, contents: String) -> Result<WriteReport, String> {
+    write_file_with_diff(&file_path, &contents)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn touch_file(path: String) -> Result<(), String> {
+    touch_path(&path).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn read_file(file_path: String) -> Result<String, String> {
-    read_file_contents(&file_path).map_err(|e| e.to_string())
+pub async fn create_archive(paths: Vec<String>, destination: String, format: ArchiveFormat) -> Result<ArchiveSummary, String> {
+    create_archive_file(&paths, &destination, format).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
-pub async fn write_file(file_path: String, contents: String) -> Result<String, String> {
-    write_file_contents(&file_path, &contents)
-        .map_err(|e| e.to_string())?;
-    Ok(format!("Successfully wrote to {}", file_path))
+pub async fn extract_archive(archive: String, destination: String) -> Result<ArchiveSummary, String> {
+    extract_archive_file(&archive, &destination).map_err(|e| e.to_string())
 }
 
 #[tauri::command]
@@ -378,27 +1393,89 @@ pub async fn get_agent_capabilities() -> Result<Vec<AgentCapability>, String> {
     Ok(AgentSession::get_capabilities())
 }
 
+/// Agent capabilities as OpenAI function-calling tool definitions
+#[tauri::command]
+pub async fn get_capabilities_as_tools() -> Result<Vec<serde_json::Value>, String> {
+    Ok(AgentSession::get_capabilities().iter().map(AgentCapability::to_tool).collect())
+}
+
 #[tauri::command]
 pub async fn execute_agent_action(
+    db: State<'_, Database>,
     agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
     session_id: String,
     action_type: String,
     parameters: HashMap<String, serde_json::Value>,
+    store_as: Option<String>,
 ) -> Result<AgentAction, String> {
     // Get the session from the map
     let session = {
         let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
-        sessions.get(&session_id)
+        let session = sessions.get(&session_id)
             .ok_or_else(|| "Agent session not found".to_string())?
-            .clone()
+            .clone();
+        session.touch();
+        session
     };
     
     // Execute the action
-    let result = session.execute_action(&action_type, parameters).await.map_err(|e| e.to_string())?;
+    let result = session.execute_action(&action_type, parameters, store_as.as_deref()).await.map_err(|e| e.to_string())?;
+    db.record_agent_actions(&session_id, std::slice::from_ref(&result)).await.map_err(|e| e.to_string())?;
     
     Ok(result)
 }
 
+#[tauri::command]
+pub async fn execute_agent_actions(
+    db: State<'_, Database>,
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    session_id: String,
+    actions: Vec<ActionRequest>,
+    stop_on_error: bool,
+) -> Result<Vec<AgentAction>, String> {
+    let session = {
+        let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+        let session = sessions.get(&session_id)
+            .ok_or_else(|| "Agent session not found".to_string())?
+            .clone();
+        session.touch();
+        session
+    };
+
+    let results = session.execute_actions(actions, stop_on_error).await.map_err(|e| e.to_string())?;
+    db.record_agent_actions(&session_id, &results).await.map_err(|e| e.to_string())?;
+
+    Ok(results)
+}
+
+/// Ask the session's running search or recursive file operation to stop.
+/// The action is recorded as cancelled along with what it had done.
+#[tauri::command]
+pub async fn cancel_agent_action(
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    session_id: String,
+) -> Result<(), String> {
+    let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+
+    sessions.get(&session_id)
+        .ok_or_else(|| "Agent session not found".to_string())
+        .map(|session| session.cancel())
+}
+
+/// Choose which interpreters the session's `run_code` action may use
+#[tauri::command]
+pub async fn set_agent_interpreters(
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    session_id: String,
+    interpreters: Vec<String>,
+) -> Result<Vec<String>, String> {
+    let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+    let session = sessions.get(&session_id)
+        .ok_or_else(|| "Agent session not found".to_string())?;
+
+    session.set_allowed_interpreters(interpreters).map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn get_agent_session(
     agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
@@ -411,6 +1488,18 @@ pub async fn get_agent_session(
         .map(|session| session.clone())
 }
 
+#[tauri::command]
+pub async fn get_agent_cwd(
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    session_id: String,
+) -> Result<String, String> {
+    let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+    
+    sessions.get(&session_id)
+        .map(|session| session.current_directory())
+        .ok_or_else(|| "Agent session not found".to_string())
+}
+
 #[tauri::command]
 pub async fn create_or_get_agent_session(
     agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
@@ -419,8 +1508,11 @@ pub async fn create_or_get_agent_session(
     let mut sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
     
     if let Some(session) = sessions.get(&session_id) {
+        session.touch();
         Ok(session.clone())
     } else {
+        evict_lru_sessions(&mut sessions, 1);
+
         let new_session = AgentSession::new(session_id.clone());
         let session_clone = new_session.clone();
         sessions.insert(session_id, new_session);
@@ -428,6 +1520,68 @@ pub async fn create_or_get_agent_session(
     }
 }
 
+#[tauri::command]
+pub async fn get_agent_actions(
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    session_id: String,
+    limit: Option<usize>,
+) -> Result<Vec<AgentAction>, String> {
+    let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+
+    sessions.get(&session_id)
+        .ok_or_else(|| "Agent session not found".to_string())
+        .map(|session| session.recent_actions(limit))
+}
+
+/// Recent agent actions from every session, newest first, for the activity log
+#[tauri::command]
+pub async fn get_all_agent_actions(
+    db: State<'_, Database>,
+    limit: Option<usize>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Vec<AgentActionRecord>, String> {
+    db.get_all_agent_actions(limit, since).await.map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn clear_agent_actions(
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    session_id: String,
+) -> Result<(), String> {
+    let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+
+    sessions.get(&session_id)
+        .ok_or_else(|| "Agent session not found".to_string())
+        .map(|session| session.clear_actions())
+}
+
+#[tauri::command]
+pub async fn list_agent_sessions(
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+) -> Result<Vec<String>, String> {
+    let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+
+    // Most recently used first
+    let mut entries: Vec<_> = sessions.values().collect();
+    entries.sort_by_key(|session| std::cmp::Reverse(session.last_used()));
+
+    Ok(entries.into_iter().map(|session| session.id.clone()).collect())
+}
+
+#[tauri::command]
+pub async fn close_agent_session(
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    session_id: String,
+) -> Result<AgentSession, String> {
+    let mut sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+
+    let mut session = sessions.remove(&session_id)
+        .ok_or_else(|| "Agent session not found".to_string())?;
+    session.active = false;
+
+    Ok(session)
+}
+
 // System Operations Commands with Permission System
 #[tauri::command]
 pub async fn request_permission(
@@ -454,21 +1608,64 @@ pub async fn request_permission(
     }
 }
 
+/// Record a system operation in the audit log. Logging is best-effort: the
+/// operation has already happened, so a failed write is only reported.
+async fn log_operation<T>(
+    db: &Database,
+    operation: &str,
+    parameters: &HashMap<String, serde_json::Value>,
+    outcome: &Result<T, String>,
+    describe: impl FnOnce(&T) -> String,
+) {
+    let (success, result) = match outcome {
+        Ok(value) => (true, describe(value)),
+        Err(e) => (false, e.clone()),
+    };
+    
+    if let Err(e) = db.record_operation(operation, parameters, success, &result).await {
+        eprintln!("Failed to record {} in the operation log: {}", operation, e);
+    }
+}
+
+/// Recent system operations (apps launched, commands run, files changed,
+/// processes killed), newest first
+#[tauri::command]
+pub async fn get_operation_log(
+    db: State<'_, Database>,
+    limit: Option<usize>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> Result<Vec<OperationLogEntry>, String> {
+    db.get_operation_log(limit, since).await.map_err(|e| e.to_string())
+}
+
 #[tauri::command]
 pub async fn launch_app(
     window: tauri::Window,
+    db: State<'_, Database>,
     app_path: String,
     arguments: Option<Vec<String>>,
     request_permission: bool,
+) -> Result<u32, String> {
+    let mut params = HashMap::new();
+    params.insert("path".to_string(), json!(app_path));
+    if let Some(ref args) = arguments {
+        params.insert("arguments".to_string(), json!(args));
+    }
+    
+    let outcome = launch_app_checked(&window, &params, &app_path, arguments, request_permission);
+    log_operation(&db, "launch_app", &params, &outcome, |pid| format!("Started with PID {}", pid)).await;
+    outcome
+}
+
+fn launch_app_checked(
+    window: &tauri::Window,
+    params: &HashMap<String, serde_json::Value>,
+    app_path: &str,
+    arguments: Option<Vec<String>>,
+    request_permission: bool,
 ) -> Result<u32, String> {
     if request_permission {
-        let mut params = HashMap::new();
-        params.insert("path".to_string(), json!(app_path));
-        if let Some(ref args) = arguments {
-            params.insert("arguments".to_string(), json!(args));
-        }
-        
-        let permission = check_permission_level("launch_app", &params);
+        let permission = check_permission_level("launch_app", params);
         
         // Emit permission request and wait for response
         window.emit("permission_request", json!({
@@ -485,7 +1682,7 @@ pub async fn launch_app(
         }
     }
     
-    launch_application(&app_path, arguments)
+    launch_application(app_path, arguments)
         .map_err(|e| e.to_string())
 }
 
@@ -498,18 +1695,38 @@ pub async fn get_installed_apps() -> Result<Vec<AppInfo>, String> {
 #[tauri::command]
 pub async fn execute_command(
     window: tauri::Window,
+    db: State<'_, Database>,
     command: String,
     working_directory: Option<String>,
     request_permission: bool,
+    max_output_bytes: Option<usize>,
+    shell: Option<String>,
+) -> Result<CommandResult, String> {
+    let mut params = HashMap::new();
+    params.insert("command".to_string(), json!(command));
+    if let Some(ref dir) = working_directory {
+        params.insert("working_directory".to_string(), json!(dir));
+    }
+    if let Some(ref shell) = shell {
+        params.insert("shell".to_string(), json!(shell));
+    }
+    
+    let outcome = execute_command_checked(&window, &params, &command, working_directory.as_deref(), request_permission, max_output_bytes, shell.as_deref());
+    log_operation(&db, "execute_command", &params, &outcome, |result| format!("Exited with code {}", result.exit_code)).await;
+    outcome
+}
+
+fn execute_command_checked(
+    window: &tauri::Window,
+    params: &HashMap<String, serde_json::Value>,
+    command: &str,
+    working_directory: Option<&str>,
+    request_permission: bool,
+    max_output_bytes: Option<usize>,
+    shell: Option<&str>,
 ) -> Result<CommandResult, String> {
     if request_permission {
-        let mut params = HashMap::new();
-        params.insert("command".to_string(), json!(command));
-        if let Some(ref dir) = working_directory {
-            params.insert("working_directory".to_string(), json!(dir));
-        }
-        
-        let permission = check_permission_level("execute_command", &params);
+        let permission = check_permission_level("execute_command", params);
         
         // Emit permission request
         window.emit("permission_request", json!({
@@ -526,20 +1743,152 @@ pub async fn execute_command(
         }
     }
     
-    execute_terminal_command(&command, working_directory.as_deref())
+    execute_terminal_command(command, working_directory, max_output_bytes, shell)
         .map_err(|e| e.to_string())
 }
 
+/// The shell `execute_command` uses when no `shell` is given
+#[tauri::command]
+pub async fn detect_default_shell() -> Result<String, String> {
+    Ok(detect_shell())
+}
+
+#[tauri::command]
+pub async fn open_shell_session(
+    window: tauri::Window,
+    db: State<'_, Database>,
+    shells: State<'_, Mutex<HashMap<String, ShellSession>>>,
+    working_directory: Option<String>,
+) -> Result<String, String> {
+    let mut params = HashMap::new();
+    if let Some(ref dir) = working_directory {
+        params.insert("working_directory".to_string(), json!(dir));
+    }
+    
+    let outcome = spawn_shell_session(window, &shells, working_directory.as_deref());
+    log_operation(&db, "open_shell_session", &params, &outcome, |session_id| format!("Opened shell session {}", session_id)).await;
+    outcome
+}
+
+fn spawn_shell_session(
+    window: tauri::Window,
+    shells: &Mutex<HashMap<String, ShellSession>>,
+    working_directory: Option<&str>,
+) -> Result<String, String> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    
+    // Hold the lock until the new shell is in so concurrent opens can't overshoot the cap
+    let mut shells = shells.lock().map_err(|e| e.to_string())?;
+    
+    // Shells that exited on their own no longer count toward the cap
+    shells.retain(|_, shell| !shell.has_exited());
+    if shells.len() >= MAX_SHELL_SESSIONS {
+        return Err(format!("Too many open shell sessions (max {})", MAX_SHELL_SESSIONS));
+    }
+    
+    let output_window = window.clone();
+    let output_id = session_id.clone();
+    let exit_id = session_id.clone();
+    
+    let shell = ShellSession::spawn(
+        working_directory,
+        move |stream, data| {
+            let _ = output_window.emit("shell_output", json!({
+                "session_id": output_id,
+                "stream": stream,
+                "data": data
+            }));
+        },
+        move || {
+            let _ = window.emit("shell_exit", json!({
+                "session_id": exit_id
+            }));
+        },
+    )
+    .map_err(|e| e.to_string())?;
+    
+    shells.insert(session_id.clone(), shell);
+    
+    Ok(session_id)
+}
+
+#[tauri::command]
+pub async fn shell_send(
+    db: State<'_, Database>,
+    shells: State<'_, Mutex<HashMap<String, ShellSession>>>,
+    session_id: String,
+    input: String,
+) -> Result<(), String> {
+    let mut params = HashMap::new();
+    params.insert("session_id".to_string(), json!(session_id));
+    params.insert("command".to_string(), json!(input));
+    
+    let outcome = send_to_shell(&shells, &params, &session_id, &input);
+    log_operation(&db, "shell_send", &params, &outcome, |_| "Sent to shell".to_string()).await;
+    outcome
+}
+
+fn send_to_shell(
+    shells: &Mutex<HashMap<String, ShellSession>>,
+    params: &HashMap<String, serde_json::Value>,
+    session_id: &str,
+    input: &str,
+) -> Result<(), String> {
+    // Input is run by the shell, so it gets the same screening as execute_command
+    let permission = check_permission_level("execute_command", params);
+    if permission.level == PermissionLevel::Dangerous {
+        return Err("Permission denied: This command requires explicit user permission".to_string());
+    }
+    
+    let mut shells = shells.lock().map_err(|e| e.to_string())?;
+    let shell = shells.get_mut(session_id).ok_or("Shell session not found")?;
+    
+    shell.send(input).map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn close_shell_session(
+    shells: State<'_, Mutex<HashMap<String, ShellSession>>>,
+    session_id: String,
+) -> Result<bool, String> {
+    // Dropping the session kills the shell
+    let removed = shells.lock().map_err(|e| e.to_string())?.remove(&session_id);
+    Ok(removed.is_some())
+}
+
 #[tauri::command]
 pub async fn perform_file_system_operation(
     window: tauri::Window,
+    db: State<'_, Database>,
     operation_type: String,
     source: String,
     destination: Option<String>,
     recursive: bool,
     request_permission: bool,
 ) -> Result<String, String> {
-    let file_op_type = match operation_type.as_str() {
+    let mut params = HashMap::new();
+    params.insert("operation_type".to_string(), json!(operation_type));
+    params.insert("source".to_string(), json!(source));
+    if let Some(destination) = &destination {
+        params.insert("destination".to_string(), json!(destination));
+    }
+    params.insert("recursive".to_string(), json!(recursive));
+    
+    let outcome = file_system_operation_checked(&window, &params, &operation_type, source, destination, recursive, request_permission);
+    log_operation(&db, "file_operation", &params, &outcome, |message| message.clone()).await;
+    outcome
+}
+
+fn file_system_operation_checked(
+    window: &tauri::Window,
+    params: &HashMap<String, serde_json::Value>,
+    operation_type: &str,
+    source: String,
+    destination: Option<String>,
+    recursive: bool,
+    request_permission: bool,
+) -> Result<String, String> {
+    let file_op_type = match operation_type {
         "copy" => FileOperationType::Copy,
         "move" => FileOperationType::Move,
         "delete" => FileOperationType::Delete,
@@ -548,11 +1897,8 @@ pub async fn perform_file_system_operation(
         _ => return Err(format!("Invalid operation type: {}", operation_type)),
     };
     
-    if request_permission && matches!(file_op_type, FileOperationType::Delete) {
-        let mut params = HashMap::new();
-        params.insert("path".to_string(), json!(source));
-        
-        let permission = check_permission_level("delete_file", &params);
+    if request_permission {
+        let permission = check_permission_level("file_operation", params);
         
         window.emit("permission_request", json!({
             "operation": permission.operation,
@@ -563,7 +1909,7 @@ pub async fn perform_file_system_operation(
         })).map_err(|e| e.to_string())?;
         
         if permission.level == PermissionLevel::Dangerous {
-            return Err("Permission denied: Deleting system files requires explicit permission".to_string());
+            return Err(format!("Permission denied: {} requires explicit permission", permission.description));
         }
     }
     
@@ -574,7 +1920,7 @@ pub async fn perform_file_system_operation(
         recursive,
     };
     
-    perform_file_operation(&operation)
+    perform_file_operation(&operation, None)
         .map_err(|e| e.to_string())
 }
 
@@ -587,14 +1933,26 @@ pub async fn get_processes() -> Result<Vec<ProcessInfo>, String> {
 #[tauri::command]
 pub async fn terminate_process(
     window: tauri::Window,
+    db: State<'_, Database>,
+    pid: u32,
+    request_permission: bool,
+) -> Result<String, String> {
+    let mut params = HashMap::new();
+    params.insert("pid".to_string(), json!(pid));
+    
+    let outcome = terminate_process_checked(&window, &params, pid, request_permission);
+    log_operation(&db, "kill_process", &params, &outcome, |message| message.clone()).await;
+    outcome
+}
+
+fn terminate_process_checked(
+    window: &tauri::Window,
+    params: &HashMap<String, serde_json::Value>,
     pid: u32,
     request_permission: bool,
 ) -> Result<String, String> {
     if request_permission {
-        let mut params = HashMap::new();
-        params.insert("pid".to_string(), json!(pid));
-        
-        let permission = check_permission_level("kill_process", &params);
+        let permission = check_permission_level("kill_process", params);
         
         window.emit("permission_request", json!({
             "operation": permission.operation,
@@ -613,3 +1971,158 @@ pub async fn terminate_process(
     
     Ok(format!("Successfully terminated process with PID: {}", pid))
 }
+
+/// When the app started, for `ping`'s uptime
+pub struct StartTime(pub std::time::Instant);
+
+#[tauri::command]
+pub async fn ping(
+    db: State<'_, Database>,
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    start_time: State<'_, StartTime>,
+) -> Result<PingInfo, String> {
+    let agent_sessions = agent_sessions.lock().map_err(|e| e.to_string())?.len();
+
+    Ok(PingInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_seconds: start_time.0.elapsed().as_secs(),
+        database_ok: db.ping().await,
+        agent_sessions,
+        active_streams: db.active_stream_count(),
+    })
+}
+
+#[tauri::command]
+pub async fn get_version_info(db: State<'_, Database>) -> Result<VersionInfo, String> {
+    Ok(VersionInfo {
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        tauri_version: tauri::VERSION.to_string(),
+        sqlite_version: db.sqlite_version().await.map_err(|e| e.to_string())?,
+        schema_version: db.schema_version().await.map_err(|e| e.to_string())?,
+        os: std::env::consts::OS.to_string(),
+        os_family: std::env::consts::FAMILY.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        debug_build: cfg!(debug_assertions),
+    })
+}
+
+/// Stop everything in flight: streaming replies, running agent actions
+/// (including `run_code` interpreters), followed files, watched
+/// directories, streaming searches and shell sessions
+#[tauri::command]
+pub async fn abort_all(
+    db: State<'_, Database>,
+    agent_sessions: State<'_, Mutex<HashMap<String, AgentSession>>>,
+    followers: State<'_, Mutex<HashMap<String, Arc<AtomicBool>>>>,
+    shells: State<'_, Mutex<HashMap<String, ShellSession>>>,
+    watches: State<'_, Mutex<HashMap<String, DirectoryWatch>>>,
+    searches: State<'_, ActiveSearches>,
+) -> Result<AbortReport, String> {
+    let streams = db.abort_streams();
+
+    let agent_actions = {
+        let sessions = agent_sessions.lock().map_err(|e| e.to_string())?;
+        sessions.values()
+            .filter(|session| session.is_running())
+            .inspect(|session| session.cancel())
+            .count()
+    };
+
+    let file_followers = {
+        let mut followers = followers.lock().map_err(|e| e.to_string())?;
+        let count = followers.len();
+        for (_, stop) in followers.drain() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        count
+    };
+
+    let directory_watches = {
+        let mut watches = watches.lock().map_err(|e| e.to_string())?;
+        let count = watches.len();
+        watches.clear();
+        count
+    };
+
+    // Each search removes itself from the map once it notices the cancel
+    let searches = {
+        let searches = searches.0.lock().map_err(|e| e.to_string())?;
+        for cancel in searches.values() {
+            cancel.store(true, Ordering::Relaxed);
+        }
+        searches.len()
+    };
+
+    // Dropping the sessions kills the shells, so do it outside the lock
+    let closed: Vec<ShellSession> = shells.lock()
+        .map_err(|e| e.to_string())?
+        .drain()
+        .map(|(_, shell)| shell)
+        .collect();
+    let shell_sessions = closed.len();
+    drop(closed);
+
+    Ok(AbortReport { streams, agent_actions, file_followers, directory_watches, searches, shell_sessions })
+}
+
+#[tauri::command]
+pub async fn get_environment() -> Result<EnvInfo, String> {
+    get_environment_info()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn which(name: String) -> Result<Option<String>, String> {
+    find_executable(&name)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn read_clipboard() -> Result<String, String> {
+    read_clipboard_text()
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn write_clipboard(text: String) -> Result<(), String> {
+    write_clipboard_text(&text)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn list_displays() -> Result<Vec<DisplayInfo>, String> {
+    list_system_displays()
+        .map_err(|e| e.to_string())
+}
+
+/// Grab a display as a PNG data URL. Screen contents are private, so the
+/// caller has to pass `confirm` after asking the user.
+#[tauri::command]
+pub async fn capture_screenshot(
+    display: Option<usize>,
+    confirm: bool,
+) -> Result<String, String> {
+    if !confirm {
+        return Err("Capturing the screen shares its contents; confirm to continue".to_string());
+    }
+
+    capture_screenshot_data_url(display)
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn transcribe_audio(
+    db: State<'_, Database>,
+    path_or_data: String,
+    config_id: String,
+) -> Result<String, String> {
+    // Validate the audio before touching the network
+    let audio = load_audio_input(&path_or_data).map_err(|e| e.to_string())?;
+
+    let api_config = db.get_api_config(&config_id).await.map_err(|e| e.to_string())?;
+    let api_config = api_config.ok_or("API configuration not found")?;
+
+    db.transcribe_audio(&api_config, audio)
+        .await
+        .map_err(|e| e.to_string())
+}
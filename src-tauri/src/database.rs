@@ -1,46 +1,965 @@
 use anyhow::Result;
-use chrono::Utc;
-use sqlx::{migrate::MigrateDatabase, Pool, Sqlite, SqlitePool, Row};
-use std::path::PathBuf;
+use chrono::{DateTime, Utc};
+use sqlx::{migrate::MigrateDatabase, sqlite::SqliteRow, Pool, Sqlite, SqlitePool, Row};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::LazyLock;
 use uuid::Uuid;
 use reqwest::Client;
 use serde_json::json;
 use tauri::Emitter;
 
 use crate::models::*;
+use crate::file_operations::{set_max_threads, AudioInput};
+use crate::model_capabilities::max_output_tokens;
+use crate::agentic::AgentAction;
+
+/// Upper bound on the transcript sent when summarizing a chat
+const MAX_SUMMARY_TRANSCRIPT_CHARS: usize = 100_000;
+
+/// Hash of the messages a stored context summary covers, so editing or
+/// deleting any of them invalidates the summary
+fn summary_fingerprint(messages: &[Message]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    for message in messages {
+        let role = match message.role {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+        };
+        for field in [message.id.as_str(), role, message.content.as_str()] {
+            hasher.update(field.as_bytes());
+            hasher.update([0]);
+        }
+    }
+    hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Derive the transcription endpoint from a configured chat completions URL
+fn transcription_url(base_url: &str) -> String {
+    let trimmed = base_url.trim_end_matches('/');
+    match trimmed.strip_suffix("/chat/completions") {
+        Some(root) => format!("{}/audio/transcriptions", root),
+        None => format!("{}/audio/transcriptions", trimmed),
+    }
+}
+
+/// Join an Ollama server URL and an API endpoint such as `chat`. The base
+/// may be a bare host, sit behind a proxy path, end in slashes, or already
+/// include `/api`, a full `/api/<endpoint>` path or the OpenAI-compatible `/v1`.
+fn ollama_url(base_url: Option<&str>, endpoint: &str) -> String {
+    let mut root = base_url
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .unwrap_or(OLLAMA_DEFAULT_URL)
+        .trim_end_matches('/');
+
+    if let Some((prefix, last)) = root.rsplit_once('/') {
+        if prefix.ends_with("/api") && !last.is_empty() && last.chars().all(|c| c.is_ascii_lowercase()) {
+            root = prefix;
+        }
+    }
+    let root = root.strip_suffix("/api")
+        .or_else(|| root.strip_suffix("/v1"))
+        .unwrap_or(root);
+
+    format!("{}/api/{}", root, endpoint)
+}
+
+/// Separates `<think>...</think>` blocks (DeepSeek-R1, QwQ and other models
+/// served through Ollama or OpenAI-compatible APIs) from the visible reply,
+/// tolerating tags that are split across streamed chunks
+#[derive(Default)]
+struct ThinkTagSplitter {
+    in_think: bool,
+    pending: String,
+}
+
+impl ThinkTagSplitter {
+    const OPEN: &'static str = "<think>";
+    const CLOSE: &'static str = "</think>";
+
+    /// Feed the next chunk; returns the (content, reasoning) text it settles
+    fn push(&mut self, chunk: &str) -> (String, String) {
+        self.pending.push_str(chunk);
+        let mut content = String::new();
+        let mut reasoning = String::new();
+
+        loop {
+            let tag = if self.in_think { Self::CLOSE } else { Self::OPEN };
+            let out = if self.in_think { &mut reasoning } else { &mut content };
+
+            if let Some(index) = self.pending.find(tag) {
+                out.push_str(&self.pending[..index]);
+                self.pending.drain(..index + tag.len());
+                self.in_think = !self.in_think;
+                continue;
+            }
+
+            // Hold back a trailing partial tag until the next chunk decides it
+            let keep = (1..tag.len())
+                .rev()
+                .find(|&len| self.pending.ends_with(&tag[..len]))
+                .unwrap_or(0);
+            let settled = self.pending.len() - keep;
+            out.push_str(&self.pending[..settled]);
+            self.pending.drain(..settled);
+
+            return (content, reasoning);
+        }
+    }
+
+    /// Flush whatever was held back at the end of the stream
+    fn finish(&mut self) -> (String, String) {
+        let rest = std::mem::take(&mut self.pending);
+        if self.in_think {
+            (String::new(), rest)
+        } else {
+            (rest, String::new())
+        }
+    }
+}
+
+impl CompletionOutput {
+    /// Combine a provider's reply with its separately reported reasoning,
+    /// moving any inline `<think>` blocks into the reasoning as well
+    fn new(content: String, reasoning: Option<String>) -> Self {
+        let mut splitter = ThinkTagSplitter::default();
+        let (mut visible, mut thoughts) = splitter.push(&content);
+        let (rest_visible, rest_thoughts) = splitter.finish();
+        visible.push_str(&rest_visible);
+        thoughts.push_str(&rest_thoughts);
+
+        let reasoning = [reasoning.unwrap_or_default(), thoughts]
+            .into_iter()
+            .map(|part| part.trim().to_string())
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        if reasoning.is_empty() {
+            return Self { content: visible, reasoning: None, usage: None, api_config_id: None, finish_reason: None };
+        }
+
+        // Drop the blank lines models leave between their thoughts and the reply
+        Self {
+            content: visible.trim_start().to_string(),
+            reasoning: Some(reasoning),
+            usage: None,
+            api_config_id: None,
+            finish_reason: None,
+        }
+    }
+
+    fn with_usage(mut self, usage: Option<TokenUsage>) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    fn with_finish_reason(mut self, reason: Option<&str>) -> Self {
+        self.finish_reason = reason.map(normalize_finish_reason);
+        self
+    }
+
+    fn from_choice(choice: &ChatChoice) -> Self {
+        // Convert content Value to String
+        let content = match &choice.message.content {
+            serde_json::Value::String(s) => s.clone(),
+            serde_json::Value::Null => String::new(),
+            other => other.to_string(),
+        };
+        let reasoning = choice.message.reasoning.clone()
+            .or_else(|| choice.message.reasoning_content.clone());
+
+        Self::new(content, reasoning).with_finish_reason(choice.finish_reason.as_deref())
+    }
+}
+
+/// Map a provider's stop reason onto OpenAI's names, so `length` always
+/// means the reply hit the token limit
+fn normalize_finish_reason(reason: &str) -> String {
+    match reason {
+        // Anthropic and Gemini
+        "max_tokens" | "MAX_TOKENS" => "length".to_string(),
+        "end_turn" | "stop_sequence" | "STOP" => "stop".to_string(),
+        other => other.to_lowercase(),
+    }
+}
+
+/// Clamp `max_tokens` to what the model can actually produce, so an
+/// oversized setting doesn't turn into a provider error. Unknown models
+/// pass through unchanged.
+fn clamp_max_tokens(config: &ApiConfig) -> ApiConfig {
+    let mut config = config.clone();
+
+    if let (Some(requested), Some(limit)) = (config.max_tokens, max_output_tokens(&config.model)) {
+        if requested > limit {
+            eprintln!(
+                "Clamping max_tokens for model {} from {} to {}",
+                config.model, requested, limit
+            );
+            config.max_tokens = Some(limit);
+        }
+    }
+
+    config
+}
+
+/// One line of a server-sent event stream
+enum SseLine<'a> {
+    Data(&'a str),
+    Done,
+    /// Comments (`: keep-alive`), blank lines, empty `data:` events and
+    /// fields we don't use (`event:`, `id:`, `retry:`)
+    Ignore,
+}
+
+fn parse_sse_line(line: &str) -> SseLine<'_> {
+    let Some(data) = line.strip_prefix("data:") else {
+        return SseLine::Ignore;
+    };
+
+    match data.trim() {
+        "" => SseLine::Ignore,
+        "[DONE]" => SseLine::Done,
+        data => SseLine::Data(data),
+    }
+}
+
+/// Pull system messages out for providers that take them as a separate
+/// request field rather than as part of the conversation
+fn split_system_messages(messages: Vec<ChatMessage>) -> (Option<String>, Vec<ChatMessage>) {
+    let (system, conversation): (Vec<ChatMessage>, Vec<ChatMessage>) = messages
+        .into_iter()
+        .partition(|msg| msg.role == "system");
+
+    let system = system
+        .iter()
+        .filter_map(|msg| msg.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    ((!system.is_empty()).then_some(system), conversation)
+}
+
+/// Forward one parsed stream delta to the frontend: visible text on
+/// `streaming_chunk`, reasoning on `reasoning_chunk`
+fn emit_stream_chunks(
+    window: &tauri::Window,
+    message_id: &str,
+    content: &str,
+    reasoning: &str,
+    full_response: &mut String,
+    full_reasoning: &mut String,
+) {
+    if !reasoning.is_empty() {
+        full_reasoning.push_str(reasoning);
+        let _ = window.emit("reasoning_chunk", serde_json::json!({
+            "message_id": message_id,
+            "chunk": reasoning,
+            "full_reasoning": full_reasoning
+        }));
+    }
+
+    if !content.is_empty() {
+        full_response.push_str(content);
+        let _ = window.emit("streaming_chunk", serde_json::json!({
+            "message_id": message_id,
+            "chunk": content,
+            "full_content": full_response
+        }));
+    }
+}
 
 pub struct Database {
     pool: Pool<Sqlite>,
+    /// Set on shutdown so in-flight streams stop early
+    closing: AtomicBool,
+    /// Number of `send_chat_completion_streaming` calls in progress
+    active_streams: AtomicUsize,
+    /// Bumped by `abort_streams`; streams started before the bump stop
+    stream_epoch: AtomicU64,
+    /// The database file; `None` for in-memory databases
+    path: Option<PathBuf>,
+    /// Per chat: details of the provider error behind its latest failed send
+    last_api_errors: std::sync::Mutex<HashMap<String, ApiErrorDetails>>,
+    /// What was recovered if the file was rebuilt on this launch
+    startup_repair: Option<RepairReport>,
+}
+
+/// Counts a stream as active for as long as it is alive
+struct ActiveStream<'a> {
+    counter: &'a AtomicUsize,
+    epoch: u64,
+}
+
+impl<'a> ActiveStream<'a> {
+    fn start(counter: &'a AtomicUsize, epoch: &AtomicU64) -> Self {
+        counter.fetch_add(1, Ordering::SeqCst);
+        Self { counter, epoch: epoch.load(Ordering::SeqCst) }
+    }
+}
+
+impl Drop for ActiveStream<'_> {
+    fn drop(&mut self) {
+        self.counter.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Sent after a partial reply so the model picks up where it stopped
+pub const CONTINUE_PROMPT: &str = "Your previous response was cut off. Continue exactly where it stopped, without repeating anything or adding a preamble.";
+
+// Wait before reconnecting a dropped stream, doubling on each attempt
+const RECONNECT_BASE_DELAY: std::time::Duration = std::time::Duration::from_millis(500);
+const RECONNECT_MAX_DELAY: std::time::Duration = std::time::Duration::from_secs(8);
+
+/// A streamed reply that failed after some of it had arrived. Callers can
+/// downcast to this to keep the partial reply.
+#[derive(Debug)]
+pub struct StreamInterrupted {
+    pub partial: CompletionOutput,
+    pub source: anyhow::Error,
+}
+
+impl std::fmt::Display for StreamInterrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl std::error::Error for StreamInterrupted {}
+
+/// A provider answered with an error status
+#[derive(Debug, thiserror::Error)]
+#[error("{message}")]
+pub struct ProviderError {
+    pub status: u16,
+    pub message: String,
+    pub details: ApiErrorDetails,
+}
+
+impl ProviderError {
+    /// `label` prefixes the raw `body` in the message, which the UI has always shown
+    fn new(provider: &ApiProvider, status: reqwest::StatusCode, label: &str, body: &str) -> Self {
+        Self {
+            status: status.as_u16(),
+            message: format!("{}: {}", label, body),
+            details: ApiErrorDetails::from_response(provider.clone(), status.as_u16(), body),
+        }
+    }
+}
+
+/// Whether a failed request is worth repeating against a fallback config:
+/// the provider was unreachable, timed out, rate limited or had a server
+/// error. Rejections of the request itself would fail there too.
+fn is_provider_unavailable(err: &anyhow::Error) -> bool {
+    let err = err.downcast_ref::<StreamInterrupted>().map_or(err, |interrupted| &interrupted.source);
+    if let Some(err) = err.downcast_ref::<ProviderError>() {
+        return matches!(err.status, 408 | 429) || err.status >= 500;
+    }
+    err.downcast_ref::<reqwest::Error>()
+        .is_some_and(|err| err.is_connect() || err.is_timeout())
+}
+
+/// Resolve the directory holding the database and other app data.
+///
+/// `CHATME_DATA_DIR` overrides the platform default so portable installs and
+/// tests can relocate everything.
+pub fn app_data_dir() -> Result<PathBuf> {
+    match std::env::var_os("CHATME_DATA_DIR") {
+        Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+        _ => dirs::data_local_dir()
+            .map(|dir| dir.join("chatme"))
+            .ok_or_else(|| anyhow::anyhow!("Could not determine the local data directory; set CHATME_DATA_DIR")),
+    }
+}
+
+/// Create the data directory if needed and make sure we can write to it
+fn ensure_writable_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir).map_err(|e| {
+        anyhow::anyhow!("Failed to create data directory {}: {}", dir.display(), e)
+    })?;
+
+    let probe = dir.join(format!(".write-test-{}", Uuid::new_v4()));
+    std::fs::write(&probe, b"").map_err(|e| {
+        anyhow::anyhow!("Data directory {} is not writable: {}", dir.display(), e)
+    })?;
+    let _ = std::fs::remove_file(&probe);
+
+    Ok(())
+}
+
+/// Longest string parameter kept verbatim in the agent activity log
+const MAX_LOGGED_PARAMETER_CHARS: usize = 1_000;
+
+/// Default and maximum number of entries `get_all_agent_actions` returns
+const DEFAULT_AGENT_ACTION_LOG_LIMIT: usize = 100;
+const MAX_AGENT_ACTION_LOG_LIMIT: usize = 1_000;
+
+/// Parameters as they go into the activity log, with long strings (file
+/// contents, code) cut down to a prefix and their full length
+fn loggable_parameters(parameters: &HashMap<String, serde_json::Value>) -> HashMap<String, serde_json::Value> {
+    parameters.iter()
+        .map(|(name, value)| {
+            let value = match value {
+                serde_json::Value::String(text) if text.chars().count() > MAX_LOGGED_PARAMETER_CHARS => {
+                    let prefix: String = text.chars().take(MAX_LOGGED_PARAMETER_CHARS).collect();
+                    serde_json::Value::String(format!("{}… ({} chars)", prefix, text.chars().count()))
+                }
+                other => other.clone(),
+            };
+            (name.clone(), value)
+        })
+        .collect()
+}
+
+/// Default and maximum number of entries `get_operation_log` returns
+const DEFAULT_OPERATION_LOG_LIMIT: usize = 100;
+const MAX_OPERATION_LOG_LIMIT: usize = 1_000;
+
+/// `password=…` or `API_KEY: …`, with the value in the second group
+static SECRET_ASSIGNMENT: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(
+        r#"(?i)(\w*(?:password|passwd|secret|token|api[_-]?key|auth)\w*\s*[=:]\s*)("[^"]*"|'[^']*'|[^\s"']+)"#
+    ).expect("valid secret assignment pattern")
+});
+
+/// `--token …` inside a command line, with the value in the second group
+static SECRET_FLAG_VALUE: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(
+        r#"(?i)(--?[\w-]*(?:password|passwd|secret|token|api[_-]?key|auth)[\w-]*\s+)("[^"]*"|'[^']*'|[^\s"'-][^\s"']*)"#
+    ).expect("valid secret flag pattern")
+});
+
+static BEARER_TOKEN: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"(?i)\b(bearer|basic)\s+[A-Za-z0-9._~+/=-]+").expect("valid bearer pattern")
+});
+
+static API_KEY: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"\b(sk|pk|ghp|gho|xox[abp])[-_][A-Za-z0-9_-]{8,}").expect("valid key pattern")
+});
+
+/// A parameter name that says it holds a secret
+static SECRET_NAME: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"(?i)password|passwd|secret|token|api[_-]?key|auth").expect("valid secret name pattern")
+});
+
+/// A whole argument that is a secret flag like `--token`
+static SECRET_FLAG: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"(?i)^--?[\w-]*(?:password|passwd|secret|token|api[_-]?key|auth)[\w-]*$")
+        .expect("valid secret flag pattern")
+});
+
+/// Mask values that look like credentials: `password=…`, `API_KEY: …`,
+/// `--token …`, bearer tokens and `sk-…` style keys
+fn redact_secrets(text: &str) -> String {
+    let text = BEARER_TOKEN.replace_all(text, "${1} [redacted]");
+    let text = API_KEY.replace_all(&text, "[redacted]");
+    let text = SECRET_ASSIGNMENT.replace_all(&text, "${1}[redacted]");
+    SECRET_FLAG_VALUE.replace_all(&text, "${1}[redacted]").into_owned()
+}
+
+/// Parameters as they go into the operation log: shortened like the agent
+/// activity log, with secrets masked and secret-named parameters hidden
+fn redacted_parameters(parameters: &HashMap<String, serde_json::Value>) -> HashMap<String, serde_json::Value> {
+    loggable_parameters(parameters)
+        .into_iter()
+        .map(|(name, value)| {
+            let value = if SECRET_NAME.is_match(&name) {
+                serde_json::Value::String("[redacted]".to_string())
+            } else {
+                redact_value(value)
+            };
+            (name, value)
+        })
+        .collect()
+}
+
+// Argument lists get the same treatment, including a value passed as the
+// element after a secret flag like `--token`
+fn redact_value(value: serde_json::Value) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(text) => serde_json::Value::String(redact_secrets(&text)),
+        serde_json::Value::Array(items) => {
+            let mut hide_next = false;
+            serde_json::Value::Array(items.into_iter()
+                .map(|item| {
+                    let hide = std::mem::replace(&mut hide_next, item.as_str().is_some_and(|arg| SECRET_FLAG.is_match(arg)));
+                    if hide {
+                        serde_json::Value::String("[redacted]".to_string())
+                    } else {
+                        redact_value(item)
+                    }
+                })
+                .collect())
+        }
+        other => other,
+    }
+}
+
+/// What `PRAGMA integrity_check` reports, minus the single `ok` row of a healthy database
+async fn integrity_problems<'e, E: sqlx::SqliteExecutor<'e>>(executor: E) -> Result<Vec<String>> {
+    let rows: Vec<String> = sqlx::query_scalar("PRAGMA integrity_check")
+        .fetch_all(executor)
+        .await?;
+
+    Ok(rows.into_iter().filter(|row| row != "ok").collect())
+}
+
+/// File the damaged database at `database_path` is moved to, with its WAL and shared-memory files alongside
+fn corrupt_backup_path(database_path: &Path) -> PathBuf {
+    let stamp = Utc::now().format("%Y%m%d-%H%M%S");
+    PathBuf::from(format!("{}.corrupt-{}", database_path.display(), stamp))
+}
+
+/// Whether an error means the file itself is damaged (`SQLITE_CORRUPT` or
+/// `SQLITE_NOTADB`), as opposed to being locked, unreadable or missing
+fn is_corruption_error(error: &sqlx::Error) -> bool {
+    const SQLITE_CORRUPT: i32 = 11;
+    const SQLITE_NOTADB: i32 = 26;
+
+    match error {
+        sqlx::Error::Database(db_error) => db_error.code()
+            .and_then(|code| code.parse::<i32>().ok())
+            .is_some_and(|code| matches!(code & 0xff, SQLITE_CORRUPT | SQLITE_NOTADB)),
+        _ => false,
+    }
+}
+
+/// Move `database_path` (and its `-wal`/`-shm` files) aside and put `recovered` in its place
+fn swap_in_recovered(database_path: &Path, recovered: &Path) -> Result<PathBuf> {
+    let backup = corrupt_backup_path(database_path);
+
+    for suffix in ["", "-wal", "-shm"] {
+        let from = PathBuf::from(format!("{}{}", database_path.display(), suffix));
+        if from.exists() {
+            let to = PathBuf::from(format!("{}{}", backup.display(), suffix));
+            std::fs::rename(&from, &to)
+                .map_err(|e| anyhow::anyhow!("Failed to move {} aside: {}", from.display(), e))?;
+        }
+    }
+
+    std::fs::rename(recovered, database_path)
+        .map_err(|e| anyhow::anyhow!("Failed to move the recovered database into place: {}", e))?;
+
+    Ok(backup)
+}
+
+/// Copy whatever can still be read from `source` into a fresh database at
+/// `target` with the current schema. Tables are copied in bulk, falling back
+/// to row by row when the bulk copy hits damaged pages. The copy is built in
+/// a scratch file and only appears at `target` once it's complete.
+async fn recover_into(source: &Path, target: &Path) -> Result<Vec<TableRecovery>> {
+    let partial = PathBuf::from(format!("{}.partial", target.display()));
+    let outcome = copy_readable_rows(source, &partial).await;
+
+    match outcome {
+        Ok(report) => {
+            std::fs::rename(&partial, target)
+                .map_err(|e| anyhow::anyhow!("Failed to finish the recovered database: {}", e))?;
+            Ok(report)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
+async fn copy_readable_rows(source: &Path, target: &Path) -> Result<Vec<TableRecovery>> {
+    use sqlx::Connection;
+
+    let _ = std::fs::remove_file(target);
+    let mut conn = sqlx::SqliteConnection::connect(&format!("sqlite:{}?mode=rwc", target.display())).await?;
+    sqlx::migrate!("./migrations").run(&mut conn).await?;
+
+    // Rows arrive in whatever order they can be read, so don't enforce references
+    sqlx::query("PRAGMA foreign_keys = OFF").execute(&mut conn).await?;
+    sqlx::query("ATTACH DATABASE ? AS damaged")
+        .bind(source.to_string_lossy().to_string())
+        .execute(&mut conn)
+        .await
+        .map_err(|e| anyhow::anyhow!("The damaged database can't be read at all: {}", e))?;
+
+    // The search index and its shadow tables are keyed by the damaged file's
+    // rowids; it's rebuilt from the copied messages instead
+    let tables: Vec<String> = sqlx::query_scalar(
+        r"SELECT name FROM main.sqlite_master
+        WHERE type = 'table' AND name NOT LIKE 'sqlite_%' AND name != '_sqlx_migrations' AND name NOT LIKE 'messages\_fts%' ESCAPE '\'
+        ORDER BY name"
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    let mut report = Vec::new();
+    for table in tables {
+        let columns = |schema: &'static str| format!("SELECT name FROM pragma_table_info('{}', '{}')", table.replace('\'', "''"), schema);
+        let target_columns: Vec<String> = sqlx::query_scalar(&columns("main")).fetch_all(&mut conn).await?;
+        let source_columns: Vec<String> = sqlx::query_scalar(&columns("damaged")).fetch_all(&mut conn).await
+            .unwrap_or_default();
+        let mut shared: Vec<String> = target_columns.into_iter()
+            .filter(|column| source_columns.contains(column))
+            .map(|column| format!("\"{}\"", column.replace('"', "\"\"")))
+            .collect();
+        if shared.is_empty() {
+            continue;
+        }
+        // The search index refers to messages by rowid, so keep them stable
+        if table == "messages" {
+            shared.insert(0, "rowid".to_string());
+        }
+
+        let columns = shared.join(", ");
+        let copy = format!("INSERT OR REPLACE INTO main.\"{table}\" ({columns}) SELECT {columns} FROM damaged.\"{table}\"");
+        let mut recovery = TableRecovery { table: table.clone(), rows_recovered: 0, rows_lost: 0 };
+
+        match sqlx::query(&copy).execute(&mut conn).await {
+            Ok(result) => recovery.rows_recovered = result.rows_affected(),
+            Err(_) => {
+                // Collect the rowids that can still be listed, then copy them one at a time
+                let mut rowids = Vec::new();
+                {
+                    let listing = format!("SELECT rowid FROM damaged.\"{table}\" ORDER BY rowid");
+                    let mut stream = sqlx::query_scalar::<_, i64>(&listing).fetch(&mut conn);
+                    while let Some(Ok(rowid)) = futures_util::StreamExt::next(&mut stream).await {
+                        rowids.push(rowid);
+                    }
+                }
+
+                let single = format!("{copy} WHERE rowid = ?");
+                for rowid in rowids {
+                    match sqlx::query(&single).bind(rowid).execute(&mut conn).await {
+                        Ok(result) if result.rows_affected() > 0 => recovery.rows_recovered += 1,
+                        _ => recovery.rows_lost += 1,
+                    }
+                }
+            }
+        }
+
+        report.push(recovery);
+    }
+
+    sqlx::query("DETACH DATABASE damaged").execute(&mut conn).await?;
+    sqlx::query("INSERT INTO messages_fts(messages_fts) VALUES('rebuild')").execute(&mut conn).await?;
+    conn.close().await?;
+
+    Ok(report)
+}
+
+/// Check the database file before the app opens it and rebuild it from
+/// whatever is readable if the check fails. Returns `None` when it's healthy.
+/// Errors that don't point at a damaged file (a lock, missing permissions)
+/// are returned as-is rather than treated as corruption.
+async fn repair_on_startup(database_path: &Path) -> Result<Option<RepairReport>> {
+    let url = format!("sqlite:{}", database_path.display());
+    let problems = match SqlitePool::connect(&url).await {
+        Ok(pool) => {
+            let checked = sqlx::query_scalar::<_, String>("PRAGMA integrity_check")
+                .fetch_all(&pool)
+                .await;
+            pool.close().await;
+            match checked {
+                Ok(rows) => rows.into_iter().filter(|row| row != "ok").collect(),
+                Err(e) if is_corruption_error(&e) => vec![e.to_string()],
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Err(e) if is_corruption_error(&e) => vec![e.to_string()],
+        Err(e) => return Err(e.into()),
+    };
+    if problems.is_empty() {
+        return Ok(None);
+    }
+
+    let recovered = database_path.with_extension("db.recovered");
+    let tables = recover_into(database_path, &recovered).await
+        .map_err(|e| anyhow::anyhow!("Database at {} is damaged and couldn't be recovered: {}", database_path.display(), e))?;
+    let backup = swap_in_recovered(database_path, &recovered)?;
+
+    Ok(Some(RepairReport {
+        problems,
+        tables,
+        backup_path: backup.to_string_lossy().to_string(),
+    }))
+}
+
+/// Escape `%`, `_` and the escape char itself for use in a `LIKE ... ESCAPE '\'` pattern
+fn like_pattern(query: &str) -> String {
+    let escaped = query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Quote `query` as an FTS5 phrase whose last word may be a prefix, so
+/// operators and punctuation in it are taken literally
+fn fts_phrase(query: &str) -> String {
+    format!("\"{}\" *", query.replace('"', "\"\""))
+}
+
+/// Cut a window of text around the first match so the UI can show context
+fn snippet_around(text: &str, start: usize, end: usize) -> String {
+    const CONTEXT_CHARS: usize = 40;
+
+    let snippet_start = text[..start]
+        .char_indices()
+        .rev()
+        .nth(CONTEXT_CHARS - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let snippet_end = text[end..]
+        .char_indices()
+        .nth(CONTEXT_CHARS)
+        .map(|(i, _)| end + i)
+        .unwrap_or(text.len());
+
+    let mut snippet = String::new();
+    if snippet_start > 0 {
+        snippet.push_str("...");
+    }
+    snippet.push_str(text[snippet_start..snippet_end].trim());
+    if snippet_end < text.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+fn is_duplicate_message(previous: &Message, role: &MessageRole, content: &str, images: Option<&[String]>) -> bool {
+    previous.role == *role
+        && previous.content == content
+        && previous.images.as_deref().unwrap_or_default() == images.unwrap_or_default()
+}
+
+fn message_from_row(row: &SqliteRow) -> Result<Message> {
+    let role_str: String = row.try_get("role")?;
+    let role = match role_str.as_str() {
+        "user" => MessageRole::User,
+        "assistant" => MessageRole::Assistant,
+        _ => return Err(anyhow::anyhow!("Invalid message role: {}", role_str)),
+    };
+
+    // Parse images from JSON string
+    let images: Option<Vec<String>> = match row.try_get::<Option<String>, _>("images")? {
+        Some(images_str) => serde_json::from_str(&images_str).ok(),
+        None => None,
+    };
+
+    Ok(Message {
+        id: row.try_get("id")?,
+        chat_id: row.try_get("chat_id")?,
+        content: row.try_get("content")?,
+        role,
+        created_at: row.try_get("created_at")?,
+        images,
+        api_config_id: row.try_get("api_config_id")?,
+        reasoning: row.try_get("reasoning")?,
+        finish_reason: row.try_get("finish_reason")?,
+        metrics: None,
+        created_at_local: None,
+    })
+}
+
+fn chat_with_last_message_from_row(row: &SqliteRow) -> ChatWithLastMessage {
+    ChatWithLastMessage {
+        id: row.get("id"),
+        title: row.get("title"),
+        api_config_id: row.get("api_config_id"),
+        api_config_name: row.get("api_config_name"),
+        sort_order: row.get("sort_order"),
+        pinned: row.get("pinned"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+        last_message: row.get("last_message"),
+        last_message_time: row.get("last_message_time"),
+        unread_count: 0, // TODO: Implement unread count logic
+        created_at_local: None,
+    }
 }
 
 impl Database {
     pub async fn new() -> Result<Self> {
-        let app_dir = dirs::data_local_dir()
-            .map(|dir| dir.join("chatme"))
-            .unwrap_or_else(|| PathBuf::from("."));
-        
-        std::fs::create_dir_all(&app_dir)?;
+        Self::with_data_dir(app_data_dir()?).await
+    }
+
+    pub async fn with_data_dir(app_dir: PathBuf) -> Result<Self> {
+        ensure_writable_dir(&app_dir)?;
         let database_path = app_dir.join("chatme.db");
         let database_url = format!("sqlite:{}", database_path.display());
 
         // Create database if it doesn't exist
+        let mut startup_repair = None;
         if !Sqlite::database_exists(&database_url).await.unwrap_or(false) {
             Sqlite::create_database(&database_url).await?;
+        } else if let Some(report) = repair_on_startup(&database_path).await? {
+            eprintln!(
+                "Database failed its integrity check ({}) and was rebuilt; recovered {} rows, lost {}. The damaged file is at {}",
+                report.problems.join("; "),
+                report.tables.iter().map(|table| table.rows_recovered).sum::<u64>(),
+                report.tables.iter().map(|table| table.rows_lost).sum::<u64>(),
+                report.backup_path,
+            );
+            startup_repair = Some(report);
         }
 
         let pool = SqlitePool::connect(&database_url).await?;
 
+        let mut db = Self::from_pool(pool).await?;
+        db.path = Some(database_path);
+        db.startup_repair = startup_repair;
+        if let Ok(settings) = db.get_app_settings().await {
+            set_max_threads(settings.max_threads as usize);
+        }
+        Ok(db)
+    }
+
+    /// Open a throwaway in-memory database with the full schema applied.
+    #[cfg(test)]
+    pub async fn new_in_memory() -> Result<Self> {
+        // Every connection to `sqlite::memory:` gets its own database, so pin
+        // the pool to a single connection that is never recycled.
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+            .connect("sqlite::memory:")
+            .await?;
+
+        Self::from_pool(pool).await
+    }
+
+    async fn from_pool(pool: Pool<Sqlite>) -> Result<Self> {
         // Run migrations
         sqlx::migrate!("./migrations").run(&pool).await.map_err(|e| {
             eprintln!("Migration error: {}", e);
             e
         })?;
 
-        Ok(Database { pool })
+        Ok(Database {
+            pool,
+            closing: AtomicBool::new(false),
+            active_streams: AtomicUsize::new(0),
+            stream_epoch: AtomicU64::new(0),
+            path: None,
+            last_api_errors: std::sync::Mutex::new(HashMap::new()),
+            startup_repair: None,
+        })
+    }
+
+    /// Stop in-flight streams, checkpoint the WAL into the main database file
+    /// and close the pool, so the next launch doesn't have to recover
+    pub async fn close(&self) {
+        self.closing.store(true, Ordering::SeqCst);
+
+        if let Err(e) = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&self.pool).await {
+            eprintln!("WAL checkpoint failed: {}", e);
+        }
+
+        self.pool.close().await;
+    }
+
+    /// Run an integrity check on the open database. The file can't be
+    /// rebuilt safely while the app keeps writing to it; every launch checks
+    /// it before opening the pool, so damage found here is repaired by a restart.
+    pub async fn check_integrity(&self) -> Result<IntegrityReport> {
+        let problems = integrity_problems(&self.pool).await?;
+
+        Ok(IntegrityReport {
+            restart_required: !problems.is_empty() && self.path.is_some(),
+            problems,
+            startup_repair: self.startup_repair.clone(),
+        })
+    }
+
+    /// Cheap liveness check that doesn't touch any user data
+    pub async fn ping(&self) -> bool {
+        sqlx::query("SELECT 1").execute(&self.pool).await.is_ok()
+    }
+
+    /// Version of the SQLite library the database runs on
+    pub async fn sqlite_version(&self) -> Result<String> {
+        Ok(sqlx::query_scalar("SELECT sqlite_version()").fetch_one(&self.pool).await?)
+    }
+
+    /// Latest migration applied to the schema
+    pub async fn schema_version(&self) -> Result<Option<i64>> {
+        Ok(sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success = 1").fetch_one(&self.pool).await?)
+    }
+
+    pub fn active_stream_count(&self) -> usize {
+        self.active_streams.load(Ordering::SeqCst)
+    }
+
+    /// Stop every stream in progress, returning how many there were. Streams
+    /// notice between chunks and fail with a cancellation error.
+    pub fn abort_streams(&self) -> usize {
+        self.stream_epoch.fetch_add(1, Ordering::SeqCst);
+        self.active_stream_count()
+    }
+
+    /// Structured details of the provider error that made the latest send in
+    /// `chat_id` fail. `None` once a send there succeeds or fails for another
+    /// reason, so a failure in one chat never shows up in another.
+    pub fn last_api_error(&self, chat_id: &str) -> Option<ApiErrorDetails> {
+        self.last_api_errors.lock().ok().and_then(|last| last.get(chat_id).cloned())
+    }
+
+    /// Remember how a send to `config` for `chat_id` ended for `last_api_error`
+    pub fn record_send_outcome<T>(&self, chat_id: &str, config: &ApiConfig, outcome: &Result<T>) {
+        let details = outcome.as_ref().err().and_then(|err| {
+            let err = err.downcast_ref::<StreamInterrupted>().map_or(err, |interrupted| &interrupted.source);
+            if let Some(err) = err.downcast_ref::<ProviderError>() {
+                return Some(err.details.clone());
+            }
+            err.downcast_ref::<reqwest::Error>()
+                .filter(|err| err.status().is_none())
+                .map(|err| ApiErrorDetails::unreachable(config.provider.clone(), err.to_string()))
+        });
+
+        if let Ok(mut last) = self.last_api_errors.lock() {
+            match details {
+                Some(details) => last.insert(chat_id.to_string(), details),
+                None => last.remove(chat_id),
+            };
+        }
+    }
+
+    fn ensure_open(&self) -> Result<()> {
+        if self.closing.load(Ordering::SeqCst) {
+            return Err(anyhow::anyhow!("Cancelled: the app is shutting down"));
+        }
+        Ok(())
+    }
+
+    fn ensure_stream_live(&self, stream: &ActiveStream) -> Result<()> {
+        self.ensure_open()?;
+        if self.stream_epoch.load(Ordering::SeqCst) != stream.epoch {
+            return Err(anyhow::anyhow!("Cancelled: the stream was aborted"));
+        }
+        Ok(())
     }
 
     // Chat operations
+    async fn ensure_api_config_exists(&self, api_config_id: Option<&str>) -> Result<()> {
+        if let Some(config_id) = api_config_id {
+            let exists: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM api_configs WHERE id = ?)")
+                .bind(config_id)
+                .fetch_one(&self.pool)
+                .await?;
+
+            if !exists {
+                return Err(anyhow::anyhow!("API configuration not found: {}", config_id));
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn create_chat(&self, title: String, api_config_id: Option<String>) -> Result<Chat> {
+        self.ensure_api_config_exists(api_config_id.as_deref()).await?;
+
         let id = Uuid::new_v4().to_string();
         let now = Utc::now();
 
@@ -66,43 +985,96 @@ impl Database {
                 c.title,
                 c.api_config_id,
                 ac.name as api_config_name,
+                c.sort_order,
+                c.pinned,
                 c.created_at,
                 c.updated_at,
                 m.content as last_message,
                 m.created_at as last_message_time
             FROM chats c
             LEFT JOIN api_configs ac ON c.api_config_id = ac.id
-            LEFT JOIN (
-                SELECT DISTINCT chat_id, content, created_at,
-                       ROW_NUMBER() OVER (PARTITION BY chat_id ORDER BY created_at DESC) as rn
-                FROM messages
-            ) m ON c.id = m.chat_id AND m.rn = 1
-            ORDER BY COALESCE(m.created_at, c.updated_at) DESC
+            LEFT JOIN messages m ON m.id = c.last_message_id
+            ORDER BY
+                c.sort_order IS NULL,
+                c.sort_order ASC,
+                COALESCE(c.last_message_at, c.updated_at) DESC,
+                -- Chats active in the same instant (e.g. after an import) keep a stable order
+                c.created_at DESC,
+                c.id ASC
             "#
         )
         .fetch_all(&self.pool)
         .await?;
 
-        let chats = rows
-            .into_iter()
-            .map(|row| {
-                ChatWithLastMessage {
-                    id: row.get("id"),
-                    title: row.get("title"),
-                    api_config_id: row.get("api_config_id"),
-                    api_config_name: row.get("api_config_name"),
-                    created_at: row.get("created_at"),
-                    updated_at: row.get("updated_at"),
-                    last_message: row.get("last_message"),
-                    last_message_time: row.get("last_message_time"),
-                    unread_count: 0, // TODO: Implement unread count logic
-                }
-            })
-            .collect();
+        let chats = rows.iter().map(chat_with_last_message_from_row).collect();
+
+        Ok(chats)
+    }
+
+    /// The `limit` most recently active chats, for quick switching.
+    ///
+    /// Cheaper than `get_chats`: the limit is applied on the `updated_at`
+    /// index first (it is bumped on every new message).
+    pub async fn get_recent_chats(&self, limit: i64) -> Result<Vec<ChatWithLastMessage>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                c.id,
+                c.title,
+                c.api_config_id,
+                ac.name as api_config_name,
+                c.sort_order,
+                c.pinned,
+                c.created_at,
+                c.updated_at,
+                m.content as last_message,
+                m.created_at as last_message_time
+            FROM (
+                SELECT * FROM chats ORDER BY updated_at DESC, id ASC LIMIT ?
+            ) c
+            LEFT JOIN api_configs ac ON c.api_config_id = ac.id
+            LEFT JOIN messages m ON m.id = c.last_message_id
+            ORDER BY c.updated_at DESC, c.id ASC
+            "#
+        )
+        .bind(limit.max(0))
+        .fetch_all(&self.pool)
+        .await?;
+
+        let chats = rows.iter().map(chat_with_last_message_from_row).collect();
 
         Ok(chats)
     }
 
+    /// Recompute every chat's `last_message_id` / `last_message_at`. Triggers
+    /// keep them current, so this only repairs data edited outside the app.
+    /// Returns the number of chats whose values changed.
+    pub async fn backfill_last_messages(&self) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            WITH latest AS (
+                SELECT
+                    c.id,
+                    (SELECT id FROM messages WHERE chat_id = c.id
+                     ORDER BY created_at DESC, rowid DESC LIMIT 1) AS message_id,
+                    (SELECT MAX(created_at) FROM messages WHERE chat_id = c.id) AS message_at
+                FROM chats c
+            )
+            UPDATE chats SET
+                last_message_id = latest.message_id,
+                last_message_at = latest.message_at
+            FROM latest
+            WHERE chats.id = latest.id
+              AND (chats.last_message_id IS NOT latest.message_id
+                   OR chats.last_message_at IS NOT latest.message_at)
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
     pub async fn get_chat(&self, chat_id: &str) -> Result<Option<Chat>> {
         let chat = sqlx::query_as::<_, Chat>("SELECT * FROM chats WHERE id = ?")
             .bind(chat_id)
@@ -112,14 +1084,40 @@ impl Database {
         Ok(chat)
     }
 
-    pub async fn update_chat(&self, chat_id: &str, title: String, api_config_id: Option<String>) -> Result<Chat> {
+    pub async fn update_chat(&self, chat_id: &str, request: UpdateChatRequest) -> Result<Chat> {
+        request.validate()?;
+        self.ensure_api_config_exists(request.api_config_id.as_ref().and_then(Option::as_deref)).await?;
+
         let now = Utc::now();
+        let pinned_context = request.pinned_context.as_ref().map(|context| {
+            context.as_deref()
+                .map(str::trim)
+                .filter(|context| !context.is_empty())
+        });
         
+        // Each optional column is only written when the request carries it
         let chat = sqlx::query_as::<_, Chat>(
-            "UPDATE chats SET title = ?, api_config_id = ?, updated_at = ? WHERE id = ? RETURNING *"
+            r#"
+            UPDATE chats SET
+                title = COALESCE(?, title),
+                api_config_id = CASE WHEN ? THEN ? ELSE api_config_id END,
+                temperature_override = CASE WHEN ? THEN ? ELSE temperature_override END,
+                max_tokens_override = CASE WHEN ? THEN ? ELSE max_tokens_override END,
+                pinned_context = CASE WHEN ? THEN ? ELSE pinned_context END,
+                updated_at = ?
+            WHERE id = ?
+            RETURNING *
+            "#
         )
-        .bind(&title)
-        .bind(&api_config_id)
+        .bind(&request.title)
+        .bind(request.api_config_id.is_some())
+        .bind(request.api_config_id.flatten())
+        .bind(request.temperature_override.is_some())
+        .bind(request.temperature_override.flatten())
+        .bind(request.max_tokens_override.is_some())
+        .bind(request.max_tokens_override.flatten())
+        .bind(pinned_context.is_some())
+        .bind(pinned_context.flatten())
         .bind(now)
         .bind(chat_id)
         .fetch_one(&self.pool)
@@ -128,6 +1126,54 @@ impl Database {
         Ok(chat)
     }
 
+    /// The config a chat should send with: its own or the default, with the
+    /// chat's generation overrides applied on top
+    pub async fn get_chat_api_config(&self, chat: &Chat) -> Result<Option<ApiConfig>> {
+        let config = if let Some(config_id) = &chat.api_config_id {
+            self.get_api_config(config_id).await?
+        } else {
+            self.get_default_api_config().await?
+        };
+
+        Ok(config.map(|config| chat.apply_overrides(config)))
+    }
+
+    /// Pin chats to the given order. Chats left out go back to recency
+    /// ordering below them, so an empty list restores the default.
+    pub async fn reorder_chats(&self, ordered_ids: &[String]) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE chats SET sort_order = NULL")
+            .execute(&mut *tx)
+            .await?;
+
+        for (position, chat_id) in ordered_ids.iter().enumerate() {
+            let result = sqlx::query("UPDATE chats SET sort_order = ? WHERE id = ?")
+                .bind(position as i64)
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?;
+
+            if result.rows_affected() == 0 {
+                return Err(anyhow::anyhow!("Chat not found: {}", chat_id));
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Pin or unpin a chat. This isn't activity, so `updated_at` is left alone.
+    pub async fn set_chat_pinned(&self, chat_id: &str, pinned: bool) -> Result<Chat> {
+        sqlx::query_as::<_, Chat>("UPDATE chats SET pinned = ? WHERE id = ? RETURNING *")
+            .bind(pinned)
+            .bind(chat_id)
+            .fetch_optional(&self.pool)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("Chat not found: {}", chat_id))
+    }
+
     pub async fn delete_chat(&self, chat_id: &str) -> Result<()> {
         // Delete messages first (foreign key constraint)
         sqlx::query("DELETE FROM messages WHERE chat_id = ?")
@@ -141,11 +1187,210 @@ impl Database {
             .execute(&self.pool)
             .await?;
 
+        if let Ok(mut last) = self.last_api_errors.lock() {
+            last.remove(chat_id);
+        }
+
         Ok(())
     }
 
+    /// Delete chats with no activity in the last `days` days, along with
+    /// their messages. Pinned chats are kept unless `include_pinned` is set.
+    pub async fn delete_chats_older_than(&self, days: i64, include_pinned: bool) -> Result<u64> {
+        if days < 0 {
+            return Err(anyhow::anyhow!("days must not be negative"));
+        }
+        let cutoff = Utc::now() - chrono::Duration::days(days);
+
+        let mut tx = self.pool.begin().await?;
+
+        // Last activity is the newer of the chat's own timestamp and its latest message
+        let chat_ids: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT c.id FROM chats c
+            WHERE MAX(c.updated_at, COALESCE(
+                    (SELECT MAX(m.created_at) FROM messages m WHERE m.chat_id = c.id),
+                    c.updated_at
+                )) < ?
+              AND (? OR NOT c.pinned)
+            "#
+        )
+        .bind(cutoff)
+        .bind(include_pinned)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for chat_id in &chat_ids {
+            sqlx::query("DELETE FROM messages WHERE chat_id = ?")
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?;
+
+            sqlx::query("DELETE FROM chats WHERE id = ?")
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(chat_ids.len() as u64)
+    }
+
+    /// Search chat titles and message bodies across every chat.
+    ///
+    /// Messages are found through the `messages_fts` full-text index, which
+    /// matches the query as a phrase of whole words, the last of which may be
+    /// a prefix. Titles are few and short, so they are scanned for the query
+    /// as a substring. Hits are ranked by how often the query occurs, then by
+    /// recency.
+    pub async fn global_search(&self, query: &str, limit: usize) -> Result<Vec<GlobalHit>> {
+        let query = query.trim();
+        if query.is_empty() || limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        // Decides which titles really match, since LIKE only folds ASCII
+        // case, and counts occurrences for ranking
+        let matcher = regex::RegexBuilder::new(&regex::escape(query))
+            .case_insensitive(true)
+            .build()?;
+        let pattern = like_pattern(query);
+        // Over-fetch so ranking has something to choose from
+        let candidate_limit = (limit * 4) as i64;
+
+        let mut hits = Vec::new();
+
+        let chat_rows = sqlx::query(
+            "SELECT id, title, updated_at FROM chats WHERE title LIKE ? ESCAPE '\\' ORDER BY updated_at DESC LIMIT ?"
+        )
+        .bind(&pattern)
+        .bind(candidate_limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        for row in chat_rows {
+            let title: String = row.try_get("title")?;
+            let relevance = matcher.find_iter(&title).count();
+            if relevance == 0 {
+                continue;
+            }
+
+            hits.push(GlobalHit {
+                kind: GlobalHitKind::Chat,
+                chat_id: row.try_get("id")?,
+                snippet: title.clone(),
+                chat_title: title,
+                message_id: None,
+                timestamp: row.try_get("updated_at")?,
+                relevance,
+            });
+        }
+
+        let message_rows = sqlx::query(
+            r#"
+            SELECT m.id, m.chat_id, m.content, m.created_at, c.title,
+                snippet(messages_fts, 0, '', '', '…', 16) AS fts_snippet
+            FROM messages_fts
+            JOIN messages m ON m.rowid = messages_fts.rowid
+            JOIN chats c ON c.id = m.chat_id
+            WHERE messages_fts MATCH ?
+            ORDER BY rank
+            LIMIT ?
+            "#
+        )
+        .bind(fts_phrase(query))
+        .bind(candidate_limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        for row in message_rows {
+            let content: String = row.try_get("content")?;
+            // The index also matches across punctuation and diacritics, where
+            // the literal query may not occur; its own snippet covers those
+            let snippet = match matcher.find(&content) {
+                Some(first) => snippet_around(&content, first.start(), first.end()),
+                None => row.try_get("fts_snippet")?,
+            };
+
+            hits.push(GlobalHit {
+                kind: GlobalHitKind::Message,
+                chat_id: row.try_get("chat_id")?,
+                chat_title: row.try_get("title")?,
+                message_id: Some(row.try_get("id")?),
+                snippet,
+                timestamp: row.try_get("created_at")?,
+                relevance: matcher.find_iter(&content).count().max(1),
+            });
+        }
+
+        hits.sort_by(|a, b| {
+            b.relevance
+                .cmp(&a.relevance)
+                .then_with(|| b.timestamp.cmp(&a.timestamp))
+        });
+        hits.truncate(limit);
+
+        Ok(hits)
+    }
+
     // Message operations
     pub async fn create_message(&self, chat_id: String, content: String, role: MessageRole, images: Option<Vec<String>>) -> Result<Message> {
+        self.create_message_with_config(chat_id, content, role, images, None).await
+    }
+
+    /// Like `create_message`, but returns the chat's latest message instead of
+    /// inserting a new one when it has the same role, content and images
+    pub async fn create_message_unless_duplicate(&self, chat_id: String, content: String, role: MessageRole, images: Option<Vec<String>>) -> Result<Message> {
+        let row = sqlx::query("SELECT id, chat_id, content, role, created_at, images, api_config_id, reasoning, finish_reason FROM messages WHERE chat_id = ? ORDER BY created_at DESC LIMIT 1")
+            .bind(&chat_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        if let Some(previous) = row.as_ref().map(message_from_row).transpose()? {
+            if is_duplicate_message(&previous, &role, &content, images.as_deref()) {
+                return Ok(previous);
+            }
+        }
+
+        self.create_message(chat_id, content, role, images).await
+    }
+
+    /// Like `create_message`, but tags the message with the API config that produced it
+    pub async fn create_message_with_config(
+        &self,
+        chat_id: String,
+        content: String,
+        role: MessageRole,
+        images: Option<Vec<String>>,
+        api_config_id: Option<String>,
+    ) -> Result<Message> {
+        self.insert_message(chat_id, content, role, images, api_config_id, None, None).await
+    }
+
+    /// Store a model reply together with its reasoning
+    pub async fn create_assistant_message(
+        &self,
+        chat_id: String,
+        output: CompletionOutput,
+        api_config_id: Option<String>,
+    ) -> Result<Message> {
+        // Credit a fallback config that stood in for the one asked
+        let api_config_id = output.api_config_id.or(api_config_id);
+        self.insert_message(chat_id, output.content, MessageRole::Assistant, None, api_config_id, output.reasoning, output.finish_reason).await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn insert_message(
+        &self,
+        chat_id: String,
+        content: String,
+        role: MessageRole,
+        images: Option<Vec<String>>,
+        api_config_id: Option<String>,
+        reasoning: Option<String>,
+        finish_reason: Option<String>,
+    ) -> Result<Message> {
         let id = Uuid::new_v4().to_string();
         let now = Utc::now();
         
@@ -156,7 +1401,7 @@ impl Database {
         };
 
         sqlx::query(
-            "INSERT INTO messages (id, chat_id, content, role, created_at, images) VALUES (?, ?, ?, ?, ?, ?)"
+            "INSERT INTO messages (id, chat_id, content, role, created_at, images, api_config_id, reasoning, finish_reason) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)"
         )
         .bind(&id)
         .bind(&chat_id)
@@ -164,6 +1409,9 @@ impl Database {
         .bind(&role)
         .bind(now)
         .bind(&images_json)
+        .bind(&api_config_id)
+        .bind(&reasoning)
+        .bind(&finish_reason)
         .execute(&self.pool)
         .await?;
 
@@ -181,62 +1429,468 @@ impl Database {
             role,
             created_at: now,
             images,
+            api_config_id,
+            reasoning,
+            finish_reason,
+            metrics: None,
+            created_at_local: None,
         })
     }
 
+    /// Insert many messages into one chat in a single transaction, in the
+    /// given order, bumping the chat's `updated_at` once at the end
+    pub async fn create_messages(&self, chat_id: &str, messages: Vec<NewMessage>) -> Result<Vec<Message>> {
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let chat_exists: Option<i64> = sqlx::query_scalar("SELECT 1 FROM chats WHERE id = ?")
+            .bind(chat_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        if chat_exists.is_none() {
+            return Err(anyhow::anyhow!("Chat not found: {}", chat_id));
+        }
+
+        let mut created = Vec::with_capacity(messages.len());
+        for (index, message) in messages.into_iter().enumerate() {
+            let id = Uuid::new_v4().to_string();
+            let created_at = message.created_at
+                .unwrap_or_else(|| now + chrono::Duration::microseconds(index as i64));
+            let images_json = match message.images.as_ref() {
+                Some(imgs) if !imgs.is_empty() => Some(serde_json::to_string(imgs)?),
+                _ => None,
+            };
+
+            sqlx::query(
+                "INSERT INTO messages (id, chat_id, content, role, created_at, images, api_config_id, reasoning) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+            )
+            .bind(&id)
+            .bind(chat_id)
+            .bind(&message.content)
+            .bind(&message.role)
+            .bind(created_at)
+            .bind(&images_json)
+            .bind(&message.api_config_id)
+            .bind(&message.reasoning)
+            .execute(&mut *tx)
+            .await?;
+
+            created.push(Message {
+                id,
+                chat_id: chat_id.to_string(),
+                content: message.content,
+                role: message.role,
+                created_at,
+                images: message.images,
+                api_config_id: message.api_config_id,
+                reasoning: message.reasoning,
+                finish_reason: None,
+                metrics: None,
+                created_at_local: None,
+            });
+        }
+
+        sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+            .bind(now)
+            .bind(chat_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(created)
+    }
+
     pub async fn get_messages(&self, chat_id: &str) -> Result<Vec<Message>> {
         // Use the full query with images column
-        let rows = sqlx::query("SELECT id, chat_id, content, role, created_at, images FROM messages WHERE chat_id = ? ORDER BY created_at ASC")
+        let rows = sqlx::query("SELECT id, chat_id, content, role, created_at, images, api_config_id, reasoning, finish_reason FROM messages WHERE chat_id = ? ORDER BY created_at ASC")
             .bind(chat_id)
             .fetch_all(&self.pool)
             .await?;
 
-        let mut messages = Vec::new();
+        rows.iter().map(message_from_row).collect()
+    }
+
+    pub async fn get_message(&self, message_id: &str) -> Result<Option<Message>> {
+        let row = sqlx::query("SELECT id, chat_id, content, role, created_at, images, api_config_id, reasoning, finish_reason FROM messages WHERE id = ?")
+            .bind(message_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.as_ref().map(message_from_row).transpose()
+    }
+
+    pub async fn delete_message(&self, message_id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM messages WHERE id = ?")
+            .bind(message_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_messages(&self, message_ids: &[String]) -> Result<u64> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        let mut deleted = 0;
+
+        for message_id in message_ids {
+            let chat_id: Option<String> = sqlx::query_scalar("DELETE FROM messages WHERE id = ? RETURNING chat_id")
+                .bind(message_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            if let Some(chat_id) = chat_id {
+                deleted += 1;
+
+                sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+                    .bind(now)
+                    .bind(&chat_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(deleted)
+    }
+
+    /// Duplicate a message into another chat with a fresh id and timestamp
+    pub async fn copy_message_to_chat(&self, message_id: &str, target_chat_id: &str) -> Result<Message> {
+        let message = self.get_message(message_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Message not found: {}", message_id))?;
+
+        if self.get_chat(target_chat_id).await?.is_none() {
+            return Err(anyhow::anyhow!("Chat not found: {}", target_chat_id));
+        }
+
+        self.create_message(target_chat_id.to_string(), message.content, message.role, message.images).await
+    }
+
+    /// Append text to an existing message, e.g. the continuation of a
+    /// truncated reply, recording why the appended part stopped
+    pub async fn append_to_message(&self, message_id: &str, text: &str, finish_reason: Option<&str>) -> Result<Message> {
+        let mut message = self.get_message(message_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Message not found: {}", message_id))?;
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE messages SET content = content || ?, finish_reason = ? WHERE id = ?")
+            .bind(text)
+            .bind(finish_reason)
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(&message.chat_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        message.content.push_str(text);
+        message.finish_reason = finish_reason.map(str::to_string);
+        Ok(message)
+    }
+
+    /// Remove messages that repeat the message right before them (same role,
+    /// content and images), e.g. after a double-submitted retry. Returns the
+    /// number of messages removed.
+    pub async fn deduplicate_chat(&self, chat_id: &str) -> Result<u64> {
+        let messages = self.get_messages(chat_id).await?;
+
+        let duplicate_ids: Vec<String> = messages
+            .windows(2)
+            .filter(|pair| is_duplicate_message(&pair[0], &pair[1].role, &pair[1].content, pair[1].images.as_deref()))
+            .map(|pair| pair[1].id.clone())
+            .collect();
+
+        if duplicate_ids.is_empty() {
+            return Ok(0);
+        }
+
+        self.delete_messages(&duplicate_ids).await
+    }
+
+    /// Merge runs of consecutive messages from the same role into the first
+    /// message of each run, joining their text (and reasoning) with newlines.
+    /// Messages with images are left as they are. Flags on merged-away
+    /// messages move to the message they were merged into. Returns the number
+    /// of messages removed.
+    pub async fn compact_chat(&self, chat_id: &str) -> Result<u64> {
+        let messages = self.get_messages(chat_id).await?;
+
+        let has_images = |msg: &Message| msg.images.as_ref().is_some_and(|images| !images.is_empty());
+
+        let mut runs: Vec<Vec<&Message>> = Vec::new();
+        for message in &messages {
+            match runs.last_mut() {
+                Some(run) if run[0].role == message.role && !has_images(run[0]) && !has_images(message) => {
+                    run.push(message)
+                }
+                _ => runs.push(vec![message]),
+            }
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let mut merged = 0;
+
+        for run in runs.iter().filter(|run| run.len() > 1) {
+            let survivor = run[0];
+            let content = run.iter().map(|msg| msg.content.as_str()).collect::<Vec<_>>().join("\n");
+            let reasoning = run
+                .iter()
+                .filter_map(|msg| msg.reasoning.as_deref())
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            sqlx::query("UPDATE messages SET content = ?, reasoning = ? WHERE id = ?")
+                .bind(&content)
+                .bind((!reasoning.is_empty()).then_some(reasoning))
+                .bind(&survivor.id)
+                .execute(&mut *tx)
+                .await?;
+
+            for message in &run[1..] {
+                sqlx::query(
+                    "INSERT OR IGNORE INTO message_flags (message_id, flag_type, created_at) SELECT ?, flag_type, created_at FROM message_flags WHERE message_id = ?"
+                )
+                .bind(&survivor.id)
+                .bind(&message.id)
+                .execute(&mut *tx)
+                .await?;
+
+                sqlx::query("DELETE FROM messages WHERE id = ?")
+                    .bind(&message.id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                merged += 1;
+            }
+        }
+
+        if merged > 0 {
+            sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+                .bind(Utc::now())
+                .bind(chat_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(merged)
+    }
+
+    /// Relabel a message's role, e.g. to fix up an imported conversation
+    pub async fn set_message_role(&self, message_id: &str, role: MessageRole) -> Result<Message> {
+        let mut message = self.get_message(message_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Message not found: {}", message_id))?;
+
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE messages SET role = ? WHERE id = ?")
+            .bind(&role)
+            .bind(message_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(&message.chat_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        message.role = role;
+        Ok(message)
+    }
+
+    /// Remove every message in a chat but keep the chat itself
+    pub async fn clear_chat_messages(&self, chat_id: &str) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        let deleted = sqlx::query("DELETE FROM messages WHERE chat_id = ?")
+            .bind(chat_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        sqlx::query("UPDATE chats SET updated_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(chat_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(deleted)
+    }
+
+    // Message flag operations
+    pub async fn flag_message(&self, message_id: &str, flag: MessageFlag) -> Result<()> {
+        sqlx::query("INSERT OR IGNORE INTO message_flags (message_id, flag_type, created_at) VALUES (?, ?, ?)")
+            .bind(message_id)
+            .bind(flag)
+            .bind(Utc::now())
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn unflag_message(&self, message_id: &str, flag: MessageFlag) -> Result<()> {
+        sqlx::query("DELETE FROM message_flags WHERE message_id = ? AND flag_type = ?")
+            .bind(message_id)
+            .bind(flag)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_flagged_messages(&self, chat_id: &str) -> Result<Vec<FlaggedMessage>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT f.message_id, f.flag_type
+            FROM message_flags f
+            JOIN messages m ON m.id = f.message_id
+            WHERE m.chat_id = ?
+            ORDER BY f.created_at ASC
+            "#
+        )
+        .bind(chat_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut flags_by_message: HashMap<String, Vec<MessageFlag>> = HashMap::new();
         for row in rows {
-            let role_str: String = row.try_get("role")?;
-            let role = match role_str.as_str() {
-                "user" => MessageRole::User,
-                "assistant" => MessageRole::Assistant,
-                _ => return Err(anyhow::anyhow!("Invalid message role: {}", role_str)),
+            let flag_str: String = row.try_get("flag_type")?;
+            let flag = match flag_str.as_str() {
+                "bookmark" => MessageFlag::Bookmark,
+                "thumbs_up" => MessageFlag::ThumbsUp,
+                "thumbs_down" => MessageFlag::ThumbsDown,
+                _ => return Err(anyhow::anyhow!("Invalid message flag: {}", flag_str)),
             };
 
-            // Parse images from JSON string
-            let images: Option<Vec<String>> = match row.try_get::<Option<String>, _>("images")? {
-                Some(images_str) => serde_json::from_str(&images_str).ok(),
-                None => None,
-            };
+            flags_by_message
+                .entry(row.try_get("message_id")?)
+                .or_default()
+                .push(flag);
+        }
+
+        let flagged = self.get_messages(chat_id).await?
+            .into_iter()
+            .filter_map(|message| {
+                flags_by_message
+                    .remove(&message.id)
+                    .map(|flags| FlaggedMessage { message, flags })
+            })
+            .collect();
+
+        Ok(flagged)
+    }
+
+    // Export operations
+    pub async fn export_chat(&self, chat_id: &str) -> Result<ChatExport> {
+        let chat = self.get_chat(chat_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Chat not found: {}", chat_id))?;
+        let messages = self.get_messages(chat_id).await?;
+
+        Ok(ChatExport { chat, messages })
+    }
+
+    /// Write every chat with its messages to `path`, one chat at a time so
+    /// large histories never have to fit in memory at once. Returns the
+    /// number of chats written.
+    pub async fn export_all_chats(&self, path: &Path, format: ExportFormat) -> Result<usize> {
+        use std::io::Write;
+
+        let chat_ids: Vec<String> = sqlx::query_scalar("SELECT id FROM chats ORDER BY created_at ASC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let file = std::fs::File::create(path)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", path.display(), e))?;
+        let mut writer = std::io::BufWriter::new(file);
+
+        let result: Result<usize> = async {
+            if let ExportFormat::Json = format {
+                writer.write_all(b"[")?;
+            }
+
+            let mut written = 0;
+            for chat_id in &chat_ids {
+                // Skip chats deleted since the id list was read
+                let Some(chat) = self.get_chat(chat_id).await? else {
+                    continue;
+                };
+                let export = ChatExport {
+                    chat,
+                    messages: self.get_messages(chat_id).await?,
+                };
+
+                match format {
+                    ExportFormat::Jsonl => {
+                        serde_json::to_writer(&mut writer, &export)?;
+                        writer.write_all(b"\n")?;
+                    }
+                    ExportFormat::Json => {
+                        if written > 0 {
+                            writer.write_all(b",")?;
+                        }
+                        writer.write_all(b"\n")?;
+                        serde_json::to_writer(&mut writer, &export)?;
+                    }
+                }
+                written += 1;
+            }
 
-            messages.push(Message {
-                id: row.try_get("id")?,
-                chat_id: row.try_get("chat_id")?,
-                content: row.try_get("content")?,
-                role,
-                created_at: row.try_get("created_at")?,
-                images,
-            });
-        }
+            if let ExportFormat::Json = format {
+                writer.write_all(b"\n]\n")?;
+            }
+            writer.flush()?;
 
-        Ok(messages)
-    }
+            Ok(written)
+        }.await;
 
-    pub async fn delete_message(&self, message_id: &str) -> Result<()> {
-        sqlx::query("DELETE FROM messages WHERE id = ?")
-            .bind(message_id)
-            .execute(&self.pool)
-            .await?;
+        if result.is_err() {
+            let _ = std::fs::remove_file(path);
+        }
 
-        Ok(())
+        result
     }
 
     // API Configuration operations
-    pub async fn create_api_config(&self, request: CreateApiConfigRequest) -> Result<ApiConfig> {
+    pub async fn create_api_config(&self, mut request: CreateApiConfigRequest) -> Result<ApiConfig> {
+        let settings = self.get_app_settings().await?;
+        request.apply_defaults(&settings);
+        request.normalize()?;
+        request.validate()?;
+
         let id = Uuid::new_v4().to_string();
+        self.validate_fallback(&id, request.fallback_config_id.as_deref()).await?;
         let now = Utc::now();
 
+        // Unset the other defaults and write this one atomically so concurrent
+        // calls can't leave zero or two defaults behind
+        let mut tx = self.pool.begin().await?;
+
         // If this is set as default, unset all other defaults
         if request.is_default {
             sqlx::query("UPDATE api_configs SET is_default = FALSE")
-                .execute(&self.pool)
+                .execute(&mut *tx)
                 .await?;
         }
 
@@ -244,8 +1898,8 @@ impl Database {
             r#"
             INSERT INTO api_configs (
                 id, name, provider, api_key, base_url, model, 
-                temperature, max_tokens, is_default, created_at, updated_at
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) 
+                temperature, max_tokens, is_default, fallback_config_id, keep_alive, context_strategy, created_at, updated_at
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) 
             RETURNING *
             "#
         )
@@ -258,11 +1912,16 @@ impl Database {
         .bind(request.temperature)
         .bind(request.max_tokens)
         .bind(request.is_default)
+        .bind(&request.fallback_config_id)
+        .bind(&request.keep_alive)
+        .bind(serde_json::to_string(&request.context_strategy.unwrap_or_default())?)
         .bind(now)
         .bind(now)
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *tx)
         .await?;
 
+        tx.commit().await?;
+
         Ok(config)
     }
 
@@ -297,13 +1956,52 @@ impl Database {
         Ok(config)
     }
 
-    pub async fn update_api_config(&self, config_id: &str, request: UpdateApiConfigRequest) -> Result<ApiConfig> {
+    /// Check that `fallback_id` names another config and that following the
+    /// fallback chain from it never leads back to `config_id`
+    async fn validate_fallback(&self, config_id: &str, fallback_id: Option<&str>) -> Result<()> {
+        let Some(fallback_id) = fallback_id else {
+            return Ok(());
+        };
+        if fallback_id == config_id {
+            return Err(ValidationError::new("fallback_config_id", "a config can't fall back to itself").into());
+        }
+
+        let mut seen = HashSet::new();
+        let mut next = Some(fallback_id.to_string());
+        while let Some(id) = next {
+            if id == config_id {
+                return Err(ValidationError::new("fallback_config_id", "the fallback chain would loop back to this config").into());
+            }
+            if !seen.insert(id.clone()) {
+                break;
+            }
+            let config = self.get_api_config(&id).await?
+                .ok_or_else(|| ValidationError::new("fallback_config_id", format!("API configuration not found: {}", id)))?;
+            next = config.fallback_config_id;
+        }
+
+        Ok(())
+    }
+
+    pub async fn update_api_config(&self, config_id: &str, mut request: UpdateApiConfigRequest) -> Result<ApiConfig> {
+        let existing = self.get_api_config(config_id).await?
+            .ok_or_else(|| anyhow::anyhow!("API configuration not found: {}", config_id))?;
+        request.normalize()?;
+        request.validate(&existing.provider)?;
+        let fallback_config_id = request.fallback_config_id.unwrap_or(existing.fallback_config_id);
+        let keep_alive = request.keep_alive.unwrap_or(existing.keep_alive);
+        let context_strategy = request.context_strategy
+            .map_or(existing.context_strategy, Option::unwrap_or_default);
+        self.validate_fallback(config_id, fallback_config_id.as_deref()).await?;
+
         let now = Utc::now();
 
+        let mut tx = self.pool.begin().await?;
+
         // If this is set as default, unset all other defaults
         if request.is_default {
             sqlx::query("UPDATE api_configs SET is_default = FALSE")
-                .execute(&self.pool)
+                .execute(&mut *tx)
                 .await?;
         }
 
@@ -311,7 +2009,7 @@ impl Database {
             r#"
             UPDATE api_configs SET 
                 name = ?, api_key = ?, base_url = ?, model = ?, 
-                temperature = ?, max_tokens = ?, is_default = ?, updated_at = ?
+                temperature = ?, max_tokens = ?, is_default = ?, fallback_config_id = ?, keep_alive = ?, context_strategy = ?, updated_at = ?
             WHERE id = ? 
             RETURNING *
             "#
@@ -323,48 +2021,775 @@ impl Database {
         .bind(request.temperature)
         .bind(request.max_tokens)
         .bind(request.is_default)
+        .bind(&fallback_config_id)
+        .bind(&keep_alive)
+        .bind(serde_json::to_string(&context_strategy)?)
         .bind(now)
         .bind(config_id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(config)
+    }
+
+    pub async fn set_default_api_config(&self, config_id: &str) -> Result<ApiConfig> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("UPDATE api_configs SET is_default = FALSE WHERE id != ?")
+            .bind(config_id)
+            .execute(&mut *tx)
+            .await?;
+
+        let config = sqlx::query_as::<_, ApiConfig>(
+            "UPDATE api_configs SET is_default = TRUE, updated_at = ? WHERE id = ? RETURNING *"
+        )
+        .bind(Utc::now())
+        .bind(config_id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("API configuration not found: {}", config_id))?;
+
+        tx.commit().await?;
+
+        Ok(config)
+    }
+
+    /// Serialize every API config to JSON. Keys are blanked unless `include_keys` is set.
+    pub async fn export_api_configs(&self, include_keys: bool) -> Result<String> {
+        let configs = self.get_api_configs().await?
+            .into_iter()
+            .map(|config| CreateApiConfigRequest {
+                name: config.name,
+                provider: Some(config.provider),
+                api_key: if include_keys { config.api_key } else { String::new() },
+                base_url: config.base_url,
+                model: config.model,
+                temperature: Some(config.temperature),
+                max_tokens: config.max_tokens,
+                is_default: config.is_default,
+                fallback_config_id: None,
+                keep_alive: config.keep_alive,
+                context_strategy: Some(config.context_strategy),
+            })
+            .collect();
+
+        let export = ApiConfigExport { version: 1, configs };
+        Ok(serde_json::to_string_pretty(&export)?)
+    }
+
+    /// Create or update configs by name from an `export_api_configs` payload.
+    ///
+    /// Existing configs are only touched when `overwrite` is set, and a blank
+    /// key in the payload keeps the stored one. At most one imported entry
+    /// becomes the default.
+    pub async fn import_api_configs(&self, json: &str, overwrite: bool) -> Result<ApiConfigImportSummary> {
+        let mut export: ApiConfigExport = serde_json::from_str(json)
+            .map_err(|e| anyhow::anyhow!("Invalid API config export: {}", e))?;
+
+        let settings = self.get_app_settings().await?;
+        for config in &mut export.configs {
+            config.apply_defaults(&settings);
+            config.normalize()
+                .map_err(|e| anyhow::anyhow!("API configuration '{}': {}", config.name, e))?;
+        }
+
+        for config in &export.configs {
+            config.validate()
+                .map_err(|e| anyhow::anyhow!("API configuration '{}': {}", config.name, e))?;
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let mut summary = ApiConfigImportSummary::default();
+        let mut default_assigned = false;
+        let now = Utc::now();
+
+        for config in export.configs {
+            let existing_id: Option<String> = sqlx::query_scalar("SELECT id FROM api_configs WHERE name = ? LIMIT 1")
+                .bind(&config.name)
+                .fetch_optional(&mut *tx)
+                .await?;
+
+            if existing_id.is_some() && !overwrite {
+                summary.skipped += 1;
+                continue;
+            }
+
+            let is_default = config.is_default && !default_assigned;
+            if is_default {
+                sqlx::query("UPDATE api_configs SET is_default = FALSE")
+                    .execute(&mut *tx)
+                    .await?;
+                default_assigned = true;
+            }
+
+            // Exports from before strategies existed leave an overwritten config's strategy alone
+            let context_strategy = config.context_strategy.map(|strategy| serde_json::to_string(&strategy)).transpose()?;
+
+            match existing_id {
+                Some(id) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE api_configs SET
+                            provider = ?,
+                            api_key = CASE WHEN ? = '' THEN api_key ELSE ? END,
+                            base_url = ?, model = ?, temperature = ?, max_tokens = ?,
+                            is_default = CASE WHEN ? THEN TRUE ELSE is_default END,
+                            keep_alive = ?, context_strategy = COALESCE(?, context_strategy), updated_at = ?
+                        WHERE id = ?
+                        "#
+                    )
+                    .bind(&config.provider)
+                    .bind(&config.api_key)
+                    .bind(&config.api_key)
+                    .bind(&config.base_url)
+                    .bind(&config.model)
+                    .bind(config.temperature)
+                    .bind(config.max_tokens)
+                    .bind(is_default)
+                    .bind(&config.keep_alive)
+                    .bind(&context_strategy)
+                    .bind(now)
+                    .bind(&id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                    summary.updated += 1;
+                }
+                None => {
+                    sqlx::query(
+                        r#"
+                        INSERT INTO api_configs (
+                            id, name, provider, api_key, base_url, model,
+                            temperature, max_tokens, is_default, keep_alive, context_strategy, created_at, updated_at
+                        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                        "#
+                    )
+                    .bind(Uuid::new_v4().to_string())
+                    .bind(&config.name)
+                    .bind(&config.provider)
+                    .bind(&config.api_key)
+                    .bind(&config.base_url)
+                    .bind(&config.model)
+                    .bind(config.temperature)
+                    .bind(config.max_tokens)
+                    .bind(is_default)
+                    .bind(&config.keep_alive)
+                    .bind(match context_strategy {
+                        Some(json) => json,
+                        None => serde_json::to_string(&ContextStrategy::default())?,
+                    })
+                    .bind(now)
+                    .bind(now)
+                    .execute(&mut *tx)
+                    .await?;
+
+                    summary.created += 1;
+                }
+            }
+        }
+
+        tx.commit().await?;
+
+        Ok(summary)
+    }
+
+    /// Reply counts and estimated output tokens per API config, optionally
+    /// limited to replies created at or after `since`
+    pub async fn get_usage_summary(&self, since: Option<chrono::DateTime<Utc>>) -> Result<Vec<ProviderUsage>> {
+        let usage = sqlx::query_as::<_, ProviderUsage>(
+            r#"
+            SELECT
+                u.config_id AS api_config_id,
+                a.name AS config_name,
+                a.provider AS provider,
+                COUNT(*) AS assistant_messages,
+                -- Roughly four characters per token, as in MessageMetrics
+                COALESCE(SUM((LENGTH(u.content) + 3) / 4), 0) AS estimated_tokens
+            FROM (
+                SELECT COALESCE(m.api_config_id, c.api_config_id) AS config_id, m.content
+                FROM messages m
+                JOIN chats c ON c.id = m.chat_id
+                WHERE m.role = 'assistant' AND (? IS NULL OR m.created_at >= ?)
+            ) u
+            LEFT JOIN api_configs a ON a.id = u.config_id
+            GROUP BY u.config_id
+            ORDER BY assistant_messages DESC
+            "#
+        )
+        .bind(since)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(usage)
+    }
+
+    // App settings operations
+    pub async fn get_app_settings(&self) -> Result<AppSettings> {
+        let settings = sqlx::query_as::<_, AppSettings>(
+            "SELECT default_temperature, default_max_tokens, default_provider, stream_idle_timeout_secs, stream_max_reconnects, max_threads FROM app_settings WHERE id = 1"
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(settings)
+    }
+
+    pub async fn update_app_settings(&self, request: UpdateAppSettingsRequest) -> Result<AppSettings> {
+        request.validate()?;
+
+        let settings = sqlx::query_as::<_, AppSettings>(
+            r#"
+            UPDATE app_settings SET
+                default_temperature = ?, default_max_tokens = ?, default_provider = ?,
+                stream_idle_timeout_secs = COALESCE(?, stream_idle_timeout_secs),
+                stream_max_reconnects = COALESCE(?, stream_max_reconnects),
+                max_threads = COALESCE(?, max_threads)
+            WHERE id = 1
+            RETURNING default_temperature, default_max_tokens, default_provider, stream_idle_timeout_secs, stream_max_reconnects, max_threads
+            "#
+        )
+        .bind(request.default_temperature)
+        .bind(request.default_max_tokens)
+        .bind(&request.default_provider)
+        .bind(request.stream_idle_timeout_secs)
+        .bind(request.stream_max_reconnects)
+        .bind(request.max_threads)
+        .fetch_one(&self.pool)
+        .await?;
+
+        set_max_threads(settings.max_threads as usize);
+        Ok(settings)
+    }
+
+    // Preference operations
+    pub async fn get_preference(&self, key: &str) -> Result<Option<serde_json::Value>> {
+        let value: Option<String> = sqlx::query_scalar("SELECT value FROM preferences WHERE key = ?")
+            .bind(key)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        value
+            .map(|value| serde_json::from_str(&value))
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("Invalid stored value for preference '{}': {}", key, e))
+    }
+
+    pub async fn set_preference(&self, key: &str, value: &serde_json::Value) -> Result<()> {
+        if key.trim().is_empty() {
+            return Err(anyhow::anyhow!("Preference key must not be empty"));
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO preferences (key, value, updated_at) VALUES (?, ?, ?)
+            ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = excluded.updated_at
+            "#
+        )
+        .bind(key)
+        .bind(serde_json::to_string(value)?)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The built-in slash commands merged with the user's overrides from the
+    /// `slash_commands` preference
+    pub async fn get_slash_commands(&self) -> Result<HashMap<String, String>> {
+        let mut commands: HashMap<String, String> = DEFAULT_SLASH_COMMANDS.iter()
+            .map(|(name, template)| (name.to_string(), template.to_string()))
+            .collect();
+
+        let Some(overrides) = self.get_preference(SLASH_COMMANDS_PREFERENCE).await? else {
+            return Ok(commands);
+        };
+        let overrides = overrides.as_object()
+            .ok_or_else(|| anyhow::anyhow!("Preference '{}' must be an object of command name to template", SLASH_COMMANDS_PREFERENCE))?;
+
+        for (name, template) in overrides {
+            let name = name.trim_start_matches('/').to_ascii_lowercase();
+            match template {
+                serde_json::Value::Null => {
+                    commands.remove(&name);
+                }
+                serde_json::Value::String(template) => {
+                    commands.insert(name, template.clone());
+                }
+                _ => return Err(anyhow::anyhow!("Slash command '/{}' must map to a template string or null", name)),
+            }
+        }
+
+        Ok(commands)
+    }
+
+    /// Expand a leading `/command` in a user message; `None` leaves the text as typed
+    pub async fn expand_slash_commands(&self, text: &str) -> Result<Option<String>> {
+        if !text.trim_start().starts_with('/') {
+            return Ok(None);
+        }
+        let commands = self.get_slash_commands().await?;
+        Ok(expand_slash_command(text, &commands)?)
+    }
+
+    // Prompt template operations
+    pub async fn create_prompt_template(&self, name: &str, body: &str) -> Result<PromptTemplate> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(ValidationError::new("name", "must not be empty").into());
+        }
+        if body.trim().is_empty() {
+            return Err(ValidationError::new("body", "must not be empty").into());
+        }
+
+        let now = Utc::now();
+        let template = sqlx::query_as::<_, PromptTemplate>(
+            "INSERT INTO prompt_templates (id, name, body, created_at, updated_at) VALUES (?, ?, ?, ?, ?) RETURNING *"
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(name)
+        .bind(body)
+        .bind(now)
+        .bind(now)
         .fetch_one(&self.pool)
+        .await
+        .map_err(|e| match &e {
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                anyhow::anyhow!("A prompt template named '{}' already exists", name)
+            }
+            _ => e.into(),
+        })?;
+
+        Ok(template)
+    }
+
+    pub async fn get_prompt_templates(&self) -> Result<Vec<PromptTemplate>> {
+        let templates = sqlx::query_as::<_, PromptTemplate>(
+            "SELECT * FROM prompt_templates ORDER BY name COLLATE NOCASE ASC"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(templates)
+    }
+
+    pub async fn get_prompt_template(&self, template_id: &str) -> Result<Option<PromptTemplate>> {
+        let template = sqlx::query_as::<_, PromptTemplate>(
+            "SELECT * FROM prompt_templates WHERE id = ?"
+        )
+        .bind(template_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(template)
+    }
+
+    pub async fn delete_prompt_template(&self, template_id: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM prompt_templates WHERE id = ?")
+            .bind(template_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Append finished agent actions to the cross-session activity log
+    pub async fn record_agent_actions(&self, session_id: &str, actions: &[AgentAction]) -> Result<()> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        for action in actions {
+            sqlx::query(
+                "INSERT INTO agent_actions (session_id, action_type, description, parameters, success, cancelled, error_message, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"
+            )
+            .bind(session_id)
+            .bind(&action.action_type)
+            .bind(&action.description)
+            .bind(serde_json::to_string(&loggable_parameters(&action.parameters))?)
+            .bind(action.success)
+            .bind(action.cancelled)
+            .bind(&action.error_message)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Most recent agent actions from every session, newest first, optionally
+    /// only those at or after `since`
+    pub async fn get_all_agent_actions(&self, limit: Option<usize>, since: Option<DateTime<Utc>>) -> Result<Vec<AgentActionRecord>> {
+        let limit = limit.unwrap_or(DEFAULT_AGENT_ACTION_LOG_LIMIT).clamp(1, MAX_AGENT_ACTION_LOG_LIMIT);
+
+        let rows = sqlx::query(
+            "SELECT * FROM agent_actions WHERE (? IS NULL OR created_at >= ?) ORDER BY created_at DESC, id DESC LIMIT ?"
+        )
+        .bind(since)
+        .bind(since)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let parameters: String = row.try_get("parameters")?;
+                Ok(AgentActionRecord {
+                    id: row.try_get("id")?,
+                    session_id: row.try_get("session_id")?,
+                    action_type: row.try_get("action_type")?,
+                    description: row.try_get("description")?,
+                    parameters: serde_json::from_str(&parameters).unwrap_or_default(),
+                    success: row.try_get("success")?,
+                    cancelled: row.try_get("cancelled")?,
+                    error_message: row.try_get("error_message")?,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    /// Append a system operation to the audit log, masking secrets in its
+    /// parameters and result
+    pub async fn record_operation(
+        &self,
+        operation: &str,
+        parameters: &HashMap<String, serde_json::Value>,
+        success: bool,
+        result: &str,
+    ) -> Result<()> {
+        let result = match result.char_indices().nth(MAX_LOGGED_PARAMETER_CHARS) {
+            Some((end, _)) => format!("{}… ({} chars)", &result[..end], result.chars().count()),
+            None => result.to_string(),
+        };
+
+        sqlx::query(
+            "INSERT INTO operation_log (operation, parameters, success, result, created_at) VALUES (?, ?, ?, ?, ?)"
+        )
+        .bind(operation)
+        .bind(serde_json::to_string(&redacted_parameters(parameters))?)
+        .bind(success)
+        .bind(redact_secrets(&result))
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Most recent system operations, newest first, optionally only those at
+    /// or after `since`
+    pub async fn get_operation_log(&self, limit: Option<usize>, since: Option<DateTime<Utc>>) -> Result<Vec<OperationLogEntry>> {
+        let limit = limit.unwrap_or(DEFAULT_OPERATION_LOG_LIMIT).clamp(1, MAX_OPERATION_LOG_LIMIT);
+
+        let rows = sqlx::query(
+            "SELECT * FROM operation_log WHERE (? IS NULL OR created_at >= ?) ORDER BY created_at DESC, id DESC LIMIT ?"
+        )
+        .bind(since)
+        .bind(since)
+        .bind(limit as i64)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| {
+                let parameters: String = row.try_get("parameters")?;
+                Ok(OperationLogEntry {
+                    id: row.try_get("id")?,
+                    operation: row.try_get("operation")?,
+                    parameters: serde_json::from_str(&parameters).unwrap_or_default(),
+                    success: row.try_get("success")?,
+                    result: row.try_get("result")?,
+                    created_at: row.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn get_all_preferences(&self) -> Result<HashMap<String, serde_json::Value>> {
+        let rows = sqlx::query("SELECT key, value FROM preferences")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut preferences = HashMap::with_capacity(rows.len());
+        for row in rows {
+            let key: String = row.try_get("key")?;
+            let value: String = row.try_get("value")?;
+            let value = serde_json::from_str(&value)
+                .map_err(|e| anyhow::anyhow!("Invalid stored value for preference '{}': {}", key, e))?;
+            preferences.insert(key, value);
+        }
+
+        Ok(preferences)
+    }
+
+    /// Delete an API config, returning how many chats were detached from it.
+    ///
+    /// Chats still using the config block the delete unless `force` is set, in
+    /// which case they are reset to the default config.
+    pub async fn delete_api_config(&self, config_id: &str, force: bool) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+
+        // Don't allow deleting if it's the only config or if chats are using it
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_configs")
+            .fetch_one(&mut *tx)
+            .await?;
+
+        if count <= 1 {
+            return Err(anyhow::anyhow!("Cannot delete the last API configuration"));
+        }
+
+        let chats_using: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chats WHERE api_config_id = ?")
+            .bind(config_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+        if chats_using > 0 && !force {
+            return Err(anyhow::anyhow!("Cannot delete API configuration that is being used by chats"));
+        }
+
+        let affected = sqlx::query("UPDATE chats SET api_config_id = NULL WHERE api_config_id = ?")
+            .bind(config_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+
+        sqlx::query("UPDATE api_configs SET fallback_config_id = NULL WHERE fallback_config_id = ?")
+            .bind(config_id)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM api_configs WHERE id = ?")
+            .bind(config_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        Ok(affected)
+    }
+
+    /// Ask the chat's model for a condensed summary of the conversation so far
+    pub async fn summarize_chat_history(&self, chat_id: &str) -> Result<String> {
+        let chat = self.get_chat(chat_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Chat not found: {}", chat_id))?;
+        let config = self.get_chat_api_config(&chat).await?
+            .ok_or_else(|| anyhow::anyhow!("No API configuration found"))?;
+
+        let messages = self.get_messages(chat_id).await?;
+        if messages.is_empty() {
+            return Err(anyhow::anyhow!("Chat has no messages to summarize"));
+        }
+
+        self.summarize_messages(&config, None, &messages).await
+    }
+
+    /// Summary of the messages a `Summarized` context leaves out. The
+    /// summary is stored per chat and only extended with the messages that
+    /// scrolled out since, so each chunk of history is summarized once.
+    pub async fn summarize_earlier_messages(&self, chat_id: &str, config: &ApiConfig, earlier: &[Message]) -> Result<String> {
+        if earlier.is_empty() {
+            return Ok(String::new());
+        }
+
+        let summary = match self.context_summary(chat_id, earlier).await? {
+            Some((covered, summary)) if covered == earlier.len() => return Ok(summary),
+            Some((covered, summary)) => self.summarize_messages(config, Some(&summary), &earlier[covered..]).await?,
+            None => self.summarize_messages(config, None, earlier).await?,
+        };
+
+        sqlx::query(
+            r#"
+            INSERT INTO chat_context_summaries (chat_id, covered_messages, fingerprint, summary, updated_at)
+            VALUES (?, ?, ?, ?, ?)
+            ON CONFLICT(chat_id) DO UPDATE SET
+                covered_messages = excluded.covered_messages,
+                fingerprint = excluded.fingerprint,
+                summary = excluded.summary,
+                updated_at = excluded.updated_at
+            "#
+        )
+        .bind(chat_id)
+        .bind(earlier.len() as i64)
+        .bind(summary_fingerprint(earlier))
+        .bind(&summary)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(summary)
+    }
+
+    /// The stored context summary for `chat_id` and how many of `earlier`
+    /// it covers, if those messages are unchanged since it was written
+    pub async fn context_summary(&self, chat_id: &str, earlier: &[Message]) -> Result<Option<(usize, String)>> {
+        let stored: Option<(i64, String, String)> = sqlx::query_as(
+            "SELECT covered_messages, fingerprint, summary FROM chat_context_summaries WHERE chat_id = ?"
+        )
+        .bind(chat_id)
+        .fetch_optional(&self.pool)
         .await?;
 
-        Ok(config)
+        Ok(stored.and_then(|(covered, fingerprint, summary)| {
+            let covered = usize::try_from(covered).ok()?;
+            let covered_messages = earlier.get(..covered)?;
+            (summary_fingerprint(covered_messages) == fingerprint).then_some((covered, summary))
+        }))
     }
 
-    pub async fn delete_api_config(&self, config_id: &str) -> Result<()> {
-        // Don't allow deleting if it's the only config or if chats are using it
-        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_configs")
-            .fetch_one(&self.pool)
-            .await?;
-
-        if count <= 1 {
-            return Err(anyhow::anyhow!("Cannot delete the last API configuration"));
+    /// Summarize `messages`, or with `previous` extend that summary of the
+    /// conversation before them
+    async fn summarize_messages(&self, config: &ApiConfig, previous: Option<&str>, messages: &[Message]) -> Result<String> {
+        // Keep the most recent part of very long chats so the request fits the context window
+        let mut entries = Vec::new();
+        let mut transcript_len = 0;
+        for message in messages.iter().rev() {
+            let speaker = match message.role {
+                MessageRole::User => "User",
+                MessageRole::Assistant => "Assistant",
+            };
+            let entry = format!("{}: {}", speaker, message.content);
+            if transcript_len + entry.len() > MAX_SUMMARY_TRANSCRIPT_CHARS && !entries.is_empty() {
+                break;
+            }
+            transcript_len += entry.len();
+            entries.push(entry);
         }
+        entries.reverse();
+        let transcript = entries.join("\n\n");
+
+        let prompt = match previous {
+            Some(previous) => format!(
+                "Here is a summary of a conversation so far, followed by the messages that came after it. \
+                 Rewrite the summary so it also covers the new messages. \
+                 Keep every decision, fact, open question and piece of code that later messages may depend on. \
+                 Reply with the summary only.\n\nSummary:\n{}\n\nNew messages:\n{}",
+                previous, transcript
+            ),
+            None => format!(
+                "Summarize the following conversation so it can be continued in a new chat. \
+                 Keep every decision, fact, open question and piece of code that later messages may depend on. \
+                 Reply with the summary only.\n\n{}",
+                transcript
+            ),
+        };
 
-        let chats_using: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM chats WHERE api_config_id = ?")
-            .bind(config_id)
-            .fetch_one(&self.pool)
-            .await?;
+        let summary = self.send_chat_completion(config, vec![ChatMessage {
+            role: "user".to_string(),
+            content: serde_json::Value::String(prompt),
+        }]).await?;
 
-        if chats_using > 0 {
-            return Err(anyhow::anyhow!("Cannot delete API configuration that is being used by chats"));
-        }
+        Ok(summary.trim().to_string())
+    }
 
-        sqlx::query("DELETE FROM api_configs WHERE id = ?")
-            .bind(config_id)
-            .execute(&self.pool)
-            .await?;
+    /// Start a new chat on the same API config, seeded with a summary of `chat_id`
+    pub async fn fork_with_summary(&self, chat_id: &str) -> Result<Chat> {
+        let chat = self.get_chat(chat_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Chat not found: {}", chat_id))?;
 
-        Ok(())
+        let summary = self.summarize_chat_history(chat_id).await?;
+
+        let forked = self.create_chat(format!("{} (continued)", chat.title), chat.api_config_id.clone()).await?;
+        self.create_message(
+            forked.id.clone(),
+            format!("Summary of the previous conversation:\n\n{}", summary),
+            MessageRole::Assistant,
+            None,
+        ).await?;
+
+        // Re-read so updated_at reflects the seeded message
+        self.get_chat(&forked.id).await?
+            .ok_or_else(|| anyhow::anyhow!("Chat not found: {}", forked.id))
     }
 
     // LLM Integration
     pub async fn send_chat_completion(&self, config: &ApiConfig, messages: Vec<ChatMessage>) -> Result<String> {
+        self.send_chat_completion_with_reasoning(config, messages)
+            .await
+            .map(|output| output.content)
+    }
+
+    /// Like `send_chat_completion`, but keeps the model's reasoning, if any.
+    /// Falls back like [`Self::with_fallbacks`] when the provider is unavailable.
+    pub async fn send_chat_completion_with_reasoning(&self, config: &ApiConfig, messages: Vec<ChatMessage>) -> Result<CompletionOutput> {
+        self.with_fallbacks(
+            config,
+            |current| {
+                let messages = messages.clone();
+                async move { self.send_to_provider(&current, messages).await }
+            },
+            |_, _, _| {},
+        ).await
+    }
+
+    /// Run `attempt` with `config` and, while its provider is unavailable and
+    /// no part of a reply has arrived, move down the fallback chain, stopping
+    /// at the first config seen twice. `on_fallback` hears about each switch.
+    /// The output's `api_config_id` names the config that answered if it
+    /// wasn't `config`, and so does the partial reply of a [`StreamInterrupted`].
+    async fn with_fallbacks<F, Fut>(
+        &self,
+        config: &ApiConfig,
+        mut attempt: F,
+        mut on_fallback: impl FnMut(&ApiConfig, &ApiConfig, &anyhow::Error),
+    ) -> Result<CompletionOutput>
+    where
+        F: FnMut(ApiConfig) -> Fut,
+        Fut: std::future::Future<Output = Result<CompletionOutput>>,
+    {
+        let mut tried = HashSet::from([config.id.clone()]);
+        let mut current = config.clone();
+
+        loop {
+            let mut err = match attempt(current.clone()).await {
+                Ok(mut output) => {
+                    if current.id != config.id {
+                        output.api_config_id = Some(current.id.clone());
+                    }
+                    return Ok(output);
+                }
+                Err(err) => err,
+            };
+
+            // A reply that's already on screen can't be finished by another model
+            let nothing_arrived = err.downcast_ref::<StreamInterrupted>().is_none_or(|interrupted| {
+                interrupted.partial.content.is_empty() && interrupted.partial.reasoning.as_deref().is_none_or(str::is_empty)
+            });
+            let fallback = match current.fallback_config_id.as_deref() {
+                Some(id) if nothing_arrived && is_provider_unavailable(&err) && tried.insert(id.to_string()) => {
+                    self.get_api_config(id).await?
+                }
+                _ => None,
+            };
+            let Some(fallback) = fallback else {
+                if let Some(interrupted) = err.downcast_mut::<StreamInterrupted>() {
+                    if current.id != config.id {
+                        interrupted.partial.api_config_id = Some(current.id.clone());
+                    }
+                }
+                return Err(err);
+            };
+
+            self.ensure_open()?;
+            on_fallback(&current, &fallback, &err);
+            current = fallback;
+        }
+    }
+
+    async fn send_to_provider(&self, config: &ApiConfig, messages: Vec<ChatMessage>) -> Result<CompletionOutput> {
+        let config = &clamp_max_tokens(config);
         let client = Client::new();
         
         match config.provider {
             ApiProvider::OpenAI => {
-                let url = config.base_url.as_deref().unwrap_or("https://api.openai.com/v1/chat/completions");
+                let url = config.base_url.as_deref().unwrap_or(OPENAI_DEFAULT_URL);
                 
                 let request_body = json!({
                     "model": config.model,
@@ -381,9 +2806,11 @@ impl Database {
                     .send()
                     .await?;
 
-                if !response.status().is_success() {
+                let status = response.status();
+
+                if !status.is_success() {
                     let error_text = response.text().await?;
-                    return Err(anyhow::anyhow!("API request failed: {}", error_text));
+                    return Err(ProviderError::new(&config.provider, status, "API request failed", &error_text).into());
                 }
 
                 // Try to parse as ChatCompletionResponse, but provide better error handling
@@ -392,12 +2819,7 @@ impl Database {
                 match serde_json::from_str::<ChatCompletionResponse>(&response_text) {
                     Ok(completion) => {
                         if let Some(choice) = completion.choices.first() {
-                            // Convert content Value to String
-                            let content_str = match &choice.message.content {
-                                serde_json::Value::String(s) => s.clone(),
-                                other => other.to_string(),
-                            };
-                            Ok(content_str)
+                            Ok(CompletionOutput::from_choice(choice).with_usage(completion.usage))
                         } else {
                             Err(anyhow::anyhow!("No response choices from API"))
                         }
@@ -411,8 +2833,10 @@ impl Database {
                 }
             },
             ApiProvider::Anthropic => {
-                let url = config.base_url.as_deref().unwrap_or("https://api.anthropic.com/v1/messages");
+                let url = config.base_url.as_deref().unwrap_or(ANTHROPIC_DEFAULT_URL);
                 
+                let (system, messages) = split_system_messages(messages);
+
                 // Convert messages to Anthropic format
                 let anthropic_messages: Vec<serde_json::Value> = messages.into_iter().map(|msg| {
                     json!({
@@ -421,11 +2845,14 @@ impl Database {
                     })
                 }).collect();
 
-                let request_body = json!({
+                let mut request_body = json!({
                     "model": config.model,
                     "max_tokens": config.max_tokens.unwrap_or(1000),
                     "messages": anthropic_messages
                 });
+                if let Some(system) = system {
+                    request_body["system"] = json!(system);
+                }
 
                 let response = client
                     .post(url)
@@ -436,26 +2863,36 @@ impl Database {
                     .send()
                     .await?;
 
-                if !response.status().is_success() {
+                let status = response.status();
+
+                if !status.is_success() {
                     let error_text = response.text().await?;
-                    return Err(anyhow::anyhow!("Anthropic API request failed: {}", error_text));
+                    return Err(ProviderError::new(&config.provider, status, "Anthropic API request failed", &error_text).into());
                 }
 
                 let response_json: serde_json::Value = response.json().await?;
                 
-                if let Some(content) = response_json["content"][0]["text"].as_str() {
-                    Ok(content.to_string())
-                } else {
-                    Err(anyhow::anyhow!("Invalid response format from Anthropic API"))
+                // Extended thinking comes back as `thinking` blocks ahead of the `text` blocks
+                let blocks = response_json["content"].as_array()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid response format from Anthropic API"))?;
+
+                let mut content = String::new();
+                let mut thinking = String::new();
+                for block in blocks {
+                    match block["type"].as_str() {
+                        Some("text") => content.push_str(block["text"].as_str().unwrap_or_default()),
+                        Some("thinking") => thinking.push_str(block["thinking"].as_str().unwrap_or_default()),
+                        _ => {}
+                    }
                 }
+
+                Ok(CompletionOutput::new(content, Some(thinking))
+                    .with_finish_reason(response_json["stop_reason"].as_str()))
             },
             ApiProvider::Ollama => {
-                let url = format!(
-                    "{}/api/chat", 
-                    config.base_url.as_deref().unwrap_or("http://localhost:11434")
-                );
+                let url = ollama_url(config.base_url.as_deref(), "chat");
                 
-                let request_body = json!({
+                let mut request_body = json!({
                     "model": config.model,
                     "messages": messages,
                     "stream": false,
@@ -463,29 +2900,52 @@ impl Database {
                         "temperature": config.temperature
                     }
                 });
+                if let Some(keep_alive) = &config.keep_alive {
+                    request_body["keep_alive"] = keep_alive_json(keep_alive);
+                }
 
-                let response = client
+                let mut request_builder = client
                     .post(&url)
-                    .header("Content-Type", "application/json")
+                    .header("Content-Type", "application/json");
+
+                // Local servers need no key; one behind an authenticating proxy may
+                if !config.api_key.is_empty() {
+                    request_builder = request_builder.header("Authorization", format!("Bearer {}", config.api_key));
+                }
+
+                let response = request_builder
                     .json(&request_body)
                     .send()
                     .await?;
 
-                if !response.status().is_success() {
+                let status = response.status();
+
+                if !status.is_success() {
                     let error_text = response.text().await?;
-                    return Err(anyhow::anyhow!("Ollama API request failed: {}", error_text));
+                    return Err(ProviderError::new(&config.provider, status, "Ollama API request failed", &error_text).into());
                 }
 
                 let response_json: serde_json::Value = response.json().await?;
                 
                 if let Some(content) = response_json["message"]["content"].as_str() {
-                    Ok(content.to_string())
+                    // Newer Ollama versions report thinking separately; older ones inline `<think>` tags
+                    let thinking = response_json["message"]["thinking"].as_str().map(str::to_string);
+                    let usage = match (response_json["prompt_eval_count"].as_u64(), response_json["eval_count"].as_u64()) {
+                        (None, None) => None,
+                        (prompt, completion) => Some(TokenUsage::new(
+                            prompt.unwrap_or_default() as u32,
+                            completion.unwrap_or_default() as u32,
+                        )),
+                    };
+                    Ok(CompletionOutput::new(content.to_string(), thinking)
+                        .with_usage(usage)
+                        .with_finish_reason(response_json["done_reason"].as_str()))
                 } else {
                     Err(anyhow::anyhow!("Invalid response format from Ollama API"))
                 }
             },
             ApiProvider::Google => {
-                let base_url = config.base_url.as_deref().unwrap_or("https://generativelanguage.googleapis.com/v1beta/models");
+                let base_url = config.base_url.as_deref().unwrap_or(GOOGLE_DEFAULT_URL);
                 
                 // Check if using OpenAI-compatible endpoint
                 if base_url.contains("/openai/chat/completions") {
@@ -505,9 +2965,11 @@ impl Database {
                         .send()
                         .await?;
 
-                    if !response.status().is_success() {
+                    let status = response.status();
+
+                    if !status.is_success() {
                         let error_text = response.text().await?;
-                        return Err(anyhow::anyhow!("Google OpenAI-compatible API request failed: {}", error_text));
+                        return Err(ProviderError::new(&config.provider, status, "Google OpenAI-compatible API request failed", &error_text).into());
                     }
 
                     // Parse OpenAI-compatible response
@@ -516,12 +2978,7 @@ impl Database {
                     match serde_json::from_str::<ChatCompletionResponse>(&response_text) {
                         Ok(completion) => {
                             if let Some(choice) = completion.choices.first() {
-                                // Convert content Value to String
-                                let content_str = match &choice.message.content {
-                                    serde_json::Value::String(s) => s.clone(),
-                                    other => other.to_string(),
-                                };
-                                Ok(content_str)
+                                Ok(CompletionOutput::from_choice(choice).with_usage(completion.usage))
                             } else {
                                 Err(anyhow::anyhow!("No response choices from Google OpenAI-compatible API"))
                             }
@@ -536,6 +2993,8 @@ impl Database {
                     // Use original Gemini API format
                     let full_url = format!("{}/{}:generateContent?key={}", base_url, config.model, config.api_key);
                     
+                    let (system, messages) = split_system_messages(messages);
+
                     // Convert messages to Google format
                     let google_contents: Vec<serde_json::Value> = messages.into_iter().map(|msg| {
                         json!({
@@ -544,13 +3003,16 @@ impl Database {
                         })
                     }).collect();
 
-                    let request_body = json!({
+                    let mut request_body = json!({
                         "contents": google_contents,
                         "generationConfig": {
                             "temperature": config.temperature,
                             "maxOutputTokens": config.max_tokens.unwrap_or(1000)
                         }
                     });
+                    if let Some(system) = system {
+                        request_body["systemInstruction"] = json!({ "parts": [{ "text": system }] });
+                    }
 
                     let response = client
                         .post(&full_url)
@@ -559,18 +3021,32 @@ impl Database {
                         .send()
                         .await?;
 
-                    if !response.status().is_success() {
+                    let status = response.status();
+
+                    if !status.is_success() {
                         let error_text = response.text().await?;
-                        return Err(anyhow::anyhow!("Google Gemini API request failed: {}", error_text));
+                        return Err(ProviderError::new(&config.provider, status, "Google Gemini API request failed", &error_text).into());
                     }
 
                     let response_json: serde_json::Value = response.json().await?;
                     
-                    if let Some(content) = response_json["candidates"][0]["content"]["parts"][0]["text"].as_str() {
-                        Ok(content.to_string())
-                    } else {
-                        Err(anyhow::anyhow!("Invalid response format from Google Gemini API"))
+                    let parts = response_json["candidates"][0]["content"]["parts"].as_array()
+                        .ok_or_else(|| anyhow::anyhow!("Invalid response format from Google Gemini API"))?;
+
+                    // Thought summaries are parts flagged with `thought: true`
+                    let mut content = String::new();
+                    let mut thoughts = String::new();
+                    for part in parts {
+                        let text = part["text"].as_str().unwrap_or_default();
+                        if part["thought"].as_bool().unwrap_or(false) {
+                            thoughts.push_str(text);
+                        } else {
+                            content.push_str(text);
+                        }
                     }
+
+                    Ok(CompletionOutput::new(content, Some(thoughts))
+                        .with_finish_reason(response_json["candidates"][0]["finishReason"].as_str()))
                 }
             },
             ApiProvider::Custom => {
@@ -600,9 +3076,11 @@ impl Database {
                     .send()
                     .await?;
 
-                if !response.status().is_success() {
+                let status = response.status();
+
+                if !status.is_success() {
                     let error_text = response.text().await?;
-                    return Err(anyhow::anyhow!("Custom API request failed: {}", error_text));
+                    return Err(ProviderError::new(&config.provider, status, "Custom API request failed", &error_text).into());
                 }
 
                 // Try to parse as ChatCompletionResponse, but provide better error handling
@@ -611,12 +3089,7 @@ impl Database {
                 match serde_json::from_str::<ChatCompletionResponse>(&response_text) {
                     Ok(completion) => {
                         if let Some(choice) = completion.choices.first() {
-                            // Convert content Value to String
-                            let content_str = match &choice.message.content {
-                                serde_json::Value::String(s) => s.clone(),
-                                other => other.to_string(),
-                            };
-                            Ok(content_str)
+                            Ok(CompletionOutput::from_choice(choice).with_usage(completion.usage))
                         } else {
                             Err(anyhow::anyhow!("No response choices from custom API"))
                         }
@@ -632,6 +3105,94 @@ impl Database {
         }
     }
 
+    /// Ask the Ollama server hosting `model` whether it accepts images. Uses
+    /// the base URL and key of a matching Ollama config, or the local default.
+    pub async fn ollama_supports_vision(&self, model: &str) -> Result<bool> {
+        let server: Option<(String, String)> = sqlx::query_as(
+            "SELECT base_url, api_key FROM api_configs WHERE provider = 'ollama' AND model = ? AND base_url IS NOT NULL LIMIT 1"
+        )
+        .bind(model)
+        .fetch_optional(&self.pool)
+        .await?;
+        let (base_url, api_key) = server.unzip();
+
+        let url = ollama_url(base_url.as_deref(), "show");
+
+        let mut request_builder = Client::new().post(&url);
+        if let Some(api_key) = api_key.filter(|key| !key.is_empty()) {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", api_key));
+        }
+
+        let response = request_builder
+            .json(&json!({ "model": model }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow::anyhow!("Ollama API request failed: {}", error_text));
+        }
+
+        let response_json: serde_json::Value = response.json().await?;
+
+        // Recent versions list capabilities; older ones only show a `clip` projector family
+        let has = |list: &serde_json::Value, name: &str| {
+            list.as_array().is_some_and(|items| items.iter().any(|item| item.as_str() == Some(name)))
+        };
+
+        Ok(has(&response_json["capabilities"], "vision") || has(&response_json["details"]["families"], "clip"))
+    }
+
+    // Speech-to-text via an OpenAI-compatible `audio/transcriptions` endpoint
+    pub async fn transcribe_audio(&self, config: &ApiConfig, audio: AudioInput) -> Result<String> {
+        let url = match config.provider {
+            ApiProvider::OpenAI => transcription_url(config.base_url.as_deref().unwrap_or(OPENAI_DEFAULT_URL)),
+            ApiProvider::Custom => {
+                let base_url = config.base_url.as_deref().ok_or_else(|| {
+                    anyhow::anyhow!("Base URL is required for custom providers")
+                })?;
+                transcription_url(base_url)
+            },
+            _ => return Err(anyhow::anyhow!("Transcription is only supported for OpenAI-compatible providers")),
+        };
+
+        let part = reqwest::multipart::Part::bytes(audio.bytes)
+            .file_name(audio.file_name)
+            .mime_str(&audio.mime)?;
+
+        let form = reqwest::multipart::Form::new()
+            .text("model", "whisper-1")
+            .text("response_format", "json")
+            .part("file", part);
+
+        let mut request_builder = Client::new().post(&url);
+
+        if !config.api_key.is_empty() {
+            request_builder = request_builder.header("Authorization", format!("Bearer {}", config.api_key));
+        }
+
+        let response = request_builder
+            .multipart(form)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(anyhow::anyhow!("Transcription request failed: {}", error_text));
+        }
+
+        let response_json: serde_json::Value = response.json().await?;
+
+        response_json["text"]
+            .as_str()
+            .map(|text| text.trim().to_string())
+            .ok_or_else(|| anyhow::anyhow!("Invalid response format from transcription API"))
+    }
+
+    /// Stream a reply to the window. A dropped connection is retried up to
+    /// `stream_max_reconnects` times with backoff, continuing from the text
+    /// received so far; the partial reply comes back in a [`StreamInterrupted`]
+    /// error once the retries run out.
     pub async fn send_chat_completion_streaming(
         &self, 
         config: &ApiConfig, 
@@ -639,92 +3200,278 @@ impl Database {
         window: &tauri::Window,
         message_id: &str,
         chat_id: &str
-    ) -> Result<String> {
+    ) -> Result<CompletionOutput> {
+        let outcome = self.stream_completion(config, messages, window, message_id, chat_id).await;
+        self.record_send_outcome(chat_id, config, &outcome);
+        outcome
+    }
+
+    async fn stream_completion(
+        &self, 
+        config: &ApiConfig, 
+        messages: Vec<ChatMessage>,
+        window: &tauri::Window,
+        message_id: &str,
+        chat_id: &str
+    ) -> Result<CompletionOutput> {
+        let active = &ActiveStream::start(&self.active_streams, &self.stream_epoch);
+
+        self.with_fallbacks(
+            config,
+            |current| {
+                let messages = messages.clone();
+                async move {
+                    self.ensure_stream_live(active)?;
+                    self.stream_from_provider(&current, messages, window, message_id, chat_id, active).await
+                }
+            },
+            |from, to, err| {
+                let _ = window.emit("provider_fallback", serde_json::json!({
+                    "message_id": message_id,
+                    "chat_id": chat_id,
+                    "from_config_id": from.id,
+                    "to_config_id": to.id,
+                    "error": err.to_string()
+                }));
+            },
+        ).await
+    }
+
+    async fn stream_from_provider(
+        &self,
+        config: &ApiConfig,
+        messages: Vec<ChatMessage>,
+        window: &tauri::Window,
+        message_id: &str,
+        chat_id: &str,
+        active: &ActiveStream<'_>,
+    ) -> Result<CompletionOutput> {
+        let settings = self.get_app_settings().await?;
+        let idle_timeout = std::time::Duration::from_secs(settings.stream_idle_timeout_secs.max(1) as u64);
+        let max_reconnects = settings.stream_max_reconnects.max(0) as u32;
+        let config = &clamp_max_tokens(config);
         let client = Client::new();
         
         match config.provider {
             ApiProvider::OpenAI => {
-                let url = config.base_url.as_deref().unwrap_or("https://api.openai.com/v1/chat/completions");
-                
-                let request_body = json!({
-                    "model": config.model,
-                    "messages": messages,
-                    "temperature": config.temperature,
-                    "max_tokens": config.max_tokens,
-                    "stream": true
-                });
-
-                let response = client
-                    .post(url)
-                    .header("Authorization", format!("Bearer {}", config.api_key))
-                    .header("Content-Type", "application/json")
-                    .json(&request_body)
-                    .send()
-                    .await?;
-
-                if !response.status().is_success() {
-                    let error_text = response.text().await?;
-                    return Err(anyhow::anyhow!("API request failed: {}", error_text));
-                }
+                let url = config.base_url.as_deref().unwrap_or(OPENAI_DEFAULT_URL);
 
                 let mut full_response = String::new();
-                let mut stream = response.bytes_stream();
-                
+                let mut full_reasoning = String::new();
+                let mut think_tags = ThinkTagSplitter::default();
+                let mut usage: Option<TokenUsage> = None;
+                let mut finish_reason: Option<String> = None;
+                let mut reconnects = 0;
+                // Some OpenAI-compatible servers reject `stream_options`; they get one retry without it
+                let mut include_usage = true;
+
                 use futures_util::StreamExt;
-                
-                while let Some(chunk) = stream.next().await {
-                    let chunk = chunk?;
-                    let chunk_str = String::from_utf8_lossy(&chunk);
-                    
-                    // Parse SSE format
-                    for line in chunk_str.lines() {
-                        if line.starts_with("data: ") {
-                            let data = &line[6..];
-                            if data == "[DONE]" {
-                                break;
+
+                'attempt: loop {
+                    // After a drop, send what already arrived and ask the model to go on
+                    let mut request_messages = messages.clone();
+                    if !full_response.is_empty() {
+                        request_messages.push(ChatMessage {
+                            role: "assistant".to_string(),
+                            content: json!(full_response),
+                        });
+                        request_messages.push(ChatMessage {
+                            role: "user".to_string(),
+                            content: json!(CONTINUE_PROMPT),
+                        });
+                    } else {
+                        // Nothing visible yet, so start over from scratch
+                        full_reasoning.clear();
+                        think_tags = ThinkTagSplitter::default();
+                    }
+
+                    let mut request_body = json!({
+                        "model": config.model,
+                        "messages": request_messages,
+                        "temperature": config.temperature,
+                        "max_tokens": config.max_tokens,
+                        "stream": true,
+                    });
+                    if include_usage {
+                        // Adds a final chunk carrying the token usage
+                        request_body["stream_options"] = json!({ "include_usage": true });
+                    }
+
+                    let sent = client
+                        .post(url)
+                        .header("Authorization", format!("Bearer {}", config.api_key))
+                        .header("Content-Type", "application/json")
+                        .json(&request_body)
+                        .send()
+                        .await;
+
+                    let dropped: anyhow::Error = match sent {
+                        Err(e) => e.into(),
+                        // A reconnect that's rejected isn't about `stream_options`, since the first request took them
+                        Ok(response) if response.status() == reqwest::StatusCode::BAD_REQUEST && include_usage && reconnects == 0 => {
+                            include_usage = false;
+                            continue 'attempt;
+                        }
+                        Ok(response) if !response.status().is_success() => {
+                            let status = response.status();
+                            let error_text = response.text().await.unwrap_or_default();
+                            let error: anyhow::Error = ProviderError::new(&config.provider, status, "API request failed", &error_text).into();
+                            // An overloaded or rate limited server gets the same retries as a dropped connection
+                            if !is_provider_unavailable(&error) {
+                                return Err(StreamInterrupted {
+                                    partial: CompletionOutput::new(full_response, Some(full_reasoning)).with_usage(usage),
+                                    source: error,
+                                }.into());
                             }
-                            
-                            if let Ok(json_data) = serde_json::from_str::<serde_json::Value>(data) {
-                                if let Some(choices) = json_data["choices"].as_array() {
-                                    if let Some(choice) = choices.first() {
-                                        if let Some(delta) = choice["delta"].as_object() {
-                                            if let Some(content) = delta["content"].as_str() {
-                                                full_response.push_str(content);
-                                                
-                                                // Emit streaming chunk to frontend
-                                                let _ = window.emit("streaming_chunk", serde_json::json!({
-                                                    "message_id": message_id,
-                                                    "chunk": content,
-                                                    "full_content": full_response
-                                                }));
-                                            }
+                            error
+                        }
+                        Ok(response) => {
+                            let mut stream = response.bytes_stream();
+
+                            // Bytes received after the last complete line
+                            let mut pending: Vec<u8> = Vec::new();
+
+                            // Keep-alives count as activity; only total silence times out
+                            let failure = 'stream: loop {
+                                let finished = match tokio::time::timeout(idle_timeout, stream.next()).await {
+                                    Ok(Some(Ok(chunk))) => {
+                                        pending.extend_from_slice(&chunk);
+                                        false
+                                    }
+                                    Ok(Some(Err(e))) => break 'stream Some(e.into()),
+                                    Ok(None) => {
+                                        // Flush a final line the server didn't terminate
+                                        pending.push(b'\n');
+                                        true
+                                    }
+                                    Err(_) => {
+                                        break 'stream Some(anyhow::anyhow!(
+                                            "Stream stalled: no data received for {} seconds",
+                                            idle_timeout.as_secs()
+                                        ));
+                                    }
+                                };
+                                self.ensure_stream_live(active)?;
+
+                                // A line (or a multi-byte character) can span network chunks,
+                                // so only decode up to the last newline and carry the rest over
+                                let Some(line_end) = pending.iter().rposition(|&byte| byte == b'\n') else {
+                                    continue;
+                                };
+                                let complete: Vec<u8> = pending.drain(..=line_end).collect();
+                                let chunk_str = String::from_utf8_lossy(&complete);
+
+                                // Parse SSE format
+                                for line in chunk_str.lines() {
+                                    let data = match parse_sse_line(line) {
+                                        SseLine::Data(data) => data,
+                                        SseLine::Done => break 'stream None,
+                                        SseLine::Ignore => continue,
+                                    };
+
+                                    if let Ok(json_data) = serde_json::from_str::<serde_json::Value>(data) {
+                                        // Each attempt bills separately, so reconnects add up
+                                        if let Ok(chunk_usage) = serde_json::from_value::<TokenUsage>(json_data["usage"].clone()) {
+                                            usage.get_or_insert_with(TokenUsage::default).add(chunk_usage);
+                                        }
+
+                                        if let Some(reason) = json_data["choices"][0]["finish_reason"].as_str() {
+                                            finish_reason = Some(reason.to_string());
+                                        }
+
+                                        let delta = &json_data["choices"][0]["delta"];
+
+                                        let mut reasoning = delta["reasoning"].as_str()
+                                            .or_else(|| delta["reasoning_content"].as_str())
+                                            .unwrap_or_default()
+                                            .to_string();
+                                        let mut content = String::new();
+
+                                        if let Some(text) = delta["content"].as_str() {
+                                            let (visible, thoughts) = think_tags.push(text);
+                                            content = visible;
+                                            reasoning.push_str(&thoughts);
                                         }
+
+                                        emit_stream_chunks(window, message_id, &content, &reasoning, &mut full_response, &mut full_reasoning);
                                     }
                                 }
+
+                                if finished {
+                                    break None;
+                                }
+                            };
+
+                            match failure {
+                                Some(e) => e,
+                                None => break 'attempt,
                             }
                         }
+                    };
+
+                    if reconnects >= max_reconnects {
+                        return Err(StreamInterrupted {
+                            partial: CompletionOutput::new(full_response, Some(full_reasoning)).with_usage(usage),
+                            source: dropped,
+                        }.into());
                     }
+                    reconnects += 1;
+
+                    let delay = RECONNECT_BASE_DELAY
+                        .saturating_mul(1 << (reconnects - 1).min(16))
+                        .min(RECONNECT_MAX_DELAY);
+
+                    let _ = window.emit("streaming_reconnecting", serde_json::json!({
+                        "message_id": message_id,
+                        "chat_id": chat_id,
+                        "attempt": reconnects,
+                        "max_attempts": max_reconnects,
+                        "delay_ms": delay.as_millis() as u64,
+                        "error": dropped.to_string()
+                    }));
+
+                    tokio::time::sleep(delay).await;
+                    self.ensure_stream_live(active)?;
                 }
 
+                let (content, reasoning) = think_tags.finish();
+                emit_stream_chunks(window, message_id, &content, &reasoning, &mut full_response, &mut full_reasoning);
+
+                let output = CompletionOutput::new(full_response, Some(full_reasoning))
+                    .with_usage(usage)
+                    .with_finish_reason(finish_reason.as_deref());
+
                 // Emit streaming complete event with the content
                 let _ = window.emit("streaming_complete", serde_json::json!({
                     "message_id": message_id,
-                    "content": full_response,
+                    "content": output.content,
+                    "reasoning": output.reasoning,
+                    "usage": output.usage,
+                    "finish_reason": output.finish_reason,
                     "chat_id": chat_id
                 }));
 
-                Ok(full_response)
+                Ok(output)
             },
             // For other providers, fall back to non-streaming for now
             _ => {
                 // Simulate streaming by sending the full response in chunks
-                let response = self.send_chat_completion(config, messages).await?;
+                let output = self.send_to_provider(config, messages).await?;
+
+                if let Some(reasoning) = &output.reasoning {
+                    let _ = window.emit("reasoning_chunk", serde_json::json!({
+                        "message_id": message_id,
+                        "chunk": reasoning,
+                        "full_reasoning": reasoning
+                    }));
+                }
                 
                 // Split response into words and send as chunks
-                let words: Vec<&str> = response.split_whitespace().collect();
+                let words: Vec<&str> = output.content.split_whitespace().collect();
                 let mut current_content = String::new();
                 
                 for (i, word) in words.iter().enumerate() {
+                    self.ensure_stream_live(active)?;
                     current_content.push_str(word);
                     if i < words.len() - 1 {
                         current_content.push(' ');
@@ -744,12 +3491,349 @@ impl Database {
                 // Emit streaming complete event with the content
                 let _ = window.emit("streaming_complete", serde_json::json!({
                     "message_id": message_id,
-                    "content": response,
+                    "content": output.content,
+                    "reasoning": output.reasoning,
+                    "usage": output.usage,
+                    "finish_reason": output.finish_reason,
                     "chat_id": chat_id
                 }));
 
-                Ok(response)
+                Ok(output)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn config_request(name: &str) -> CreateApiConfigRequest {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "provider": "ollama",
+            "api_key": "",
+            "model": "llama3",
+            "is_default": false,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn in_memory_database_applies_every_migration() {
+        let db = Database::new_in_memory().await.unwrap();
+
+        let latest = sqlx::migrate!("./migrations").iter().map(|migration| migration.version).max();
+        assert_eq!(db.schema_version().await.unwrap(), latest);
+
+        let chat = db.create_chat("Scratch".to_string(), None).await.unwrap();
+        assert_eq!(db.get_chat(&chat.id).await.unwrap().map(|chat| chat.title), Some("Scratch".to_string()));
+    }
+
+    #[tokio::test]
+    async fn api_errors_stay_with_the_chat_that_failed() {
+        let db = Database::new_in_memory().await.unwrap();
+        let config = db.create_api_config(config_request("Local")).await.unwrap();
+        let failed: Result<()> = Err(ProviderError::new(
+            &config.provider,
+            reqwest::StatusCode::UNAUTHORIZED,
+            "API request failed",
+            r#"{"error":{"message":"bad key","code":"invalid_api_key"}}"#,
+        )
+        .into());
+
+        db.record_send_outcome("first", &config, &failed);
+        db.record_send_outcome("second", &config, &Ok(()));
+        assert_eq!(db.last_api_error("first").map(|details| details.status), Some(Some(401)));
+        assert!(db.last_api_error("second").is_none());
+
+        db.record_send_outcome("first", &config, &Ok(()));
+        assert!(db.last_api_error("first").is_none());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_set_default_leaves_one_default() {
+        let db = Arc::new(Database::new_in_memory().await.unwrap());
+        let mut ids = Vec::new();
+        for index in 0..8 {
+            ids.push(db.create_api_config(config_request(&format!("config {}", index))).await.unwrap().id);
+        }
+
+        let handles: Vec<_> = ids.iter().cycle().take(32).cloned()
+            .map(|id| {
+                let db = db.clone();
+                tokio::spawn(async move { db.set_default_api_config(&id).await })
+            })
+            .collect();
+        for handle in handles {
+            handle.await.unwrap().unwrap();
+        }
+
+        let defaults = db.get_api_configs().await.unwrap()
+            .into_iter()
+            .filter(|config| config.is_default)
+            .count();
+        assert_eq!(defaults, 1);
+    }
+
+    #[tokio::test]
+    async fn chats_with_identical_timestamps_keep_a_stable_order() {
+        let db = Database::new_in_memory().await.unwrap();
+        for index in 0..6 {
+            db.create_chat(format!("Imported {}", index), None).await.unwrap();
+        }
+        let now = Utc::now();
+        sqlx::query("UPDATE chats SET created_at = ?, updated_at = ?")
+            .bind(now)
+            .bind(now)
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let order = |chats: Vec<ChatWithLastMessage>| chats.into_iter().map(|chat| chat.id).collect::<Vec<_>>();
+        let first = order(db.get_chats().await.unwrap());
+        let mut by_id = first.clone();
+        by_id.sort();
+
+        assert_eq!(first, by_id);
+        for _ in 0..5 {
+            assert_eq!(order(db.get_chats().await.unwrap()), first);
+        }
+    }
+
+    #[tokio::test]
+    async fn per_chat_queries_use_their_indexes() {
+        let db = Database::new_in_memory().await.unwrap();
+        let plan = |query: &'static str| {
+            let pool = db.pool.clone();
+            async move {
+                let rows = sqlx::query(&format!("EXPLAIN QUERY PLAN {}", query))
+                    .bind("chat")
+                    .fetch_all(&pool)
+                    .await
+                    .unwrap();
+                rows.iter().map(|row| row.get::<String, _>("detail")).collect::<Vec<_>>().join("\n")
             }
+        };
+
+        let messages = plan("SELECT id, chat_id, content, role, created_at FROM messages WHERE chat_id = ? ORDER BY created_at ASC").await;
+        assert!(messages.contains("idx_messages_chat_id_created_at"), "{}", messages);
+        assert!(!messages.contains("TEMP B-TREE"), "sorted without the index: {}", messages);
+
+        let chats = plan("SELECT id FROM chats WHERE api_config_id = ?").await;
+        assert!(chats.contains("idx_chats_api_config_id"), "{}", chats);
+    }
+
+    #[test]
+    fn ollama_url_joins_endpoint_onto_any_base_form() {
+        let cases = [
+            (None, "http://localhost:11434/api/chat"),
+            (Some("  "), "http://localhost:11434/api/chat"),
+            (Some("https://ollama.example.com"), "https://ollama.example.com/api/chat"),
+            (Some("https://ollama.example.com/"), "https://ollama.example.com/api/chat"),
+            (Some("https://ollama.example.com//"), "https://ollama.example.com/api/chat"),
+            (Some("https://ollama.example.com/api"), "https://ollama.example.com/api/chat"),
+            (Some("https://ollama.example.com/api/"), "https://ollama.example.com/api/chat"),
+            (Some("https://ollama.example.com/api/generate"), "https://ollama.example.com/api/chat"),
+            (Some("https://ollama.example.com/v1"), "https://ollama.example.com/api/chat"),
+            (Some("https://ollama.example.com/v1/"), "https://ollama.example.com/api/chat"),
+            (Some("https://example.com/proxy/ollama/"), "https://example.com/proxy/ollama/api/chat"),
+        ];
+
+        for (base_url, expected) in cases {
+            assert_eq!(ollama_url(base_url, "chat"), expected, "base_url {:?}", base_url);
         }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn purging_old_chats_keeps_pinned_ones_after_a_reorder() {
+        let db = Database::new_in_memory().await.unwrap();
+        let kept = db.create_chat("Pinned".to_string(), None).await.unwrap();
+        let purged = db.create_chat("Ordered".to_string(), None).await.unwrap();
+        db.reorder_chats(&[purged.id.clone(), kept.id.clone()]).await.unwrap();
+        db.set_chat_pinned(&kept.id, true).await.unwrap();
+        sqlx::query("UPDATE chats SET updated_at = ?")
+            .bind(Utc::now() - chrono::Duration::days(90))
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        assert_eq!(db.delete_chats_older_than(30, false).await.unwrap(), 1);
+        assert!(db.get_chat(&purged.id).await.unwrap().is_none());
+        assert!(db.get_chat(&kept.id).await.unwrap().is_some_and(|chat| chat.pinned));
+    }
+
+    #[tokio::test]
+    async fn renaming_a_chat_keeps_its_overrides() {
+        let db = Database::new_in_memory().await.unwrap();
+        let config = db.create_api_config(config_request("Local")).await.unwrap();
+        let chat = db.create_chat("Draft".to_string(), Some(config.id.clone())).await.unwrap();
+        let update = |request: serde_json::Value| serde_json::from_value::<UpdateChatRequest>(request).unwrap();
+
+        db.update_chat(&chat.id, update(serde_json::json!({
+            "temperature_override": 0.2,
+            "max_tokens_override": 512,
+            "pinned_context": "Answer in French",
+        }))).await.unwrap();
+        let renamed = db.update_chat(&chat.id, update(serde_json::json!({ "title": "Final" }))).await.unwrap();
+
+        assert_eq!(renamed.title, "Final");
+        assert_eq!(renamed.pinned_context.as_deref(), Some("Answer in French"));
+        assert_eq!(renamed.api_config_id, Some(config.id));
+        assert_eq!(renamed.temperature_override, Some(0.2));
+        assert_eq!(renamed.max_tokens_override, Some(512));
+
+        let cleared = db.update_chat(&chat.id, update(serde_json::json!({ "api_config_id": null, "temperature_override": null }))).await.unwrap();
+        assert_eq!(cleared.title, "Final");
+        assert_eq!(cleared.api_config_id, None);
+        assert_eq!(cleared.temperature_override, None);
+        assert_eq!(cleared.max_tokens_override, Some(512));
+        assert_eq!(cleared.pinned_context.as_deref(), Some("Answer in French"));
+
+        let unpinned = db.update_chat(&chat.id, update(serde_json::json!({ "pinned_context": "  " }))).await.unwrap();
+        assert_eq!(unpinned.pinned_context, None);
+    }
+
+    #[tokio::test]
+    async fn global_search_follows_message_edits_through_the_index() {
+        let db = Database::new_in_memory().await.unwrap();
+        let chat = db.create_chat("Trip".to_string(), None).await.unwrap();
+        let message = db.create_message(chat.id.clone(), "Meet at the CAFÉ on Rue Cler".to_string(), MessageRole::User, None).await.unwrap();
+        let message_hits = |hits: Vec<GlobalHit>| hits.into_iter().filter_map(|hit| hit.message_id).collect::<Vec<_>>();
+
+        assert_eq!(message_hits(db.global_search("café", 10).await.unwrap()), vec![message.id.clone()]);
+        assert_eq!(message_hits(db.global_search("cafe on rue", 10).await.unwrap()), vec![message.id.clone()]);
+        assert_eq!(message_hits(db.global_search("rue cl", 10).await.unwrap()), vec![message.id.clone()]);
+        assert!(db.global_search("\"* OR -", 10).await.unwrap().is_empty());
+
+        db.append_to_message(&message.id, " at noon", None).await.unwrap();
+        assert_eq!(message_hits(db.global_search("noon", 10).await.unwrap()), vec![message.id.clone()]);
+
+        db.delete_message(&message.id).await.unwrap();
+        assert!(message_hits(db.global_search("café", 10).await.unwrap()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn recovery_rebuilds_message_search_for_the_copied_rows() {
+        let dir = std::env::temp_dir().join(format!("chatme-test-{}", Uuid::new_v4()));
+        let db = Database::with_data_dir(dir.clone()).await.unwrap();
+        let chat = db.create_chat("Notes".to_string(), None).await.unwrap();
+        // Leave a gap in the rowids, so a renumbered copy would shift every message
+        let dropped = db.create_message(chat.id.clone(), "scratch".to_string(), MessageRole::User, None).await.unwrap();
+        let needle = db.create_message(chat.id.clone(), "The needle is in here".to_string(), MessageRole::User, None).await.unwrap();
+        let haystack = db.create_message(chat.id.clone(), "Only hay in this one".to_string(), MessageRole::Assistant, None).await.unwrap();
+        db.delete_message(&dropped.id).await.unwrap();
+
+        let damaged_root: i64 = sqlx::query_scalar("SELECT rootpage FROM sqlite_master WHERE name = 'prompt_templates'")
+            .fetch_one(&db.pool)
+            .await
+            .unwrap();
+        let page_size: i64 = sqlx::query_scalar("PRAGMA page_size").fetch_one(&db.pool).await.unwrap();
+        db.close().await;
+
+        // Damage a table the messages don't depend on, leaving the old search index readable
+        let path = dir.join("chatme.db");
+        let mut bytes = std::fs::read(&path).unwrap();
+        let start = ((damaged_root - 1) * page_size) as usize;
+        bytes[start..start + page_size as usize].fill(0xff);
+        std::fs::write(&path, bytes).unwrap();
+
+        let db = Database::with_data_dir(dir.clone()).await.unwrap();
+        let message_hits = |hits: Vec<GlobalHit>| hits.into_iter().filter_map(|hit| hit.message_id).collect::<Vec<_>>();
+        let found = (
+            message_hits(db.global_search("needle", 10).await.unwrap()),
+            message_hits(db.global_search("hay", 10).await.unwrap()),
+        );
+        let report = db.check_integrity().await.unwrap();
+        db.close().await;
+        let backup_kept = report.startup_repair.as_ref().is_some_and(|repair| Path::new(&repair.backup_path).exists());
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert_eq!(found, (vec![needle.id], vec![haystack.id]));
+        assert!(report.problems.is_empty() && !report.restart_required, "{:?}", report);
+        let repair = report.startup_repair.unwrap();
+        assert!(backup_kept);
+        assert!(!repair.problems.is_empty());
+        let messages = repair.tables.iter().find(|table| table.table == "messages").unwrap();
+        assert_eq!((messages.rows_recovered, messages.rows_lost), (2, 0));
+    }
+
+    #[tokio::test]
+    async fn api_config_export_round_trips_through_import() {
+        let source = Database::new_in_memory().await.unwrap();
+        let mut request = config_request("Local");
+        request.keep_alive = Some("10m".to_string());
+        request.context_strategy = Some(ContextStrategy::TokenBudget(4000));
+        source.create_api_config(request).await.unwrap();
+        let export = source.export_api_configs(false).await.unwrap();
+
+        let target = Database::new_in_memory().await.unwrap();
+        let local = |db: &Database| {
+            let pool = db.pool.clone();
+            async move {
+                sqlx::query_as::<_, ApiConfig>("SELECT * FROM api_configs WHERE name = 'Local'")
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let created = target.import_api_configs(&export, false).await.unwrap();
+        assert_eq!(created.created, 1);
+        let imported = local(&target).await;
+        assert_eq!(imported.keep_alive.as_deref(), Some("10m"));
+        assert_eq!(imported.context_strategy, ContextStrategy::TokenBudget(4000));
+
+        // Overwriting an existing config takes the exported values too
+        sqlx::query("UPDATE api_configs SET keep_alive = '-1', context_strategy = ?")
+            .bind(serde_json::to_string(&ContextStrategy::Full).unwrap())
+            .execute(&target.pool)
+            .await
+            .unwrap();
+        target.import_api_configs(&export, true).await.unwrap();
+        let overwritten = local(&target).await;
+        assert_eq!(overwritten.keep_alive.as_deref(), Some("10m"));
+        assert_eq!(overwritten.context_strategy, ContextStrategy::TokenBudget(4000));
+    }
+
+    #[tokio::test]
+    async fn streams_fall_back_only_until_part_of_the_reply_arrives() {
+        let db = Database::new_in_memory().await.unwrap();
+        let backup = db.create_api_config(config_request("Backup")).await.unwrap();
+        let mut request = config_request("Primary");
+        request.fallback_config_id = Some(backup.id.clone());
+        let primary = db.create_api_config(request).await.unwrap();
+
+        // The primary's stream fails with a 503 after `partial` arrived; the backup answers
+        let stream = |partial: &'static str| {
+            let primary = primary.clone();
+            move |current: ApiConfig| {
+                let outcome = if current.id == primary.id {
+                    Err(StreamInterrupted {
+                        partial: CompletionOutput::new(partial.to_string(), None),
+                        source: ProviderError::new(&primary.provider, reqwest::StatusCode::SERVICE_UNAVAILABLE, "API request failed", "overloaded").into(),
+                    }.into())
+                } else {
+                    Ok(CompletionOutput::new("From the backup".to_string(), None))
+                };
+                async move { outcome }
+            }
+        };
+
+        let mut switches = Vec::new();
+        let answered = db.with_fallbacks(&primary, stream(""), |from, to, _| switches.push((from.id.clone(), to.id.clone())))
+            .await
+            .unwrap();
+        assert_eq!(answered.content, "From the backup");
+        assert_eq!(answered.api_config_id, Some(backup.id.clone()));
+        assert_eq!(switches, vec![(primary.id.clone(), backup.id.clone())]);
+
+        let err = db.with_fallbacks(&primary, stream("Half of an ans"), |_, _, _| panic!("fell back mid-reply"))
+            .await
+            .unwrap_err();
+        let interrupted = err.downcast_ref::<StreamInterrupted>().unwrap();
+        assert_eq!(interrupted.partial.content, "Half of an ans");
+        assert_eq!(interrupted.partial.api_config_id, None);
+    }
+}
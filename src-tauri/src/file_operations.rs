@@ -1,7 +1,13 @@
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use regex::Regex;
 use walkdir::WalkDir;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use anyhow::{Result, anyhow};
 
@@ -22,6 +28,21 @@ pub struct SearchResult {
     pub line_content: String,
     pub match_start: usize,
     pub match_end: usize,
+    pub matched_pattern: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileMatchCount {
+    pub file_path: String,
+    pub match_count: usize,
+}
+
+/// Either individual matches or per-file counts, depending on `count_only`
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SearchOutput {
+    Matches(Vec<SearchResult>),
+    Counts(Vec<FileMatchCount>),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,191 +53,1804 @@ pub struct DirectoryContents {
     pub total_directories: usize,
 }
 
-/// Open a file or directory with the default system application
-pub fn open_with_default_app(path: &str) -> Result<()> {
-    let path = Path::new(path);
+/// Open a file or directory with the default system application
+pub fn open_with_default_app(path: &str) -> Result<()> {
+    let path = Path::new(path);
+    
+    if !path.exists() {
+        return Err(anyhow!("Path does not exist: {}", path.display()));
+    }
+    
+    opener::open(path)
+        .map_err(|e| anyhow!("Failed to open file with default app: {}", e))?;
+    
+    Ok(())
+}
+
+/// URL schemes `open_url` will hand to the system; `file://` and custom handlers are refused
+const ALLOWED_URL_SCHEMES: &[&str] = &["http", "https", "mailto"];
+
+/// Open a web or mail URL in the default application
+pub fn open_url(url: &str) -> Result<()> {
+    let parsed = reqwest::Url::parse(url.trim())
+        .map_err(|e| anyhow!("Invalid URL {}: {}", url, e))?;
+
+    if !ALLOWED_URL_SCHEMES.contains(&parsed.scheme()) {
+        return Err(anyhow!(
+            "URL scheme '{}' is not allowed; expected one of: {}",
+            parsed.scheme(),
+            ALLOWED_URL_SCHEMES.join(", ")
+        ));
+    }
+
+    opener::open(parsed.as_str())
+        .map_err(|e| anyhow!("Failed to open URL: {}", e))?;
+
+    Ok(())
+}
+
+/// Read the contents of a directory and return file information
+pub fn read_directory_contents(directory_path: &str, recursive: bool) -> Result<DirectoryContents> {
+    let path = Path::new(directory_path);
+    
+    if !path.exists() {
+        return Err(anyhow!("Directory does not exist: {}", path.display()));
+    }
+    
+    if !path.is_dir() {
+        return Err(anyhow!("Path is not a directory: {}", path.display()));
+    }
+    
+    let mut files = Vec::new();
+    let mut directories = Vec::new();
+    
+    if recursive {
+        for entry in WalkDir::new(path)
+            .follow_links(false)
+            .into_iter()
+            .filter_map(|e| e.ok())
+        {
+            if entry.path() == path {
+                continue; // Skip the root directory itself
+            }
+            
+            let file_info = create_file_info(entry.path())?;
+            
+            if file_info.is_directory {
+                directories.push(file_info);
+            } else {
+                files.push(file_info);
+            }
+        }
+    } else {
+        let entries = fs::read_dir(path)
+            .map_err(|e| anyhow!("Failed to read directory: {}", e))?;
+        
+        for entry in entries {
+            let entry = entry.map_err(|e| anyhow!("Failed to read directory entry: {}", e))?;
+            let file_info = create_file_info(&entry.path())?;
+            
+            if file_info.is_directory {
+                directories.push(file_info);
+            } else {
+                files.push(file_info);
+            }
+        }
+    }
+    
+    // Sort files and directories by name
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    directories.sort_by(|a, b| a.name.cmp(&b.name));
+    
+    Ok(DirectoryContents {
+        total_files: files.len(),
+        total_directories: directories.len(),
+        files,
+        directories,
+    })
+}
+
+/// Deepest `get_file_tree` will walk, whatever depth is asked for
+pub const MAX_FILE_TREE_DEPTH: usize = 16;
+
+/// Entries `get_file_tree` returns at most, so a huge tree can't stall the UI
+const MAX_FILE_TREE_NODES: usize = 10_000;
+
+/// One entry of a directory tree, with its children nested under it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TreeNode {
+    pub name: String,
+    pub path: String,
+    pub is_directory: bool,
+    /// Directories first, then files, each sorted by name
+    pub children: Vec<TreeNode>,
+    /// Set on directories whose contents were left out: past the depth
+    /// limit, over the entry budget, or unreadable
+    #[serde(default)]
+    pub truncated: bool,
+}
+
+/// Build the tree under `directory_path`, `max_depth` levels deep (1 lists
+/// only the directory's own entries). Ignored directories such as `.git`
+/// and `node_modules` are left out, and symlinks are not followed.
+pub fn get_file_tree(directory_path: &str, max_depth: usize) -> Result<TreeNode> {
+    let path = Path::new(directory_path);
+    
+    if !path.exists() {
+        return Err(anyhow!("Directory does not exist: {}", path.display()));
+    }
+    
+    if !path.is_dir() {
+        return Err(anyhow!("Path is not a directory: {}", path.display()));
+    }
+    
+    let mut budget = MAX_FILE_TREE_NODES;
+    let mut root = tree_node(path, true);
+    fill_tree(&mut root, path, max_depth.min(MAX_FILE_TREE_DEPTH), &mut budget);
+    Ok(root)
+}
+
+fn tree_node(path: &Path, is_directory: bool) -> TreeNode {
+    TreeNode {
+        name: path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string_lossy().to_string()),
+        path: path.to_string_lossy().to_string(),
+        is_directory,
+        children: Vec::new(),
+        truncated: false,
+    }
+}
+
+fn fill_tree(node: &mut TreeNode, path: &Path, depth_left: usize, budget: &mut usize) {
+    if depth_left == 0 {
+        node.truncated = true;
+        return;
+    }
+    
+    let Ok(entries) = fs::read_dir(path) else {
+        node.truncated = true;
+        return;
+    };
+    
+    let mut children: Vec<(PathBuf, bool)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            // file_type doesn't follow symlinks, so linked directories show up as leaves
+            let is_directory = entry.file_type().ok()?.is_dir();
+            if is_directory && IGNORED_DIRECTORIES.contains(&entry.file_name().to_string_lossy().as_ref()) {
+                return None;
+            }
+            Some((entry.path(), is_directory))
+        })
+        .collect();
+    children.sort_by(|(a_path, a_dir), (b_path, b_dir)| b_dir.cmp(a_dir).then_with(|| a_path.file_name().cmp(&b_path.file_name())));
+    
+    for (child_path, is_directory) in children {
+        if *budget == 0 {
+            node.truncated = true;
+            return;
+        }
+        *budget -= 1;
+        
+        let mut child = tree_node(&child_path, is_directory);
+        if is_directory {
+            fill_tree(&mut child, &child_path, depth_left - 1, budget);
+        }
+        node.children.push(child);
+    }
+}
+
+/// Upper bound on the compiled size of a search pattern (1 MB)
+const REGEX_SIZE_LIMIT: usize = 1024 * 1024;
+
+/// Compile a user-supplied search pattern with bounded compiled size
+pub fn compile_search_regex(pattern: &str, case_sensitive: bool) -> Result<Regex> {
+    if pattern.is_empty() {
+        return Err(anyhow!("Invalid regex pattern: pattern must not be empty"));
+    }
+    
+    regex::RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .size_limit(REGEX_SIZE_LIMIT)
+        .dfa_size_limit(REGEX_SIZE_LIMIT)
+        .build()
+        .map_err(|e| anyhow!("Invalid regex pattern: {}", e))
+}
+
+/// How search patterns are interpreted
+///
+/// `fixed_string` is applied first, so with both flags set the pattern is
+/// escaped and then wrapped in `\b...\b`, i.e. the literal must appear as a
+/// whole word. Word boundaries only apply at word characters, so a literal
+/// that starts or ends with punctuation may not match as a whole word.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    /// Only match at word boundaries
+    pub whole_word: bool,
+    /// Treat the pattern as literal text rather than a regex
+    pub fixed_string: bool,
+}
+
+impl SearchOptions {
+    fn to_regex_source(self, pattern: &str) -> String {
+        let source = if self.fixed_string {
+            regex::escape(pattern)
+        } else {
+            pattern.to_string()
+        };
+        
+        if self.whole_word {
+            format!(r"\b(?:{})\b", source)
+        } else {
+            source
+        }
+    }
+}
+
+/// Prefix for the capture group wrapping each pattern in a combined search
+const PATTERN_GROUP_PREFIX: &str = "chatme_pattern_";
+
+/// One or more search patterns compiled into a single alternation, so the
+/// tree is walked once however many terms are searched for
+#[derive(Debug)]
+pub struct SearchMatcher {
+    regex: Regex,
+    patterns: Vec<String>,
+    /// Capture group index of each pattern; empty for a single pattern
+    group_indices: Vec<usize>,
+}
+
+impl SearchMatcher {
+    pub fn new(patterns: &[String], options: SearchOptions) -> Result<Self> {
+        if patterns.is_empty() {
+            return Err(anyhow!("At least one search pattern is required"));
+        }
+        
+        if patterns.iter().any(|pattern| pattern.is_empty()) {
+            return Err(anyhow!("Invalid regex pattern: pattern must not be empty"));
+        }
+        
+        let sources: Vec<String> = patterns
+            .iter()
+            .map(|pattern| options.to_regex_source(pattern))
+            .collect();
+        
+        // Compile each pattern on its own first so errors name the bad one
+        for (pattern, source) in patterns.iter().zip(&sources) {
+            compile_search_regex(source, options.case_sensitive)
+                .map_err(|e| anyhow!("{} (in pattern '{}')", e, pattern))?;
+        }
+        
+        if let [source] = sources.as_slice() {
+            return Ok(Self {
+                regex: compile_search_regex(source, options.case_sensitive)?,
+                patterns: patterns.to_vec(),
+                group_indices: Vec::new(),
+            });
+        }
+        
+        let combined = sources
+            .iter()
+            .enumerate()
+            .map(|(index, source)| format!("(?P<{}{}>{})", PATTERN_GROUP_PREFIX, index, source))
+            .collect::<Vec<_>>()
+            .join("|");
+        let regex = compile_search_regex(&combined, options.case_sensitive)?;
+        
+        let group_indices = (0..patterns.len())
+            .map(|index| {
+                let name = format!("{}{}", PATTERN_GROUP_PREFIX, index);
+                regex.capture_names()
+                    .position(|group| group == Some(name.as_str()))
+                    .ok_or_else(|| anyhow!("Failed to combine search patterns"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        
+        Ok(Self { regex, patterns: patterns.to_vec(), group_indices })
+    }
+    
+    /// Call `f(start, end, pattern)` for every match in `line`
+    fn for_each_match(&self, line: &str, mut f: impl FnMut(usize, usize, &str)) {
+        if self.group_indices.is_empty() {
+            for mat in self.regex.find_iter(line) {
+                f(mat.start(), mat.end(), &self.patterns[0]);
+            }
+            return;
+        }
+        
+        for captures in self.regex.captures_iter(line) {
+            let Some(mat) = captures.get(0) else { continue };
+            let pattern = self.group_indices
+                .iter()
+                .position(|&group| captures.get(group).is_some())
+                .map(|index| self.patterns[index].as_str())
+                .unwrap_or_default();
+            f(mat.start(), mat.end(), pattern);
+        }
+    }
+    
+    fn count(&self, line: &str) -> usize {
+        self.regex.find_iter(line).count()
+    }
+}
+
+/// Error returned by a long-running operation whose cancel flag was raised.
+/// `progress` describes the work done before it stopped.
+#[derive(Debug)]
+pub struct Cancelled {
+    pub progress: serde_json::Value,
+}
+
+impl std::fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Operation cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+pub fn is_cancelled(cancel: Option<&AtomicBool>) -> bool {
+    cancel.is_some_and(|cancel| cancel.load(Ordering::Relaxed))
+}
+
+/// Worker threads for multi-file operations, from `AppSettings.max_threads`
+static MAX_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+/// The pool behind `with_worker_pool` and the thread count it was built for
+static WORKER_POOL: Mutex<Option<(usize, Arc<rayon::ThreadPool>)>> = Mutex::new(None);
+
+/// Longest line a search with a per-file timeout will scan. The timeout is
+/// checked between lines, so this bounds how far one line (minified code,
+/// a stray binary) can run past it.
+const MAX_TIMED_LINE_BYTES: usize = 1 << 20;
+
+/// Files handed to the workers at a time by search, so `max_results` and
+/// `cancel` can stop it without visiting the whole tree
+const SEARCH_BATCH_FILES: usize = 256;
+
+/// Set how many threads multi-file operations may use; 0 uses one per CPU
+pub fn set_max_threads(threads: usize) {
+    MAX_THREADS.store(threads, Ordering::Relaxed);
+}
+
+/// Run `work` on a thread pool sized by `set_max_threads`, or on the
+/// calling thread if the pool can't be started
+pub fn with_worker_pool<R: Send>(work: impl FnOnce() -> R + Send) -> R {
+    match worker_pool() {
+        Some(pool) => pool.install(work),
+        None => work(),
+    }
+}
+
+/// The shared worker pool, rebuilt only when `set_max_threads` changed the size
+fn worker_pool() -> Option<Arc<rayon::ThreadPool>> {
+    let threads = MAX_THREADS.load(Ordering::Relaxed);
+    let mut cached = WORKER_POOL.lock().ok()?;
+    
+    if let Some((size, pool)) = cached.as_ref() {
+        if *size == threads {
+            return Some(pool.clone());
+        }
+    }
+    
+    let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(threads).build().ok()?);
+    *cached = Some((threads, pool.clone()));
+    Some(pool)
+}
+
+/// The next files of a walk to hand to the workers, up to
+/// `SEARCH_BATCH_FILES`. Stops filling early once `cancel` is raised.
+fn next_file_batch(files: &mut impl Iterator<Item = Result<PathBuf>>, cancel: Option<&AtomicBool>) -> Result<Vec<PathBuf>> {
+    let mut batch = Vec::new();
+    while batch.len() < SEARCH_BATCH_FILES && !is_cancelled(cancel) {
+        match files.next() {
+            Some(file) => batch.push(file?),
+            None => break,
+        }
+    }
+    Ok(batch)
+}
+
+/// Search for text patterns in files using regex
+///
+/// `file_timeout` bounds the time spent on any single file; files that exceed
+/// it, or have a line longer than `MAX_TIMED_LINE_BYTES`, are skipped like
+/// unreadable ones. Raising `cancel` stops the search
+/// between files with a [`Cancelled`] error holding the results so far.
+/// `skip_ignored_directories` leaves out `IGNORED_DIRECTORIES`.
+#[allow(clippy::too_many_arguments)]
+pub fn search_in_files(
+    directory_path: &str,
+    matcher: &SearchMatcher,
+    file_extension_filter: Option<&str>,
+    recursive: bool,
+    skip_ignored_directories: bool,
+    max_results: Option<usize>,
+    file_timeout: Option<Duration>,
+    cancel: Option<&AtomicBool>,
+) -> Result<Vec<SearchResult>> {
+    let path = Path::new(directory_path);
+    
+    if !path.exists() || !path.is_dir() {
+        return Err(anyhow!("Invalid directory path: {}", path.display()));
+    }
+    
+    // The walk is consumed a batch at a time, so reaching `max_results` or a
+    // cancel stops it without listing the rest of the tree
+    let mut files = searchable_files(path, file_extension_filter, recursive, skip_ignored_directories);
+    let mut results = Vec::new();
+    
+    with_worker_pool(|| {
+        loop {
+            let batch = next_file_batch(&mut files, cancel)?;
+            if is_cancelled(cancel) {
+                return Err(Cancelled { progress: serde_json::to_value(&results)? }.into());
+            }
+            if batch.is_empty() {
+                return Ok(results);
+            }
+            
+            // Files are searched in parallel but their results kept in walk order
+            let batch_results: Vec<_> = batch.par_iter()
+                .map(|file_path| search_in_file(file_path, matcher, file_timeout))
+                .collect();
+            
+            // Skip files that can't be read (e.g., permission issues)
+            for file_results in batch_results.into_iter().flatten() {
+                if max_results.is_some_and(|max| results.len() + file_results.len() > max) {
+                    return Ok(results);
+                }
+                results.extend(file_results);
+            }
+        }
+    })
+}
+
+/// Totals reported when an incremental search finishes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchSummary {
+    pub total_matches: usize,
+    pub files_searched: usize,
+    /// Stopped after `max_results` matches
+    pub truncated: bool,
+    /// Stopped because `cancel` was raised
+    pub cancelled: bool,
+}
+
+/// Like `search_in_files`, but hands each file's matches to `on_hits` as
+/// soon as that file has been searched instead of collecting them all.
+/// Stops after `max_results` matches, or between files when `cancel` is
+/// raised; the summary says which.
+#[allow(clippy::too_many_arguments)]
+pub fn search_in_files_incremental(
+    directory_path: &str,
+    matcher: &SearchMatcher,
+    file_extension_filter: Option<&str>,
+    recursive: bool,
+    skip_ignored_directories: bool,
+    max_results: Option<usize>,
+    file_timeout: Option<Duration>,
+    cancel: &AtomicBool,
+    mut on_hits: impl FnMut(Vec<SearchResult>),
+) -> Result<SearchSummary> {
+    let path = Path::new(directory_path);
+    
+    if !path.exists() || !path.is_dir() {
+        return Err(anyhow!("Invalid directory path: {}", path.display()));
+    }
+    
+    let mut summary = SearchSummary { total_matches: 0, files_searched: 0, truncated: false, cancelled: false };
+    
+    for file_path in searchable_files(path, file_extension_filter, recursive, skip_ignored_directories) {
+        if cancel.load(Ordering::Relaxed) {
+            summary.cancelled = true;
+            break;
+        }
+        let file_path = file_path?;
+        summary.files_searched += 1;
+        
+        // Skip files that can't be read (e.g., permission issues)
+        let Ok(mut hits) = search_in_file(&file_path, matcher, file_timeout) else {
+            continue;
+        };
+        if let Some(max) = max_results {
+            hits.truncate(max - summary.total_matches);
+        }
+        
+        summary.total_matches += hits.len();
+        if !hits.is_empty() {
+            on_hits(hits);
+        }
+        if max_results.is_some_and(|max| summary.total_matches >= max) {
+            summary.truncated = true;
+            break;
+        }
+    }
+    
+    Ok(summary)
+}
+
+/// Count matches per file without building a `SearchResult` for each one
+pub fn count_matches_in_files(
+    directory_path: &str,
+    matcher: &SearchMatcher,
+    file_extension_filter: Option<&str>,
+    recursive: bool,
+    skip_ignored_directories: bool,
+    file_timeout: Option<Duration>,
+    cancel: Option<&AtomicBool>,
+) -> Result<Vec<FileMatchCount>> {
+    let path = Path::new(directory_path);
+    
+    if !path.exists() || !path.is_dir() {
+        return Err(anyhow!("Invalid directory path: {}", path.display()));
+    }
+    
+    let mut files = searchable_files(path, file_extension_filter, recursive, skip_ignored_directories);
+    let mut counts = Vec::new();
+    
+    with_worker_pool(|| {
+        loop {
+            let batch = next_file_batch(&mut files, cancel)?;
+            if is_cancelled(cancel) {
+                return Err(Cancelled { progress: serde_json::to_value(&counts)? }.into());
+            }
+            if batch.is_empty() {
+                return Ok(counts);
+            }
+            
+            let batch_counts: Vec<_> = batch.par_iter()
+                .map(|file_path| count_in_file(file_path, matcher, file_timeout))
+                .collect();
+            
+            for (file_path, match_count) in batch.iter().zip(batch_counts) {
+                match match_count {
+                    Ok(0) => {}
+                    Ok(match_count) => counts.push(FileMatchCount {
+                        file_path: file_path.to_string_lossy().to_string(),
+                        match_count,
+                    }),
+                    // Skip files that can't be read (e.g., permission issues)
+                    Err(_) => continue,
+                }
+            }
+        }
+    })
+}
+
+/// Files with identical contents found by `find_duplicate_files`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    /// Size of each copy in bytes
+    pub size: u64,
+    /// Hex sha256 of the shared contents
+    pub hash: String,
+    pub paths: Vec<String>,
+}
+
+/// Reported periodically while `find_duplicate_files` runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateScanProgress {
+    pub files_scanned: usize,
+    /// Files sharing a size with another file; zero until the walk finishes
+    pub files_to_hash: usize,
+    pub files_hashed: usize,
+}
+
+/// Files walked or hashed between progress reports
+const DUPLICATE_PROGRESS_INTERVAL: usize = 500;
+
+/// Find groups of two or more files with identical contents.
+///
+/// Files are grouped by size first and only those sharing a size are hashed,
+/// so unique files are never read. Empty files and unreadable files are
+/// skipped, as are the directories search ignores. Groups come back with the
+/// most wasted space first. `on_progress` is called every few hundred files;
+/// raising `cancel` stops with a [`Cancelled`] error holding the last progress.
+pub fn find_duplicate_files(
+    directory_path: &str,
+    recursive: bool,
+    cancel: Option<&AtomicBool>,
+    mut on_progress: impl FnMut(&DuplicateScanProgress),
+) -> Result<Vec<DuplicateGroup>> {
+    let path = Path::new(directory_path);
+    
+    if !path.exists() || !path.is_dir() {
+        return Err(anyhow!("Invalid directory path: {}", path.display()));
+    }
+    
+    let walker = if recursive {
+        WalkDir::new(path).follow_links(false)
+    } else {
+        WalkDir::new(path).max_depth(1).follow_links(false)
+    };
+    
+    let mut progress = DuplicateScanProgress { files_scanned: 0, files_to_hash: 0, files_hashed: 0 };
+    let mut by_size: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    
+    let entries = walker
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry.file_type().is_dir()
+                || !IGNORED_DIRECTORIES.contains(&entry.file_name().to_string_lossy().as_ref())
+        })
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file());
+    
+    for entry in entries {
+        if is_cancelled(cancel) {
+            return Err(Cancelled { progress: serde_json::to_value(&progress)? }.into());
+        }
+        progress.files_scanned += 1;
+        if progress.files_scanned.is_multiple_of(DUPLICATE_PROGRESS_INTERVAL) {
+            on_progress(&progress);
+        }
+        
+        match entry.metadata() {
+            Ok(metadata) if metadata.len() > 0 => {
+                by_size.entry(metadata.len()).or_default().push(entry.into_path());
+            }
+            _ => continue,
+        }
+    }
+    
+    by_size.retain(|_, paths| paths.len() > 1);
+    progress.files_to_hash = by_size.values().map(Vec::len).sum();
+    on_progress(&progress);
+    
+    let mut groups = Vec::new();
+    for (size, paths) in by_size {
+        let mut by_hash: HashMap<String, Vec<String>> = HashMap::new();
+        
+        for file_path in paths {
+            if is_cancelled(cancel) {
+                return Err(Cancelled { progress: serde_json::to_value(&progress)? }.into());
+            }
+            progress.files_hashed += 1;
+            if progress.files_hashed.is_multiple_of(DUPLICATE_PROGRESS_INTERVAL) {
+                on_progress(&progress);
+            }
+            
+            // Skip files that can't be read (e.g., permission issues)
+            if let Ok(hash) = sha256_file(&file_path) {
+                by_hash.entry(hash).or_default().push(file_path.to_string_lossy().to_string());
+            }
+        }
+        
+        groups.extend(by_hash.into_iter()
+            .filter(|(_, paths)| paths.len() > 1)
+            .map(|(hash, mut paths)| {
+                paths.sort();
+                DuplicateGroup { size, hash, paths }
+            }));
+    }
+    
+    if !progress.files_hashed.is_multiple_of(DUPLICATE_PROGRESS_INTERVAL) {
+        on_progress(&progress);
+    }
+    
+    groups.sort_by(|a, b| {
+        let wasted = |group: &DuplicateGroup| group.size * (group.paths.len() as u64 - 1);
+        wasted(b).cmp(&wasted(a)).then_with(|| a.paths.cmp(&b.paths))
+    });
+    Ok(groups)
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    use sha2::{Digest, Sha256};
+    
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Directories the file tree and duplicate finder never descend into.
+/// Search and replace only skip them when asked to.
+const IGNORED_DIRECTORIES: &[&str] = &[".git", "node_modules", "target"];
+
+/// Walk the text files under `path` that search and replace should visit
+fn searchable_files<'a>(
+    path: &Path,
+    file_extension_filter: Option<&'a str>,
+    recursive: bool,
+    skip_ignored_directories: bool,
+) -> impl Iterator<Item = Result<PathBuf>> + 'a {
+    let walker = if recursive {
+        WalkDir::new(path).follow_links(false)
+    } else {
+        WalkDir::new(path).max_depth(1).follow_links(false)
+    };
+    
+    walker
+        .into_iter()
+        .filter_entry(move |entry| {
+            !skip_ignored_directories
+                || entry.depth() == 0
+                || !entry.file_type().is_dir()
+                || !IGNORED_DIRECTORIES.contains(&entry.file_name().to_string_lossy().as_ref())
+        })
+        .filter_map(|e| e.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(move |entry| {
+            // Apply file extension filter if specified
+            match file_extension_filter {
+                Some(ext_filter) => entry.path()
+                    .extension()
+                    .is_some_and(|extension| extension.to_string_lossy().to_lowercase() == ext_filter.to_lowercase()),
+                None => true,
+            }
+        })
+        .filter_map(|entry| {
+            // Skip binary files
+            match is_binary_file(entry.path()) {
+                Ok(true) => None,
+                Ok(false) => Some(Ok(entry.into_path())),
+                Err(e) => Some(Err(e)),
+            }
+        })
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReplaceResult {
+    pub file_path: String,
+    pub replacements: usize,
+    /// Changed lines as `-old` / `+new` pairs, only filled in for dry runs
+    pub preview: Option<String>,
+}
+
+/// Regex replace across files, returning per-file counts
+///
+/// With `dry_run` nothing is written and each result carries a preview of the
+/// changed lines. Files are rewritten atomically through a temp file.
+#[allow(clippy::too_many_arguments)]
+pub fn replace_in_files(
+    directory_path: &str,
+    pattern: &str,
+    replacement: &str,
+    file_extension_filter: Option<&str>,
+    case_sensitive: bool,
+    recursive: bool,
+    skip_ignored_directories: bool,
+    dry_run: bool,
+) -> Result<Vec<ReplaceResult>> {
+    let regex = compile_search_regex(pattern, case_sensitive)?;
+    
+    let path = Path::new(directory_path);
+    
+    if !path.exists() || !path.is_dir() {
+        return Err(anyhow!("Invalid directory path: {}", path.display()));
+    }
+    
+    let mut results = Vec::new();
+    
+    for file_path in searchable_files(path, file_extension_filter, recursive, skip_ignored_directories) {
+        let file_path = file_path?;
+        let contents = match fs::read_to_string(&file_path) {
+            Ok(contents) => contents,
+            // Skip files that can't be read (e.g., permission issues)
+            Err(_) => continue,
+        };
+        
+        let mut replacements = 0;
+        let mut preview = String::new();
+        let mut updated = String::with_capacity(contents.len());
+        
+        for (line_number, line) in contents.split_inclusive('\n').enumerate() {
+            let body = line.trim_end_matches(['\r', '\n']);
+            let ending = &line[body.len()..];
+            
+            let count = regex.find_iter(body).count();
+            if count == 0 {
+                updated.push_str(line);
+                continue;
+            }
+            
+            let replaced = regex.replace_all(body, replacement);
+            replacements += count;
+            
+            if dry_run {
+                preview.push_str(&format!("@@ line {} @@\n-{}\n+{}\n", line_number + 1, body, replaced));
+            }
+            
+            updated.push_str(&replaced);
+            updated.push_str(ending);
+        }
+        
+        if replacements == 0 {
+            continue;
+        }
+        
+        if !dry_run {
+            write_file_atomically(&file_path, &updated)?;
+        }
+        
+        results.push(ReplaceResult {
+            file_path: file_path.to_string_lossy().to_string(),
+            replacements,
+            preview: dry_run.then_some(preview),
+        });
+    }
+    
+    Ok(results)
+}
+
+/// Write through a sibling temp file and rename it over the target
+///
+/// The rename is atomic on the same filesystem, so the target is either left
+/// untouched or fully replaced. Existing permissions are kept, and symlinks
+/// are resolved so the link itself survives.
+fn write_file_atomically(path: &Path, contents: &str) -> Result<()> {
+    let is_symlink = fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_symlink())
+        .unwrap_or(false);
+    let target = if is_symlink {
+        fs::canonicalize(path)
+            .map_err(|e| anyhow!("Failed to resolve symlink {}: {}", path.display(), e))?
+    } else {
+        path.to_path_buf()
+    };
+    let path = target.as_path();
+    
+    let parent = path.parent()
+        .ok_or_else(|| anyhow!("Path has no parent directory: {}", path.display()))?;
+    let file_name = path.file_name()
+        .ok_or_else(|| anyhow!("Path has no file name: {}", path.display()))?;
+    let temp_path = parent.join(format!(".{}.{}.tmp", file_name.to_string_lossy(), uuid::Uuid::new_v4()));
+    
+    // Flush the contents to disk first, or a crash right after the rename
+    // can leave an empty file where the old one was
+    let result = fs::File::create(&temp_path)
+        .and_then(|mut file| {
+            file.write_all(contents.as_bytes())?;
+            file.sync_all()
+        })
+        .and_then(|_| match fs::metadata(path) {
+            // Keep the original file's permissions
+            Ok(metadata) => fs::set_permissions(&temp_path, metadata.permissions()),
+            Err(_) => Ok(()),
+        })
+        .and_then(|_| fs::rename(&temp_path, path));
+    
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(anyhow!("Failed to write {}: {}", path.display(), e));
+    }
+
+    // Persist the rename itself. Best effort: the new contents are already in place
+    #[cfg(unix)]
+    if let Ok(dir) = fs::File::open(parent) {
+        let _ = dir.sync_all();
+    }
+    
+    Ok(())
+}
+
+/// Read file contents as text
+pub fn read_file_contents(file_path: &str) -> Result<String> {
+    let path = Path::new(file_path);
+    
+    if !path.exists() {
+        return Err(anyhow!("File does not exist: {}", path.display()));
+    }
+    
+    if !path.is_file() {
+        return Err(anyhow!("Path is not a file: {}", path.display()));
+    }
+    
+    // Check if file is binary
+    if is_binary_file(path)? {
+        return Err(anyhow!("Cannot read binary file as text: {}", path.display()));
+    }
+    
+    fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read file: {}", e))
+}
+
+/// Largest file `read_structured_file` will parse
+const MAX_STRUCTURED_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Parse a CSV, TSV, JSON, TOML or YAML file into JSON, picking the format
+/// from the extension. CSV and TSV rows become objects keyed by the header
+/// row, with every field kept as a string.
+pub fn read_structured_file(file_path: &str) -> Result<serde_json::Value> {
+    let path = Path::new(file_path);
+    
+    if !path.is_file() {
+        return Err(anyhow!("File does not exist: {}", path.display()));
+    }
+    
+    let extension = path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if !matches!(extension.as_str(), "csv" | "tsv" | "json" | "toml" | "yaml" | "yml") {
+        return Err(anyhow!(
+            "Unsupported structured file format '{}'; expected csv, tsv, json, toml, yaml or yml",
+            extension
+        ));
+    }
+    
+    let size = fs::metadata(path)?.len();
+    if size > MAX_STRUCTURED_FILE_BYTES {
+        return Err(anyhow!(
+            "File is too large to parse ({} bytes, limit {} bytes): {}",
+            size, MAX_STRUCTURED_FILE_BYTES, path.display()
+        ));
+    }
+    
+    let contents = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read file: {}", e))?;
+    
+    let parsed = match extension.as_str() {
+        "csv" => parse_delimited(&contents, b','),
+        "tsv" => parse_delimited(&contents, b'\t'),
+        "json" => serde_json::from_str(&contents).map_err(anyhow::Error::from),
+        "toml" => contents.parse::<toml::Table>()
+            .map(|table| toml_to_json(toml::Value::Table(table)))
+            .map_err(anyhow::Error::from),
+        _ => serde_yaml::from_str(&contents).map_err(anyhow::Error::from),
+    };
+    
+    parsed.map_err(|e| anyhow!("Invalid {} in {}: {}", extension.to_uppercase(), path.display(), e))
+}
+
+fn parse_delimited(contents: &str, delimiter: u8) -> Result<serde_json::Value> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .from_reader(contents.as_bytes());
+    let headers = reader.headers()?.clone();
+    
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let row: serde_json::Map<String, serde_json::Value> = headers.iter()
+            .zip(record.iter())
+            .map(|(header, field)| (header.to_string(), serde_json::Value::String(field.to_string())))
+            .collect();
+        rows.push(serde_json::Value::Object(row));
+    }
+    
+    Ok(serde_json::Value::Array(rows))
+}
+
+/// Convert TOML to JSON, writing dates and times as their TOML text
+fn toml_to_json(value: toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(text) => serde_json::Value::String(text),
+        toml::Value::Integer(number) => serde_json::Value::from(number),
+        toml::Value::Float(number) => serde_json::Number::from_f64(number)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        toml::Value::Boolean(flag) => serde_json::Value::Bool(flag),
+        toml::Value::Datetime(datetime) => serde_json::Value::String(datetime.to_string()),
+        toml::Value::Array(items) => serde_json::Value::Array(items.into_iter().map(toml_to_json).collect()),
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table.into_iter().map(|(key, value)| (key, toml_to_json(value))).collect()
+        ),
+    }
+}
+
+/// Chunk size used when scanning a file backwards for `read_file_tail`
+const TAIL_CHUNK_BYTES: u64 = 8 * 1024;
+
+/// Return the last `lines` lines of a file without reading all of it
+pub fn read_file_tail(file_path: &str, lines: usize) -> Result<Vec<String>> {
+    use std::io::{Read, Seek, SeekFrom};
+    
+    let path = Path::new(file_path);
+    
+    if !path.is_file() {
+        return Err(anyhow!("File does not exist: {}", path.display()));
+    }
+    
+    if lines == 0 {
+        return Ok(Vec::new());
+    }
+    
+    let mut file = fs::File::open(path)
+        .map_err(|e| anyhow!("Failed to open file: {}", e))?;
+    let len = file.metadata()
+        .map_err(|e| anyhow!("Failed to read metadata: {}", e))?
+        .len();
+    
+    // Read chunks from the end until we have seen enough line breaks. A
+    // trailing newline terminates the last line rather than starting a new one.
+    let mut position = len;
+    let mut buffer: Vec<u8> = Vec::new();
+    
+    while position > 0 {
+        let chunk_len = TAIL_CHUNK_BYTES.min(position);
+        position -= chunk_len;
+        
+        let mut chunk = vec![0; chunk_len as usize];
+        file.seek(SeekFrom::Start(position))
+            .and_then(|_| file.read_exact(&mut chunk))
+            .map_err(|e| anyhow!("Failed to read file: {}", e))?;
+        chunk.extend_from_slice(&buffer);
+        buffer = chunk;
+        
+        let body = buffer.strip_suffix(b"\n").unwrap_or(&buffer);
+        if body.iter().filter(|&&b| b == b'\n').count() >= lines {
+            break;
+        }
+    }
+    
+    let text = String::from_utf8_lossy(&buffer);
+    let all_lines: Vec<&str> = text.lines().collect();
+    let start = all_lines.len().saturating_sub(lines);
+    
+    Ok(all_lines[start..].iter().map(|line| line.to_string()).collect())
+}
+
+/// How often `follow_file_lines` checks the file for new data
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Call `on_line` for each line appended to a file until `stop` is set
+///
+/// Starts at the current end of the file. If the file shrinks (truncation or
+/// rotation) reading restarts from the beginning; a missing file is waited for.
+pub async fn follow_file_lines(
+    file_path: &str,
+    stop: Arc<AtomicBool>,
+    mut on_line: impl FnMut(String),
+) -> Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+    
+    let path = Path::new(file_path);
+    let mut position = fs::metadata(path)
+        .map_err(|e| anyhow!("Failed to read metadata for {}: {}", path.display(), e))?
+        .len();
+    let mut pending = String::new();
+    
+    while !stop.load(Ordering::Relaxed) {
+        tokio::time::sleep(FOLLOW_POLL_INTERVAL).await;
+        
+        let Ok(metadata) = fs::metadata(path) else {
+            // Rotated away; wait for the file to reappear
+            position = 0;
+            pending.clear();
+            continue;
+        };
+        
+        if metadata.len() < position {
+            position = 0;
+            pending.clear();
+        }
+        
+        if metadata.len() == position {
+            continue;
+        }
+        
+        let mut file = fs::File::open(path)
+            .map_err(|e| anyhow!("Failed to open file: {}", e))?;
+        file.seek(SeekFrom::Start(position))
+            .map_err(|e| anyhow!("Failed to read file: {}", e))?;
+        
+        let mut appended = Vec::new();
+        let read = file.read_to_end(&mut appended)
+            .map_err(|e| anyhow!("Failed to read file: {}", e))?;
+        position += read as u64;
+        
+        pending.push_str(&String::from_utf8_lossy(&appended));
+        
+        // Only emit complete lines; keep a trailing partial line for next time
+        while let Some(newline) = pending.find('\n') {
+            let line: String = pending.drain(..=newline).collect();
+            on_line(line.trim_end_matches(['\r', '\n']).to_string());
+        }
+    }
+    
+    Ok(())
+}
+
+/// A change reported by `DirectoryWatch`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectoryChange {
+    /// The watched directory the change was seen under
+    pub watched_path: String,
+    /// `create`, `modify`, `remove` or `other`
+    pub kind: String,
+    pub paths: Vec<String>,
+}
+
+/// One OS filesystem watcher, shared by every owner (a chat, a panel) that
+/// asked to watch the same directory. It stops when dropped, which happens
+/// once the last owner lets go.
+pub struct DirectoryWatch {
+    _watcher: notify::RecommendedWatcher,
+    owners: HashSet<String>,
+}
+
+impl DirectoryWatch {
+    /// Start watching `path`. `on_change` runs on the watcher's thread.
+    pub fn start(
+        path: &Path,
+        recursive: bool,
+        on_change: impl Fn(Result<DirectoryChange>) + Send + 'static,
+    ) -> Result<Self> {
+        use notify::{EventKind, RecursiveMode, Watcher};
+
+        let watched_path = path.to_string_lossy().to_string();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let change = event
+                .map(|event| DirectoryChange {
+                    watched_path: watched_path.clone(),
+                    kind: match event.kind {
+                        EventKind::Create(_) => "create",
+                        EventKind::Modify(_) => "modify",
+                        EventKind::Remove(_) => "remove",
+                        _ => "other",
+                    }.to_string(),
+                    paths: event.paths.iter().map(|path| path.to_string_lossy().to_string()).collect(),
+                })
+                .map_err(|e| anyhow!("Directory watch failed: {}", e));
+            on_change(change);
+        })
+        .map_err(|e| anyhow!("Failed to create a directory watcher: {}", e))?;
+
+        let mode = if recursive { RecursiveMode::Recursive } else { RecursiveMode::NonRecursive };
+        watcher.watch(path, mode)
+            .map_err(|e| anyhow!("Failed to watch {}: {}", path.display(), e))?;
+
+        Ok(Self { _watcher: watcher, owners: HashSet::new() })
+    }
+}
+
+/// Key for a watched directory, so different spellings of one path share a watcher
+pub fn directory_watch_key(path: &str) -> Result<PathBuf> {
+    let canonical = fs::canonicalize(path)
+        .map_err(|e| anyhow!("Cannot watch {}: {}", path, e))?;
+    if !canonical.is_dir() {
+        return Err(anyhow!("Not a directory: {}", path));
+    }
+    Ok(canonical)
+}
+
+/// Add `owner_id` to the watch on `path`, starting the watcher if this is
+/// the first owner. The first watch decides whether it's recursive. Returns
+/// the key the watch is stored under.
+pub fn acquire_directory_watch(
+    watches: &mut HashMap<String, DirectoryWatch>,
+    path: &str,
+    owner_id: &str,
+    recursive: bool,
+    on_change: impl Fn(Result<DirectoryChange>) + Send + 'static,
+) -> Result<String> {
+    let key = directory_watch_key(path)?;
+    let key_string = key.to_string_lossy().to_string();
+
+    if !watches.contains_key(&key_string) {
+        let watch = DirectoryWatch::start(&key, recursive, on_change)?;
+        watches.insert(key_string.clone(), watch);
+    }
+    if let Some(watch) = watches.get_mut(&key_string) {
+        watch.owners.insert(owner_id.to_string());
+    }
+
+    Ok(key_string)
+}
+
+/// Drop `owner_id`'s hold on `path`, stopping the watcher if nobody else
+/// holds it. Returns whether the owner was watching it.
+pub fn release_directory_watch(
+    watches: &mut HashMap<String, DirectoryWatch>,
+    path: &str,
+    owner_id: &str,
+) -> bool {
+    // The directory may be gone by now, so fall back to the path as given
+    let key = directory_watch_key(path)
+        .map(|key| key.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string());
+
+    let Some(watch) = watches.get_mut(&key) else {
+        return false;
+    };
+    let released = watch.owners.remove(owner_id);
+    if watch.owners.is_empty() {
+        watches.remove(&key);
+    }
+    released
+}
+
+/// Drop every watch `owner_id` holds. Returns how many it released.
+pub fn release_owner_watches(
+    watches: &mut HashMap<String, DirectoryWatch>,
+    owner_id: &str,
+) -> usize {
+    let mut released = 0;
+    watches.retain(|_, watch| {
+        if watch.owners.remove(owner_id) {
+            released += 1;
+        }
+        !watch.owners.is_empty()
+    });
+    released
+}
+
+/// Write contents to a file
+pub fn write_file_contents(file_path: &str, contents: &str) -> Result<()> {
+    let path = Path::new(file_path);
+    
+    // Create parent directories if they don't exist
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("Failed to create parent directories: {}", e))?;
+    }
+    
+    if path.is_dir() {
+        return Err(anyhow!("Path is a directory: {}", path.display()));
+    }
+    
+    write_file_atomically(path, contents)
+}
+
+/// Files larger than this (old + new, 1 MB) are written without computing a diff
+const MAX_DIFF_INPUT_BYTES: usize = 1024 * 1024;
+
+/// Diffs longer than this (64 KB) are truncated
+const MAX_DIFF_BYTES: usize = 64 * 1024;
+
+/// Time budget for computing a diff; past it the diff is coarser but still correct
+const DIFF_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What `write_file_with_diff` changed on disk
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WriteReport {
+    pub path: String,
+    pub bytes_written: usize,
+    /// True when the file did not exist before
+    pub created: bool,
+    /// Unified diff against the previous contents; `None` for new, binary or oversized files
+    pub diff: Option<String>,
+    pub diff_truncated: bool,
+}
+
+/// Write a file like `write_file_contents`, reporting a unified diff when it overwrote text
+pub fn write_file_with_diff(file_path: &str, contents: &str) -> Result<WriteReport> {
+    let path = Path::new(file_path);
+    let created = !path.exists();
+    
+    // Only diff text files we can read; binary or unreadable files are still overwritten
+    let previous = if created { None } else { fs::read_to_string(path).ok() };
+    
+    write_file_contents(file_path, contents)?;
+    
+    let mut diff_truncated = false;
+    let diff = previous
+        .filter(|previous| previous.len() + contents.len() <= MAX_DIFF_INPUT_BYTES)
+        .map(|previous| {
+            let mut diff = similar::TextDiff::configure()
+                .timeout(DIFF_TIMEOUT)
+                .diff_lines(previous.as_str(), contents)
+                .unified_diff()
+                .context_radius(3)
+                .header(file_path, file_path)
+                .to_string();
+            
+            if diff.len() > MAX_DIFF_BYTES {
+                let mut cut = MAX_DIFF_BYTES;
+                while !diff.is_char_boundary(cut) {
+                    cut -= 1;
+                }
+                diff.truncate(cut);
+                diff.push_str("\n... diff truncated ...\n");
+                diff_truncated = true;
+            }
+            
+            diff
+        });
+    
+    Ok(WriteReport {
+        path: file_path.to_string(),
+        bytes_written: contents.len(),
+        created,
+        diff,
+        diff_truncated,
+    })
+}
+
+/// Create an empty file (and its parent directories) or bump its modified time, like `touch`
+pub fn touch_file(file_path: &str) -> Result<()> {
+    let path = Path::new(file_path);
+    
+    if path.is_dir() {
+        return Err(anyhow!("Path is a directory: {}", path.display()));
+    }
+    
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("Failed to create parent directories: {}", e))?;
+    }
+    
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| anyhow!("Failed to open file: {}", e))?;
+    
+    file.set_modified(std::time::SystemTime::now())
+        .map_err(|e| anyhow!("Failed to update modified time: {}", e))?;
+    
+    Ok(())
+}
+
+/// Largest image `read_image_as_data_url` will encode (20 MB)
+const MAX_IMAGE_BYTES: u64 = 20 * 1024 * 1024;
+
+/// Read an image file into a `data:` URL suitable for vision messages
+pub fn read_image_as_data_url(file_path: &str) -> Result<String> {
+    let path = Path::new(file_path);
+    
+    if !path.is_file() {
+        return Err(anyhow!("File does not exist: {}", path.display()));
+    }
+    
+    let metadata = fs::metadata(path)
+        .map_err(|e| anyhow!("Failed to read metadata: {}", e))?;
+    
+    if metadata.len() > MAX_IMAGE_BYTES {
+        return Err(anyhow!(
+            "Image is too large ({} bytes, max {} bytes): {}",
+            metadata.len(),
+            MAX_IMAGE_BYTES,
+            path.display()
+        ));
+    }
+    
+    let bytes = fs::read(path)
+        .map_err(|e| anyhow!("Failed to read file: {}", e))?;
+    
+    // Trust the file contents over the extension
+    let mime = sniff_image_mime(&bytes)
+        .ok_or_else(|| anyhow!("File is not a supported image (png, jpeg, gif, webp, bmp): {}", path.display()))?;
+    
+    use base64::Engine;
+    let encoded = base64::engine::general_purpose::STANDARD.encode(&bytes);
+    
+    Ok(format!("data:{};base64,{}", mime, encoded))
+}
+
+/// How much text `preview_file` returns (256 KB)
+const MAX_PREVIEW_TEXT_BYTES: usize = 256 * 1024;
+/// Largest image `preview_file` inlines (5 MB); bigger ones get a hex dump
+const MAX_PREVIEW_IMAGE_BYTES: u64 = 5 * 1024 * 1024;
+/// Bytes shown in a binary file's hex dump
+const PREVIEW_HEX_BYTES: usize = 256;
+
+/// What the file browser can show for a file
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum PreviewContent {
+    Text { content: String, truncated: bool },
+    Image { data_url: String },
+    /// `hex_dump` covers the first bytes in `xxd` style
+    Binary { hex_dump: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FilePreview {
+    pub info: FileInfo,
+    pub mime: Option<String>,
+    #[serde(flatten)]
+    pub content: PreviewContent,
+}
+
+/// Preview any file without knowing its type up front: text as text,
+/// images as a data URL, anything else as a hex dump
+pub fn preview_file(file_path: &str) -> Result<FilePreview> {
+    use std::io::Read;
     
-    if !path.exists() {
-        return Err(anyhow!("Path does not exist: {}", path.display()));
+    let path = Path::new(file_path);
+    
+    if !path.is_file() {
+        return Err(anyhow!("File does not exist: {}", path.display()));
     }
     
-    opener::open(path)
-        .map_err(|e| anyhow!("Failed to open file with default app: {}", e))?;
+    let info = create_file_info(path)?;
+    let size = info.size.unwrap_or_default();
     
-    Ok(())
+    // Read one byte past the cap to tell whether the text is cut off
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| file.take(MAX_PREVIEW_TEXT_BYTES as u64 + 1).read_to_end(&mut head))
+        .map_err(|e| anyhow!("Failed to read file: {}", e))?;
+    
+    let sniffed = sniff_image_mime(&head);
+    let mime = sniffed
+        .map(str::to_string)
+        .or_else(|| mime_guess::from_path(path).first().map(|mime| mime.to_string()));
+    
+    let content = if sniffed.is_some() && size <= MAX_PREVIEW_IMAGE_BYTES {
+        PreviewContent::Image { data_url: read_image_as_data_url(file_path)? }
+    } else if let Some((text, truncated)) = sniffed.is_none().then(|| decode_preview_text(&head)).flatten() {
+        PreviewContent::Text { content: text, truncated }
+    } else {
+        PreviewContent::Binary { hex_dump: hex_dump(&head[..head.len().min(PREVIEW_HEX_BYTES)]) }
+    };
+    
+    Ok(FilePreview { info, mime, content })
 }
 
-/// Read the contents of a directory and return file information
-pub fn read_directory_contents(directory_path: &str, recursive: bool) -> Result<DirectoryContents> {
-    let path = Path::new(directory_path);
+/// Decode the start of a file as UTF-8 text, or `None` if it looks binary
+fn decode_preview_text(head: &[u8]) -> Option<(String, bool)> {
+    let truncated = head.len() > MAX_PREVIEW_TEXT_BYTES;
+    let head = &head[..head.len().min(MAX_PREVIEW_TEXT_BYTES)];
     
-    if !path.exists() {
-        return Err(anyhow!("Directory does not exist: {}", path.display()));
+    if head.contains(&0) {
+        return None;
     }
     
-    if !path.is_dir() {
-        return Err(anyhow!("Path is not a directory: {}", path.display()));
+    match std::str::from_utf8(head) {
+        Ok(text) => Some((text.to_string(), truncated)),
+        // The cap may split a character; anything else is not UTF-8
+        Err(e) if truncated && e.error_len().is_none() => {
+            Some((String::from_utf8_lossy(&head[..e.valid_up_to()]).to_string(), true))
+        }
+        Err(_) => None,
     }
-    
-    let mut files = Vec::new();
-    let mut directories = Vec::new();
-    
-    if recursive {
-        for entry in WalkDir::new(path)
-            .follow_links(false)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            if entry.path() == path {
-                continue; // Skip the root directory itself
-            }
-            
-            let file_info = create_file_info(entry.path())?;
-            
-            if file_info.is_directory {
-                directories.push(file_info);
-            } else {
-                files.push(file_info);
-            }
+}
+
+/// Format bytes as offset, hex and ASCII columns, 16 per line
+fn hex_dump(bytes: &[u8]) -> String {
+    bytes
+        .chunks(16)
+        .enumerate()
+        .map(|(line, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let ascii: String = chunk
+                .iter()
+                .map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' })
+                .collect();
+            format!("{:08x}  {:<47}  |{}|", line * 16, hex.join(" "), ascii)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Largest audio payload accepted for transcription (the OpenAI limit is 25 MB)
+const MAX_AUDIO_BYTES: u64 = 25 * 1024 * 1024;
+
+/// Audio formats accepted by speech-to-text endpoints, keyed by extension
+const AUDIO_FORMATS: &[(&str, &str)] = &[
+    ("flac", "audio/flac"),
+    ("m4a", "audio/mp4"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "audio/mp4"),
+    ("mpeg", "audio/mpeg"),
+    ("mpga", "audio/mpeg"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("webm", "audio/webm"),
+];
+
+/// An audio clip ready to upload for transcription
+#[derive(Debug)]
+pub struct AudioInput {
+    pub file_name: String,
+    pub mime: String,
+    pub bytes: Vec<u8>,
+}
+
+/// Load audio from a file path or a `data:audio/...;base64,` URL, validating size and format
+pub fn load_audio_input(path_or_data: &str) -> Result<AudioInput> {
+    let input = path_or_data.trim();
+
+    if let Some(rest) = input.strip_prefix("data:") {
+        let (header, payload) = rest.split_once(',')
+            .ok_or_else(|| anyhow!("Malformed data URL"))?;
+        let mime = header.strip_suffix(";base64")
+            .ok_or_else(|| anyhow!("Audio data URLs must be base64 encoded"))?;
+        // Drop parameters such as `;codecs=opus`
+        let mime = mime.split(';').next().unwrap_or_default();
+
+        let extension = AUDIO_FORMATS.iter()
+            .find(|(_, known)| *known == mime)
+            .map(|(ext, _)| *ext)
+            .or_else(|| mime.strip_prefix("audio/").filter(|sub| AUDIO_FORMATS.iter().any(|(ext, _)| ext == sub)))
+            .ok_or_else(|| anyhow!("Unsupported audio format: {}", mime))?;
+
+        // base64 expands by 4/3, so reject oversized payloads before decoding
+        if payload.len() as u64 > MAX_AUDIO_BYTES / 3 * 4 + 4 {
+            return Err(anyhow!("Audio is too large (max {} bytes)", MAX_AUDIO_BYTES));
         }
-    } else {
-        let entries = fs::read_dir(path)
-            .map_err(|e| anyhow!("Failed to read directory: {}", e))?;
-        
-        for entry in entries {
-            let entry = entry.map_err(|e| anyhow!("Failed to read directory entry: {}", e))?;
-            let file_info = create_file_info(&entry.path())?;
-            
-            if file_info.is_directory {
-                directories.push(file_info);
-            } else {
-                files.push(file_info);
-            }
+
+        use base64::Engine;
+        let bytes = base64::engine::general_purpose::STANDARD.decode(payload)
+            .map_err(|e| anyhow!("Invalid base64 audio data: {}", e))?;
+
+        if bytes.len() as u64 > MAX_AUDIO_BYTES {
+            return Err(anyhow!("Audio is too large ({} bytes, max {} bytes)", bytes.len(), MAX_AUDIO_BYTES));
         }
+
+        return Ok(AudioInput {
+            file_name: format!("audio.{}", extension),
+            mime: mime.to_string(),
+            bytes,
+        });
     }
-    
-    // Sort files and directories by name
-    files.sort_by(|a, b| a.name.cmp(&b.name));
-    directories.sort_by(|a, b| a.name.cmp(&b.name));
-    
-    Ok(DirectoryContents {
-        total_files: files.len(),
-        total_directories: directories.len(),
-        files,
-        directories,
+
+    let path = Path::new(input);
+
+    if !path.is_file() {
+        return Err(anyhow!("File does not exist: {}", path.display()));
+    }
+
+    let extension = path.extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    let mime = AUDIO_FORMATS.iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, mime)| *mime)
+        .ok_or_else(|| anyhow!(
+            "Unsupported audio format '{}'; expected one of: {}",
+            extension,
+            AUDIO_FORMATS.iter().map(|(ext, _)| *ext).collect::<Vec<_>>().join(", ")
+        ))?;
+
+    let metadata = fs::metadata(path)
+        .map_err(|e| anyhow!("Failed to read metadata: {}", e))?;
+
+    if metadata.len() > MAX_AUDIO_BYTES {
+        return Err(anyhow!(
+            "Audio is too large ({} bytes, max {} bytes): {}",
+            metadata.len(),
+            MAX_AUDIO_BYTES,
+            path.display()
+        ));
+    }
+
+    let bytes = fs::read(path)
+        .map_err(|e| anyhow!("Failed to read file: {}", e))?;
+
+    let file_name = path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("audio.{}", extension));
+
+    Ok(AudioInput {
+        file_name,
+        mime: mime.to_string(),
+        bytes,
     })
 }
 
-/// Search for text patterns in files using regex
-pub fn search_in_files(
-    directory_path: &str,
-    pattern: &str,
-    file_extension_filter: Option<&str>,
-    case_sensitive: bool,
-    recursive: bool,
-    max_results: Option<usize>,
-) -> Result<Vec<SearchResult>> {
-    let path = Path::new(directory_path);
+/// Detect common image formats from their magic bytes
+fn sniff_image_mime(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        Some("image/jpeg")
+    } else if bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else if bytes.starts_with(b"BM") {
+        Some("image/bmp")
+    } else {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArchiveFormat {
+    Zip,
+    TarGz,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveSummary {
+    pub archive: String,
+    pub files: usize,
+    pub total_bytes: u64,
+}
+
+/// Pack files and directories into a new zip or tar.gz archive.
+/// Directories are stored under their own name; symlinks are skipped.
+pub fn create_archive(paths: &[String], destination: &str, format: ArchiveFormat) -> Result<ArchiveSummary> {
+    if paths.is_empty() {
+        return Err(anyhow!("No paths to archive"));
+    }
     
-    if !path.exists() || !path.is_dir() {
-        return Err(anyhow!("Invalid directory path: {}", path.display()));
+    let destination_path = Path::new(destination);
+    if destination_path.exists() {
+        return Err(anyhow!("Destination already exists: {}", destination_path.display()));
     }
     
-    // Compile regex pattern
-    let regex = if case_sensitive {
-        regex::RegexBuilder::new(pattern).build()
-    } else {
-        regex::RegexBuilder::new(pattern).case_insensitive(true).build()
+    // Collect (path on disk, name in archive) pairs up front so a bad input fails before writing
+    let mut entries: Vec<(PathBuf, String, bool)> = Vec::new();
+    for path in paths {
+        let root = Path::new(path);
+        if !root.exists() {
+            return Err(anyhow!("Path does not exist: {}", root.display()));
+        }
+        let base = root.parent().unwrap_or_else(|| Path::new(""));
+        
+        for entry in WalkDir::new(root).follow_links(false).into_iter().filter_map(|e| e.ok()) {
+            if entry.file_type().is_symlink() {
+                continue;
+            }
+            let name = entry.path()
+                .strip_prefix(base)
+                .unwrap_or(entry.path())
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            entries.push((entry.path().to_path_buf(), name, entry.file_type().is_dir()));
+        }
     }
-    .map_err(|e| anyhow!("Invalid regex pattern: {}", e))?;
     
-    let mut results = Vec::new();
-    let mut result_count = 0;
+    if let Some(parent) = destination_path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| anyhow!("Failed to create parent directories: {}", e))?;
+    }
     
-    let walker = if recursive {
-        WalkDir::new(path).follow_links(false)
-    } else {
-        WalkDir::new(path).max_depth(1).follow_links(false)
-    };
+    let file = fs::File::create(destination_path)
+        .map_err(|e| anyhow!("Failed to create archive: {}", e))?;
     
-    for entry in walker.into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            let file_path = entry.path();
-            
-            // Apply file extension filter if specified
-            if let Some(ext_filter) = file_extension_filter {
-                if let Some(extension) = file_path.extension() {
-                    if extension.to_string_lossy().to_lowercase() != ext_filter.to_lowercase() {
-                        continue;
+    let mut files = 0;
+    let mut total_bytes = 0;
+    
+    let result = (|| -> Result<()> {
+        match format {
+            ArchiveFormat::Zip => {
+                let mut writer = zip::ZipWriter::new(file);
+                let options = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated)
+                    .large_file(true);
+                
+                for (path, name, is_dir) in &entries {
+                    if *is_dir {
+                        writer.add_directory(name.as_str(), options)?;
+                    } else {
+                        writer.start_file(name.as_str(), options)?;
+                        let mut source = fs::File::open(path)?;
+                        total_bytes += std::io::copy(&mut source, &mut writer)?;
+                        files += 1;
                     }
-                } else {
-                    continue;
                 }
+                
+                writer.finish()?;
             }
-            
-            // Skip binary files
-            if is_binary_file(file_path)? {
-                continue;
-            }
-            
-            match search_in_file(file_path, &regex) {
-                Ok(mut file_results) => {
-                    for _result in &mut file_results {
-                        result_count += 1;
-                        if let Some(max) = max_results {
-                            if result_count > max {
-                                return Ok(results);
-                            }
-                        }
+            ArchiveFormat::TarGz => {
+                let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                let mut builder = tar::Builder::new(encoder);
+                builder.follow_symlinks(false);
+                
+                for (path, name, is_dir) in &entries {
+                    if *is_dir {
+                        builder.append_dir(name, path)?;
+                    } else {
+                        builder.append_path_with_name(path, name)?;
+                        total_bytes += fs::metadata(path)?.len();
+                        files += 1;
                     }
-                    results.extend(file_results);
-                }
-                Err(_) => {
-                    // Skip files that can't be read (e.g., permission issues)
-                    continue;
                 }
+                
+                builder.into_inner()?.finish()?;
             }
         }
+        Ok(())
+    })();
+    
+    if let Err(e) = result {
+        let _ = fs::remove_file(destination_path);
+        return Err(anyhow!("Failed to write archive {}: {}", destination_path.display(), e));
     }
     
-    Ok(results)
+    Ok(ArchiveSummary {
+        archive: destination.to_string(),
+        files,
+        total_bytes,
+    })
 }
 
-/// Read file contents as text
-pub fn read_file_contents(file_path: &str) -> Result<String> {
-    let path = Path::new(file_path);
-    
-    if !path.exists() {
-        return Err(anyhow!("File does not exist: {}", path.display()));
+/// Unpack a zip or tar.gz archive into `destination`.
+///
+/// Entries whose paths would land outside `destination` (absolute paths or
+/// `..` segments, a.k.a. zip-slip) abort the extraction. Links in tar
+/// archives are skipped.
+pub fn extract_archive(archive: &str, destination: &str) -> Result<ArchiveSummary> {
+    let archive_path = Path::new(archive);
+    if !archive_path.is_file() {
+        return Err(anyhow!("Archive does not exist: {}", archive_path.display()));
     }
     
-    if !path.is_file() {
-        return Err(anyhow!("Path is not a file: {}", path.display()));
-    }
+    let destination_path = Path::new(destination);
+    fs::create_dir_all(destination_path)
+        .map_err(|e| anyhow!("Failed to create destination: {}", e))?;
     
-    // Check if file is binary
-    if is_binary_file(path)? {
-        return Err(anyhow!("Cannot read binary file as text: {}", path.display()));
-    }
+    let mut file = fs::File::open(archive_path)
+        .map_err(|e| anyhow!("Failed to open archive: {}", e))?;
     
-    fs::read_to_string(path)
-        .map_err(|e| anyhow!("Failed to read file: {}", e))
-}
-
-/// Write contents to a file
-pub fn write_file_contents(file_path: &str, contents: &str) -> Result<()> {
-    let path = Path::new(file_path);
+    // Detect the format from the magic bytes rather than the extension
+    let mut magic = [0u8; 4];
+    let magic_len = {
+        use std::io::{Read, Seek};
+        let read = file.read(&mut magic)?;
+        file.rewind()?;
+        read
+    };
     
-    // Create parent directories if they don't exist
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)
-            .map_err(|e| anyhow!("Failed to create parent directories: {}", e))?;
-    }
+    let mut files = 0;
+    let mut total_bytes = 0;
     
-    fs::write(path, contents)
-        .map_err(|e| anyhow!("Failed to write file: {}", e))?;
+    if magic_len >= 4 && magic == *b"PK\x03\x04" {
+        let mut zip = zip::ZipArchive::new(file)
+            .map_err(|e| anyhow!("Invalid zip archive: {}", e))?;
+        
+        // Validate every name before writing anything
+        let mut targets = Vec::with_capacity(zip.len());
+        for index in 0..zip.len() {
+            let entry = zip.by_index(index)?;
+            let name = entry.enclosed_name()
+                .ok_or_else(|| anyhow!("Archive entry escapes the destination: {}", entry.name()))?;
+            targets.push(destination_path.join(name));
+        }
+        
+        for (index, target) in targets.into_iter().enumerate() {
+            let mut entry = zip.by_index(index)?;
+            
+            if entry.is_dir() {
+                fs::create_dir_all(&target)?;
+                continue;
+            }
+            
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = fs::File::create(&target)
+                .map_err(|e| anyhow!("Failed to create {}: {}", target.display(), e))?;
+            total_bytes += std::io::copy(&mut entry, &mut output)?;
+            files += 1;
+        }
+    } else if magic_len >= 2 && magic[..2] == [0x1f, 0x8b] {
+        let decoder = flate2::read::GzDecoder::new(file);
+        let mut tar = tar::Archive::new(decoder);
+        
+        for entry in tar.entries().map_err(|e| anyhow!("Invalid tar archive: {}", e))? {
+            let mut entry = entry.map_err(|e| anyhow!("Invalid tar archive: {}", e))?;
+            let entry_path = entry.path()?.to_path_buf();
+            
+            let escapes = entry_path.components().any(|component| !matches!(
+                component,
+                std::path::Component::Normal(_) | std::path::Component::CurDir
+            ));
+            if escapes {
+                return Err(anyhow!("Archive entry escapes the destination: {}", entry_path.display()));
+            }
+            
+            let entry_type = entry.header().entry_type();
+            if entry_type.is_symlink() || entry_type.is_hard_link() {
+                continue;
+            }
+            
+            let size = entry.header().size()?;
+            // `unpack_in` re-checks that the target stays inside the destination
+            if !entry.unpack_in(destination_path)? {
+                return Err(anyhow!("Archive entry escapes the destination: {}", entry_path.display()));
+            }
+            
+            if entry_type.is_file() {
+                total_bytes += size;
+                files += 1;
+            }
+        }
+    } else {
+        return Err(anyhow!("Unsupported archive format (expected zip or tar.gz): {}", archive_path.display()));
+    }
     
-    Ok(())
+    Ok(ArchiveSummary {
+        archive: archive.to_string(),
+        files,
+        total_bytes,
+    })
 }
 
 /// Get file or directory information
@@ -270,7 +1904,8 @@ fn create_file_info(path: &Path) -> Result<FileInfo> {
 }
 
 /// Search for pattern in a single file
-fn search_in_file(file_path: &Path, regex: &Regex) -> Result<Vec<SearchResult>> {
+fn search_in_file(file_path: &Path, matcher: &SearchMatcher, timeout: Option<Duration>) -> Result<Vec<SearchResult>> {
+    let started = Instant::now();
     let contents = fs::read_to_string(file_path)
         .map_err(|e| anyhow!("Failed to read file {}: {}", file_path.display(), e))?;
     
@@ -278,20 +1913,56 @@ fn search_in_file(file_path: &Path, regex: &Regex) -> Result<Vec<SearchResult>>
     let file_path_str = file_path.to_string_lossy().to_string();
     
     for (line_number, line) in contents.lines().enumerate() {
-        for mat in regex.find_iter(line) {
+        check_search_timeout(file_path, line, started, timeout)?;
+        
+        matcher.for_each_match(line, |match_start, match_end, pattern| {
             results.push(SearchResult {
                 file_path: file_path_str.clone(),
                 line_number: line_number + 1,
                 line_content: line.to_string(),
-                match_start: mat.start(),
-                match_end: mat.end(),
+                match_start,
+                match_end,
+                matched_pattern: pattern.to_string(),
             });
-        }
+        });
     }
     
     Ok(results)
 }
 
+/// Fail a timed search of `file_path` once `timeout` has passed, or before
+/// `line` if it is too long to scan without overrunning the timeout
+fn check_search_timeout(file_path: &Path, line: &str, started: Instant, timeout: Option<Duration>) -> Result<()> {
+    let Some(limit) = timeout else {
+        return Ok(());
+    };
+    
+    if started.elapsed() > limit {
+        return Err(anyhow!("Timed out searching {}", file_path.display()));
+    }
+    if line.len() > MAX_TIMED_LINE_BYTES {
+        return Err(anyhow!("Timed out searching {}: a line is longer than {} bytes", file_path.display(), MAX_TIMED_LINE_BYTES));
+    }
+    Ok(())
+}
+
+/// Count pattern matches in a single file
+fn count_in_file(file_path: &Path, matcher: &SearchMatcher, timeout: Option<Duration>) -> Result<usize> {
+    let started = Instant::now();
+    let contents = fs::read_to_string(file_path)
+        .map_err(|e| anyhow!("Failed to read file {}: {}", file_path.display(), e))?;
+    
+    let mut count = 0;
+    
+    for line in contents.lines() {
+        check_search_timeout(file_path, line, started, timeout)?;
+        
+        count += matcher.count(line);
+    }
+    
+    Ok(count)
+}
+
 /// Check if a file is binary
 fn is_binary_file(path: &Path) -> Result<bool> {
     // First check by extension
@@ -332,3 +2003,98 @@ fn is_binary_file(path: &Path) -> Result<bool> {
     // Check for null bytes (common in binary files)
     Ok(buffer[..bytes_read].contains(&0))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory under the system temp dir, removed on drop
+    struct ScratchDir(PathBuf);
+
+    impl ScratchDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("chatme-test-{}", uuid::Uuid::new_v4()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for ScratchDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn failed_atomic_write_leaves_the_original_intact() {
+        let scratch = ScratchDir::new();
+        // Short enough to exist, but the temp file's longer name is rejected
+        let path = scratch.0.join("a".repeat(240));
+        fs::write(&path, "original").unwrap();
+
+        assert!(write_file_atomically(&path, "replacement").is_err());
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "original");
+        assert_eq!(fs::read_dir(&scratch.0).unwrap().count(), 1, "the temp file was left behind");
+    }
+
+    #[test]
+    fn worker_pool_is_reused_until_resized() {
+        let first = worker_pool().unwrap();
+        assert!(Arc::ptr_eq(&first, &worker_pool().unwrap()));
+
+        let threads = MAX_THREADS.load(Ordering::Relaxed);
+        set_max_threads(threads + 1);
+        let resized = worker_pool().unwrap();
+        set_max_threads(threads);
+
+        assert!(!Arc::ptr_eq(&first, &resized));
+        assert_eq!(resized.current_num_threads(), threads + 1);
+    }
+
+    #[test]
+    fn search_stops_at_max_results_and_on_cancel() {
+        let scratch = ScratchDir::new();
+        for index in 0..(SEARCH_BATCH_FILES * 2) {
+            fs::write(scratch.0.join(format!("{index}.txt")), "needle\n").unwrap();
+        }
+        let directory = scratch.0.to_string_lossy();
+        let matcher = SearchMatcher::new(&["needle".to_string()], SearchOptions::default()).unwrap();
+
+        let limited = search_in_files(&directory, &matcher, None, true, false, Some(3), None, None).unwrap();
+        assert_eq!(limited.len(), 3);
+
+        let cancel = AtomicBool::new(true);
+        let cancelled = search_in_files(&directory, &matcher, None, true, false, None, None, Some(&cancel)).unwrap_err();
+        assert!(cancelled.downcast_ref::<Cancelled>().is_some(), "{}", cancelled);
+    }
+
+    #[test]
+    fn ignored_directories_are_only_skipped_on_request() {
+        let scratch = ScratchDir::new();
+        fs::create_dir_all(scratch.0.join("node_modules")).unwrap();
+        fs::write(scratch.0.join("node_modules").join("dep.js"), "needle").unwrap();
+        fs::write(scratch.0.join("main.js"), "needle").unwrap();
+        let directory = scratch.0.to_string_lossy();
+        let matcher = SearchMatcher::new(&["needle".to_string()], SearchOptions::default()).unwrap();
+
+        let everything = search_in_files(&directory, &matcher, None, true, false, None, None, None).unwrap();
+        let skipped = search_in_files(&directory, &matcher, None, true, true, None, None, None).unwrap();
+
+        assert_eq!(everything.len(), 2);
+        assert_eq!(skipped.len(), 1);
+        assert!(skipped[0].file_path.ends_with("main.js"));
+    }
+
+    #[test]
+    fn timed_search_skips_files_with_overlong_lines() {
+        let scratch = ScratchDir::new();
+        let minified = scratch.0.join("bundle.min.js");
+        fs::write(&minified, format!("needle\n{}needle", "x".repeat(MAX_TIMED_LINE_BYTES))).unwrap();
+        let matcher = SearchMatcher::new(&["needle".to_string()], SearchOptions::default()).unwrap();
+
+        assert_eq!(search_in_file(&minified, &matcher, None).unwrap().len(), 2);
+        assert!(search_in_file(&minified, &matcher, Some(Duration::from_secs(60))).is_err());
+        assert!(count_in_file(&minified, &matcher, Some(Duration::from_secs(60))).is_err());
+    }
+}
@@ -4,62 +4,188 @@ mod models;
 mod file_operations;
 mod agentic;
 mod system_operations;
+mod model_capabilities;
 
 use database::Database;
 use std::collections::HashMap;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use agentic::AgentSession;
+use system_operations::ShellSession;
+use file_operations::DirectoryWatch;
+use tauri::Manager;
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    tauri::async_runtime::block_on(async {
-        let db = Database::new().await.expect("Failed to initialize database");
-        let agent_sessions: Mutex<HashMap<String, AgentSession>> = Mutex::new(HashMap::new());
+    let start_time = commands::StartTime(std::time::Instant::now());
+    let db = tauri::async_runtime::block_on(Database::new()).expect("Failed to initialize database");
+    let agent_sessions: Mutex<HashMap<String, AgentSession>> = Mutex::new(HashMap::new());
+    // Stop flags for `follow_file` watchers, keyed by path
+    let file_followers: Mutex<HashMap<String, Arc<AtomicBool>>> = Mutex::new(HashMap::new());
+    let shell_sessions: Mutex<HashMap<String, ShellSession>> = Mutex::new(HashMap::new());
+    // `watch_directory` watchers, keyed by canonical path, with the owners holding each
+    let directory_watches: Mutex<HashMap<String, DirectoryWatch>> = Mutex::new(HashMap::new());
 
-        tauri::Builder::default()
-            .plugin(tauri_plugin_opener::init())
-            .manage(db)
-            .manage(agent_sessions)
-            .invoke_handler(tauri::generate_handler![
-                commands::create_chat,
-                commands::get_chats,
-                commands::get_chat,
-                commands::update_chat,
-                commands::delete_chat,
-                commands::create_message,
-                commands::get_messages,
-                commands::delete_message,
-                commands::create_api_config,
-                commands::get_api_configs,
-                commands::get_api_config,
-                commands::get_default_api_config,
-                commands::update_api_config,
-                commands::delete_api_config,
-                commands::send_ai_message,
-                commands::send_ai_message_streaming,
-                // File operations
-                commands::open_file_with_default_app,
-                commands::read_directory,
-                commands::search_files,
-                commands::read_file,
-                commands::write_file,
-                commands::get_current_directory,
-                // Agentic mode
-                commands::create_agent_session,
-                commands::get_agent_capabilities,
-                commands::execute_agent_action,
-                commands::get_agent_session,
-                commands::create_or_get_agent_session,
-                // System operations with permissions
-                commands::request_permission,
-                commands::launch_app,
-                commands::get_installed_apps,
-                commands::execute_command,
-                commands::perform_file_system_operation,
-                commands::get_processes,
-                commands::terminate_process,
-            ])
-            .run(tauri::generate_context!())
-            .expect("error while running tauri application");
+    let app = tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .manage(db)
+        .manage(agent_sessions)
+        .manage(file_followers)
+        .manage(shell_sessions)
+        .manage(directory_watches)
+        .manage(commands::ActiveSearches::default())
+        .manage(start_time)
+        .invoke_handler(tauri::generate_handler![
+            commands::create_chat,
+            commands::get_chats,
+            commands::get_recent_chats,
+            commands::get_chat,
+            commands::update_chat,
+            commands::reorder_chats,
+            commands::delete_chat,
+            commands::set_chat_pinned,
+            commands::delete_chats_older_than,
+            commands::backfill_last_messages,
+            commands::check_database_integrity,
+            commands::fork_with_summary,
+            commands::export_chat,
+            commands::export_all_chats,
+            commands::global_search,
+            commands::create_message,
+            commands::create_messages,
+            commands::get_messages,
+            commands::get_message_metrics,
+            commands::format_message,
+            commands::save_code_block,
+            commands::get_message,
+            commands::delete_message,
+            commands::delete_messages,
+            commands::clear_chat_messages,
+            commands::copy_message_to_chat,
+            commands::set_message_role,
+            commands::deduplicate_chat,
+            commands::compact_chat,
+            commands::get_usage_summary,
+            commands::flag_message,
+            commands::unflag_message,
+            commands::get_flagged_messages,
+            commands::create_api_config,
+            commands::get_api_configs,
+            commands::get_api_config,
+            commands::get_default_api_config,
+            commands::update_api_config,
+            commands::set_default_api_config,
+            commands::delete_api_config,
+            commands::get_app_settings,
+            commands::update_app_settings,
+            commands::get_preference,
+            commands::set_preference,
+            commands::get_all_preferences,
+            commands::get_slash_commands,
+            commands::create_prompt_template,
+            commands::get_prompt_templates,
+            commands::delete_prompt_template,
+            commands::render_template,
+            commands::export_api_configs,
+            commands::import_api_configs,
+            commands::send_ai_message,
+            commands::retry_message,
+            commands::get_last_api_error,
+            commands::regenerate_with_config,
+            commands::continue_response,
+            commands::model_supports_vision,
+            commands::get_provider_metadata,
+            commands::check_context_fit,
+            // File operations
+            commands::open_file_with_default_app,
+            commands::open_url,
+            commands::read_directory,
+            commands::search_files,
+            commands::search_files_streaming,
+            commands::cancel_search,
+            commands::replace_in_files,
+            commands::find_duplicate_files,
+            commands::read_file,
+            commands::read_file_tail,
+            commands::read_structured_file,
+            commands::get_file_tree,
+            commands::preview_file,
+            commands::follow_file,
+            commands::unfollow_file,
+            commands::watch_directory,
+            commands::unwatch_directory,
+            commands::unwatch_all,
+            commands::read_image_as_data_url,
+            commands::write_file,
+            commands::touch_file,
+            commands::create_archive,
+            commands::extract_archive,
+            commands::get_current_directory,
+            // Agentic mode
+            commands::create_agent_session,
+            commands::get_agent_capabilities,
+            commands::get_capabilities_as_tools,
+            commands::execute_agent_action,
+            commands::execute_agent_actions,
+            commands::cancel_agent_action,
+            commands::set_agent_interpreters,
+            commands::get_agent_session,
+            commands::get_agent_cwd,
+            commands::create_or_get_agent_session,
+            commands::list_agent_sessions,
+            commands::close_agent_session,
+            commands::get_agent_actions,
+            commands::get_all_agent_actions,
+            commands::clear_agent_actions,
+            // System operations with permissions
+            commands::request_permission,
+            commands::launch_app,
+            commands::get_installed_apps,
+            commands::execute_command,
+            commands::detect_default_shell,
+            commands::perform_file_system_operation,
+            commands::get_operation_log,
+            commands::open_shell_session,
+            commands::shell_send,
+            commands::close_shell_session,
+            commands::get_processes,
+            commands::terminate_process,
+            commands::get_environment,
+            commands::ping,
+            commands::get_version_info,
+            commands::abort_all,
+            commands::which,
+            commands::read_clipboard,
+            commands::write_clipboard,
+            commands::list_displays,
+            commands::capture_screenshot,
+            commands::transcribe_audio,
+        ])
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application");
+
+    app.run(|app_handle, event| {
+        if let tauri::RunEvent::Exit = event {
+            // Stop watchers and shells before the database goes away
+            if let Ok(mut followers) = app_handle.state::<Mutex<HashMap<String, Arc<AtomicBool>>>>().lock() {
+                for stop in followers.values() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                followers.clear();
+            }
+            if let Ok(mut shells) = app_handle.state::<Mutex<HashMap<String, ShellSession>>>().lock() {
+                shells.clear();
+            }
+            if let Ok(mut watches) = app_handle.state::<Mutex<HashMap<String, DirectoryWatch>>>().lock() {
+                watches.clear();
+            }
+            if let Ok(searches) = app_handle.state::<commands::ActiveSearches>().0.lock() {
+                for cancel in searches.values() {
+                    cancel.store(true, Ordering::Relaxed);
+                }
+            }
+
+            tauri::async_runtime::block_on(app_handle.state::<Database>().close());
+        }
     });
 }
@@ -0,0 +1,139 @@
+// Known limits of hosted models, matched by model-name prefix
+
+// Maximum output tokens per request. Longer prefixes win, so `gpt-4o` is
+// not mistaken for `gpt-4`.
+const MAX_OUTPUT_TOKENS: &[(&str, i32)] = &[
+    // OpenAI
+    ("gpt-3.5-turbo", 4096),
+    ("gpt-4", 8192),
+    ("gpt-4-turbo", 4096),
+    ("gpt-4o", 16384),
+    ("gpt-4o-mini", 16384),
+    ("gpt-4.1", 32768),
+    ("gpt-5", 128000),
+    ("o1", 100000),
+    ("o1-mini", 65536),
+    ("o3", 100000),
+    ("o3-mini", 100000),
+    ("o4-mini", 100000),
+    // Anthropic
+    ("claude-3-haiku", 4096),
+    ("claude-3-sonnet", 4096),
+    ("claude-3-opus", 4096),
+    ("claude-3-5-haiku", 8192),
+    ("claude-3-5-sonnet", 8192),
+    ("claude-3-7-sonnet", 64000),
+    ("claude-sonnet-4", 64000),
+    ("claude-opus-4", 32000),
+    // Google
+    ("gemini-1.5", 8192),
+    ("gemini-2.0", 8192),
+    ("gemini-2.5", 65536),
+    // DeepSeek
+    ("deepseek-chat", 8192),
+    ("deepseek-reasoner", 65536),
+];
+
+// Context window (prompt plus reply) in tokens
+const CONTEXT_WINDOW_TOKENS: &[(&str, i32)] = &[
+    // OpenAI
+    ("gpt-3.5-turbo", 16385),
+    ("gpt-4", 8192),
+    ("gpt-4-turbo", 128000),
+    ("gpt-4o", 128000),
+    ("gpt-4.1", 1047576),
+    ("gpt-5", 400000),
+    ("o1", 200000),
+    ("o1-mini", 128000),
+    ("o3", 200000),
+    ("o4-mini", 200000),
+    // Anthropic
+    ("claude-2", 100000),
+    ("claude-3", 200000),
+    ("claude-sonnet-4", 200000),
+    ("claude-opus-4", 200000),
+    // Google
+    ("gemini-pro", 32760),
+    ("gemini-1.0-pro", 32760),
+    ("gemini-1.5", 1048576),
+    ("gemini-2.0", 1048576),
+    ("gemini-2.5", 1048576),
+    // DeepSeek
+    ("deepseek-chat", 65536),
+    ("deepseek-reasoner", 65536),
+];
+
+// Whether a model accepts image input. Entries marked `false` override a
+// shorter `true` prefix (e.g. `o1-mini` under `o1`).
+const VISION_SUPPORT: &[(&str, bool)] = &[
+    // OpenAI
+    ("gpt-3.5-turbo", false),
+    ("gpt-4", false),
+    ("gpt-4-turbo", true),
+    ("gpt-4-vision", true),
+    ("gpt-4o", true),
+    ("gpt-4.1", true),
+    ("gpt-5", true),
+    ("o1", true),
+    ("o1-mini", false),
+    ("o3", true),
+    ("o3-mini", false),
+    ("o4-mini", true),
+    // Anthropic
+    ("claude-2", false),
+    ("claude-instant", false),
+    ("claude-3", true),
+    ("claude-sonnet-4", true),
+    ("claude-opus-4", true),
+    // Google
+    ("gemini", true),
+    ("gemini-pro", false),
+    ("gemini-1.0-pro", false),
+    ("gemini-pro-vision", true),
+    // DeepSeek
+    ("deepseek-chat", false),
+    ("deepseek-reasoner", false),
+    // Common open-weight vision models (Ollama, OpenAI-compatible servers)
+    ("llava", true),
+    ("bakllava", true),
+    ("llama3.2-vision", true),
+    ("moondream", true),
+    ("minicpm-v", true),
+    ("qwen2.5vl", true),
+    ("gemma3", true),
+];
+
+// Strip routing prefixes such as `models/` (Gemini) or `openai/` (OpenRouter)
+fn base_model_name(model: &str) -> String {
+    model
+        .trim()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase()
+}
+
+fn lookup<T: Copy>(table: &[(&str, T)], model: &str) -> Option<T> {
+    let model = base_model_name(model);
+
+    table
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| *value)
+}
+
+/// The most tokens `model` can produce in one reply, if it is a known model
+pub fn max_output_tokens(model: &str) -> Option<i32> {
+    lookup(MAX_OUTPUT_TOKENS, model)
+}
+
+/// How many tokens of prompt and reply `model` can handle, if it is a known model
+pub fn context_window_tokens(model: &str) -> Option<i32> {
+    lookup(CONTEXT_WINDOW_TOKENS, model)
+}
+
+/// Whether `model` accepts images, or `None` if the model isn't in the table
+pub fn supports_vision(model: &str) -> Option<bool> {
+    lookup(VISION_SUPPORT, model)
+}
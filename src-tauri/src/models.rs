@@ -1,5 +1,8 @@
 use chrono::{DateTime, Utc};
+use pulldown_cmark::{CodeBlockKind, Event, Options, Parser, Tag, TagEnd};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::LazyLock;
 use sqlx::FromRow;
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -7,8 +10,32 @@ pub struct Chat {
     pub id: String,
     pub title: String,
     pub api_config_id: Option<String>,
+    pub sort_order: Option<i64>,
+    /// Kept when old chats are purged
+    pub pinned: bool,
+    pub temperature_override: Option<f32>,
+    pub max_tokens_override: Option<i32>,
+    /// Sent as a system message ahead of every request in this chat
+    pub pinned_context: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// `created_at` in the timezone the caller asked for; `created_at` stays authoritative
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at_local: Option<String>,
+}
+
+impl Chat {
+    /// `config` with this chat's generation overrides applied on top
+    pub fn apply_overrides(&self, mut config: ApiConfig) -> ApiConfig {
+        if let Some(temperature) = self.temperature_override {
+            config.temperature = temperature;
+        }
+        if let Some(max_tokens) = self.max_tokens_override {
+            config.max_tokens = Some(max_tokens);
+        }
+        config
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
@@ -20,9 +47,229 @@ pub struct Message {
     pub created_at: DateTime<Utc>,
     #[sqlx(skip)]
     pub images: Option<Vec<String>>,
+    pub api_config_id: Option<String>,
+    /// Model reasoning kept apart from the visible `content`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    /// Why the model stopped writing this reply, in OpenAI's terms; `length`
+    /// means it was cut off and can be continued
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MessageMetrics>,
+    /// `created_at` in the timezone the caller asked for; `created_at` stays authoritative
+    #[sqlx(skip)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at_local: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, sqlx::Type)]
+/// Look up an IANA timezone name such as `Europe/Berlin`
+pub fn parse_timezone(name: &str) -> Result<chrono_tz::Tz, ValidationError> {
+    name.trim().parse::<chrono_tz::Tz>()
+        .map_err(|_| ValidationError::new("timezone", format!("'{}' is not a known IANA timezone", name)))
+}
+
+/// RFC 3339 rendering of a UTC timestamp in `timezone`, with its offset at that moment
+pub fn to_local_rfc3339(timestamp: &DateTime<Utc>, timezone: chrono_tz::Tz) -> String {
+    timestamp.with_timezone(&timezone).to_rfc3339()
+}
+
+/// Average silent reading speed used for `reading_time_seconds`
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MessageMetrics {
+    pub word_count: usize,
+    pub character_count: usize,
+    pub line_count: usize,
+    pub reading_time_seconds: usize,
+    pub estimated_tokens: usize,
+}
+
+impl MessageMetrics {
+    pub fn from_content(content: &str) -> Self {
+        let word_count = content.split_whitespace().count();
+        let character_count = content.chars().count();
+
+        Self {
+            word_count,
+            character_count,
+            line_count: content.lines().count(),
+            reading_time_seconds: (word_count * 60).div_ceil(WORDS_PER_MINUTE),
+            // Roughly four characters per token for English text
+            estimated_tokens: character_count.div_ceil(4),
+        }
+    }
+}
+
+/// How `format_message` renders a message's markdown content
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageFormat {
+    /// The content as stored
+    Markdown,
+    /// Formatting and raw HTML stripped; links keep their URL in parentheses
+    PlainText,
+    /// Rendered HTML, with any raw HTML in the message escaped
+    Html,
+}
+
+impl MessageFormat {
+    pub fn render(self, content: &str) -> String {
+        let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+
+        match self {
+            MessageFormat::Markdown => content.to_string(),
+            MessageFormat::PlainText => markdown_to_plain_text(Parser::new_ext(content, options)),
+            MessageFormat::Html => {
+                let events = Parser::new_ext(content, options).map(|event| match event {
+                    Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+                    event => event,
+                });
+                let mut html = String::new();
+                pulldown_cmark::html::push_html(&mut html, events);
+                html
+            }
+        }
+    }
+}
+
+fn markdown_to_plain_text<'a>(events: impl Iterator<Item = Event<'a>>) -> String {
+    let mut text = String::new();
+    // Next number for each open list; `None` for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+    let mut link_urls: Vec<(String, usize)> = Vec::new();
+
+    for event in events {
+        match event {
+            Event::Text(value) | Event::Code(value) => {
+                text.push_str(&value);
+            }
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::TaskListMarker(done) => text.push_str(if done { "[x] " } else { "[ ] " }),
+            Event::Start(Tag::List(start)) => lists.push(start),
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                if !text.is_empty() && !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                text.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        text.push_str(&format!("{}. ", number));
+                        *number += 1;
+                    }
+                    _ => text.push_str("- "),
+                }
+            }
+            Event::End(TagEnd::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::Start(Tag::Link { dest_url, .. }) => link_urls.push((dest_url.to_string(), text.len())),
+            Event::End(TagEnd::Link) => {
+                if let Some((url, start)) = link_urls.pop() {
+                    if text[start..] != url && !url.is_empty() {
+                        text.push_str(&format!(" ({})", url));
+                    }
+                }
+            }
+            Event::End(TagEnd::TableCell) => text.push('\t'),
+            Event::End(TagEnd::TableHead | TagEnd::TableRow) => {
+                text.truncate(text.trim_end_matches('\t').len());
+                text.push('\n');
+            }
+            // Paragraphs inside list items stay on the item's line
+            Event::End(TagEnd::Paragraph | TagEnd::Heading(_) | TagEnd::CodeBlock | TagEnd::Table) if lists.is_empty() => {
+                text.truncate(text.trim_end_matches('\n').len());
+                text.push_str("\n\n");
+            }
+            _ => {}
+        }
+    }
+
+    text.trim_end().to_string()
+}
+
+/// A fenced or indented code block from a message, in document order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CodeBlock {
+    /// First word of the fence's info string, e.g. `rust` for ```` ```rust ````
+    pub language: Option<String>,
+    pub code: String,
+}
+
+impl CodeBlock {
+    /// File extension for the block's language tag, when it's a known one
+    pub fn default_extension(&self) -> Option<&'static str> {
+        let extension = match self.language.as_deref()?.to_ascii_lowercase().as_str() {
+            "rust" | "rs" => "rs",
+            "python" | "py" => "py",
+            "javascript" | "js" | "node" => "js",
+            "typescript" | "ts" => "ts",
+            "jsx" => "jsx",
+            "tsx" => "tsx",
+            "json" => "json",
+            "yaml" | "yml" => "yaml",
+            "toml" => "toml",
+            "html" => "html",
+            "css" => "css",
+            "scss" => "scss",
+            "markdown" | "md" => "md",
+            "bash" | "sh" | "shell" | "zsh" => "sh",
+            "powershell" | "ps1" => "ps1",
+            "bat" | "batch" | "cmd" => "bat",
+            "c" => "c",
+            "cpp" | "c++" | "cxx" => "cpp",
+            "csharp" | "c#" | "cs" => "cs",
+            "go" | "golang" => "go",
+            "java" => "java",
+            "kotlin" | "kt" => "kt",
+            "swift" => "swift",
+            "ruby" | "rb" => "rb",
+            "php" => "php",
+            "lua" => "lua",
+            "sql" => "sql",
+            "xml" => "xml",
+            "dockerfile" | "docker" => "dockerfile",
+            "text" | "txt" | "plaintext" => "txt",
+            _ => return None,
+        };
+        Some(extension)
+    }
+}
+
+/// Pull every code block out of a message's markdown
+pub fn extract_code_blocks(content: &str) -> Vec<CodeBlock> {
+    let mut blocks = Vec::new();
+    let mut current: Option<CodeBlock> = None;
+
+    for event in Parser::new(content) {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let language = match kind {
+                    CodeBlockKind::Fenced(info) => info.split_whitespace().next().map(str::to_string),
+                    CodeBlockKind::Indented => None,
+                };
+                current = Some(CodeBlock { language, code: String::new() });
+            }
+            Event::Text(text) => {
+                if let Some(block) = current.as_mut() {
+                    block.code.push_str(&text);
+                }
+            }
+            Event::End(TagEnd::CodeBlock) => blocks.extend(current.take()),
+            _ => {}
+        }
+    }
+
+    blocks
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::Type)]
 #[sqlx(type_name = "TEXT")]
 #[serde(rename_all = "lowercase")]
 pub enum MessageRole {
@@ -32,6 +279,353 @@ pub enum MessageRole {
     Assistant,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[sqlx(type_name = "TEXT")]
+#[serde(rename_all = "snake_case")]
+pub enum MessageFlag {
+    #[sqlx(rename = "bookmark")]
+    Bookmark,
+    #[sqlx(rename = "thumbs_up")]
+    ThumbsUp,
+    #[sqlx(rename = "thumbs_down")]
+    ThumbsDown,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FlaggedMessage {
+    #[serde(flatten)]
+    pub message: Message,
+    pub flags: Vec<MessageFlag>,
+}
+
+/// Assistant replies produced with one API config. Replies are credited to
+/// the config that generated them, or to their chat's config when that
+/// wasn't recorded.
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ProviderUsage {
+    /// `None` for replies from chats that use the default config
+    pub api_config_id: Option<String>,
+    pub config_name: Option<String>,
+    pub provider: Option<ApiProvider>,
+    pub assistant_messages: i64,
+    /// Estimated from the stored reply text; providers' reported usage isn't kept
+    pub estimated_tokens: i64,
+}
+
+/// Whether the next request for a chat is likely to fit the model's context window
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ContextFit {
+    /// Rough estimate of the prompt that would be sent
+    pub prompt_tokens: usize,
+    /// `None` when the model's context window is unknown
+    pub context_limit: Option<usize>,
+    /// Tokens set aside for the reply (the config's `max_tokens`, or a default)
+    pub reserved_response_tokens: usize,
+    /// Tokens left after the prompt and the reserved reply; negative when over
+    pub headroom: Option<i64>,
+    pub fits: bool,
+}
+
+/// Backend liveness report for diagnostics
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PingInfo {
+    pub version: String,
+    pub uptime_seconds: u64,
+    pub database_ok: bool,
+    pub agent_sessions: usize,
+    pub active_streams: usize,
+}
+
+/// Versions of the app and what it's built on, for About dialogs and bug reports
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionInfo {
+    pub app_version: String,
+    pub tauri_version: String,
+    pub sqlite_version: String,
+    /// Latest database migration applied
+    pub schema_version: Option<i64>,
+    pub os: String,
+    pub os_family: String,
+    pub arch: String,
+    pub debug_build: bool,
+}
+
+/// What `abort_all` stopped
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbortReport {
+    pub streams: usize,
+    pub agent_actions: usize,
+    pub file_followers: usize,
+    pub directory_watches: usize,
+    pub searches: usize,
+    pub shell_sessions: usize,
+}
+
+/// A reusable prompt whose `{{name}}` placeholders are filled in by `render`
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub name: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PromptTemplate {
+    /// Substitute every `{{name}}` (surrounding spaces allowed) with its value
+    /// from `vars`. Placeholders without a value are all reported together.
+    pub fn render(&self, vars: &HashMap<String, String>) -> Result<String, ValidationError> {
+        render_placeholders(&self.body, vars)
+    }
+}
+
+/// A `{{name}}` placeholder, surrounding spaces allowed
+static PLACEHOLDER: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"\{\{\s*([A-Za-z0-9_.-]+)\s*\}\}").expect("valid placeholder pattern")
+});
+
+/// Template rendering shared by prompt templates and slash commands
+pub fn render_placeholders(body: &str, vars: &HashMap<String, String>) -> Result<String, ValidationError> {
+    let mut missing: Vec<&str> = Vec::new();
+    for captures in PLACEHOLDER.captures_iter(body) {
+        let name = captures.get(1).map_or("", |m| m.as_str());
+        if !vars.contains_key(name) && !missing.contains(&name) {
+            missing.push(name);
+        }
+    }
+    if !missing.is_empty() {
+        return Err(ValidationError::new("vars", format!("missing values for {}", missing.join(", "))));
+    }
+
+    Ok(PLACEHOLDER
+        .replace_all(body, |captures: &regex::Captures| vars[&captures[1]].clone())
+        .into_owned())
+}
+
+/// Preference holding slash command overrides: an object of command name to
+/// template, where `null` turns a built-in command off
+pub const SLASH_COMMANDS_PREFERENCE: &str = "slash_commands";
+
+/// Slash commands available without any configuration. Templates see
+/// `{{input}}` (everything after the command), `{{1}}`..`{{9}}` (its words)
+/// and `{{rest}}` (everything after the first word).
+pub const DEFAULT_SLASH_COMMANDS: &[(&str, &str)] = &[
+    ("summarize", "Summarize the conversation so far concisely, keeping the key points and any decisions made.\n\n{{input}}"),
+    ("translate", "Translate the following text into {{1}}. If no text follows, translate your previous reply instead. Reply with the translation only.\n\n{{rest}}"),
+    ("explain", "Explain the following clearly, step by step:\n\n{{input}}"),
+];
+
+/// Expand a message starting with `/name` into its command's template.
+/// Returns `None` when the message isn't a known slash command, so it can
+/// be sent as typed.
+pub fn expand_slash_command(text: &str, commands: &HashMap<String, String>) -> Result<Option<String>, ValidationError> {
+    let Some(after_slash) = text.trim_start().strip_prefix('/') else {
+        return Ok(None);
+    };
+
+    let name_end = after_slash.find(char::is_whitespace).unwrap_or(after_slash.len());
+    let name = &after_slash[..name_end];
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Ok(None);
+    }
+    let Some(template) = commands.get(&name.to_ascii_lowercase()) else {
+        return Ok(None);
+    };
+
+    let input = after_slash[name_end..].trim();
+    let rest = input.split_once(char::is_whitespace).map_or("", |(_, rest)| rest.trim_start());
+
+    let mut vars = HashMap::from([
+        ("input".to_string(), input.to_string()),
+        ("rest".to_string(), rest.to_string()),
+    ]);
+    for (index, word) in input.split_whitespace().take(9).enumerate() {
+        vars.insert((index + 1).to_string(), word.to_string());
+    }
+
+    let expanded = render_placeholders(template, &vars)
+        .map_err(|e| ValidationError::new("message", format!("/{}: {}", name, e.message)))?;
+    Ok(Some(expanded.trim_end().to_string()))
+}
+
+/// One agent action as kept in the activity log. Results aren't kept since
+/// they can be whole files or screenshots; long parameter strings are shortened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentActionRecord {
+    pub id: i64,
+    pub session_id: String,
+    pub action_type: String,
+    pub description: String,
+    pub parameters: HashMap<String, serde_json::Value>,
+    pub success: bool,
+    pub cancelled: bool,
+    pub error_message: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One entry in the system operation audit log. Parameters and results are
+/// shortened and have anything that looks like a secret masked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OperationLogEntry {
+    pub id: i64,
+    pub operation: String,
+    pub parameters: HashMap<String, serde_json::Value>,
+    pub success: bool,
+    /// What happened, or the error when it failed
+    pub result: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Broad reason a provider request failed, for wording the error and deciding on retries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiErrorKind {
+    /// Missing, invalid or under-privileged API key
+    Authentication,
+    /// Rate limit or quota exceeded
+    RateLimited,
+    ModelNotFound,
+    /// Any other rejection of the request itself
+    InvalidRequest,
+    /// The provider failed or is overloaded
+    ServerError,
+    /// No response at all (connection refused, timeout)
+    Unreachable,
+    Other,
+}
+
+/// A provider error with the fields providers report pulled out of the body
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiErrorDetails {
+    pub provider: ApiProvider,
+    /// HTTP status; `None` when the provider couldn't be reached
+    pub status: Option<u16>,
+    pub code: Option<String>,
+    #[serde(rename = "type")]
+    pub error_type: Option<String>,
+    /// The provider's own message, or the raw body when it isn't JSON
+    pub message: String,
+    pub kind: ApiErrorKind,
+}
+
+impl ApiErrorDetails {
+    /// Parse an error response body. Understands OpenAI/Anthropic style
+    /// `{"error": {"type", "code", "message"}}`, Google's `{"error": {"code",
+    /// "status", "message"}}` and Ollama's `{"error": "..."}`.
+    pub fn from_response(provider: ApiProvider, status: u16, body: &str) -> Self {
+        let body = body.trim();
+        let parsed = match serde_json::from_str::<serde_json::Value>(body) {
+            // Google sometimes wraps the error in a one-element array
+            Ok(serde_json::Value::Array(mut items)) if !items.is_empty() => Some(items.swap_remove(0)),
+            Ok(value) => Some(value),
+            Err(_) => None,
+        };
+
+        let mut code = None;
+        let mut error_type = None;
+        let mut message = None;
+
+        match parsed.as_ref().map(|value| &value["error"]) {
+            Some(serde_json::Value::Object(error)) => {
+                code = error.get("code").and_then(|code| match code {
+                    serde_json::Value::String(code) => Some(code.clone()),
+                    serde_json::Value::Number(code) => Some(code.to_string()),
+                    _ => None,
+                });
+                error_type = error.get("type")
+                    .or_else(|| error.get("status"))
+                    .and_then(|kind| kind.as_str())
+                    .map(str::to_string);
+                message = error.get("message").and_then(|message| message.as_str()).map(str::to_string);
+            }
+            Some(serde_json::Value::String(error)) => message = Some(error.clone()),
+            _ => {}
+        }
+
+        let message = message
+            .or_else(|| (!body.is_empty()).then(|| body.to_string()))
+            .unwrap_or_else(|| format!("HTTP {}", status));
+        let kind = classify_api_error(status, code.as_deref(), error_type.as_deref(), &message);
+
+        Self { provider, status: Some(status), code, error_type, message, kind }
+    }
+
+    /// A request that never got a response
+    pub fn unreachable(provider: ApiProvider, message: String) -> Self {
+        Self { provider, status: None, code: None, error_type: None, message, kind: ApiErrorKind::Unreachable }
+    }
+}
+
+fn classify_api_error(status: u16, code: Option<&str>, error_type: Option<&str>, message: &str) -> ApiErrorKind {
+    let hints = format!("{} {}", code.unwrap_or_default(), error_type.unwrap_or_default()).to_lowercase();
+    let mentions_model = message.to_lowercase().contains("model");
+
+    if ["invalid_api_key", "authentication", "unauthenticated", "permission"].iter().any(|hint| hints.contains(hint)) {
+        return ApiErrorKind::Authentication;
+    }
+    if ["rate_limit", "quota", "resource_exhausted"].iter().any(|hint| hints.contains(hint)) {
+        return ApiErrorKind::RateLimited;
+    }
+    if hints.contains("model_not_found") || ((status == 404 || hints.contains("not_found")) && mentions_model) {
+        return ApiErrorKind::ModelNotFound;
+    }
+
+    match status {
+        401 | 403 => ApiErrorKind::Authentication,
+        429 => ApiErrorKind::RateLimited,
+        400..=499 => ApiErrorKind::InvalidRequest,
+        500..=599 => ApiErrorKind::ServerError,
+        _ => ApiErrorKind::Other,
+    }
+}
+
+/// Rows copied out of a damaged database, per table
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TableRecovery {
+    pub table: String,
+    pub rows_recovered: u64,
+    /// Rows that were still listed but couldn't be read; rows on pages too
+    /// damaged to list at all aren't counted
+    pub rows_lost: u64,
+}
+
+/// A damaged database that was rebuilt before the app opened it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepairReport {
+    /// What `PRAGMA integrity_check` found
+    pub problems: Vec<String>,
+    pub tables: Vec<TableRecovery>,
+    /// Where the damaged file was kept
+    pub backup_path: String,
+}
+
+/// Outcome of checking the open database
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// What `PRAGMA integrity_check` found; empty when the database is healthy
+    pub problems: Vec<String>,
+    /// Damage was found in the running app; restarting rebuilds the database
+    pub restart_required: bool,
+    /// The repair made when this launch found the file damaged
+    pub startup_repair: Option<RepairReport>,
+}
+
+/// A chat together with its full message history
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatExport {
+    pub chat: Chat,
+    pub messages: Vec<Message>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One `ChatExport` object per line
+    Jsonl,
+    /// A single JSON array of `ChatExport` objects
+    Json,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct ApiConfig {
     pub id: String,
@@ -43,6 +637,13 @@ pub struct ApiConfig {
     pub temperature: f32,
     pub max_tokens: Option<i32>,
     pub is_default: bool,
+    /// Config to retry with when this one's provider is unavailable
+    pub fallback_config_id: Option<String>,
+    /// Ollama `keep_alive`, e.g. `5m` or `-1`; `None` leaves Ollama's default
+    pub keep_alive: Option<String>,
+    /// Which of a chat's messages are sent with each request
+    #[sqlx(try_from = "String")]
+    pub context_strategy: ContextStrategy,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -63,17 +664,116 @@ pub enum ApiProvider {
     Custom,
 }
 
+pub const OPENAI_DEFAULT_URL: &str = "https://api.openai.com/v1/chat/completions";
+pub const ANTHROPIC_DEFAULT_URL: &str = "https://api.anthropic.com/v1/messages";
+pub const GOOGLE_DEFAULT_URL: &str = "https://generativelanguage.googleapis.com/v1beta/models";
+pub const OLLAMA_DEFAULT_URL: &str = "http://localhost:11434";
+
+/// How a provider expects the API key to be sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthStyle {
+    /// `Authorization: Bearer <key>`
+    Bearer,
+    /// `x-api-key: <key>`
+    XApiKey,
+    /// `?key=<key>` on the request URL
+    QueryParam,
+    /// No key needed
+    None,
+}
+
+/// What the settings form needs to know about a provider. Mirrors how
+/// `send_chat_completion` talks to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderMeta {
+    pub provider: ApiProvider,
+    pub display_name: String,
+    pub default_base_url: Option<String>,
+    pub required_fields: Vec<String>,
+    /// Streams tokens as they arrive rather than replaying a finished reply
+    pub supports_streaming: bool,
+    /// Image attachments are forwarded to the model
+    pub supports_vision: bool,
+    pub supports_tools: bool,
+    pub auth_style: AuthStyle,
+}
+
+impl ApiProvider {
+    pub const ALL: [ApiProvider; 5] = [
+        ApiProvider::OpenAI,
+        ApiProvider::Anthropic,
+        ApiProvider::Google,
+        ApiProvider::Ollama,
+        ApiProvider::Custom,
+    ];
+
+    pub fn metadata(&self) -> ProviderMeta {
+        let (display_name, default_base_url, auth_style) = match self {
+            ApiProvider::OpenAI => ("OpenAI", Some(OPENAI_DEFAULT_URL), AuthStyle::Bearer),
+            ApiProvider::Anthropic => ("Anthropic", Some(ANTHROPIC_DEFAULT_URL), AuthStyle::XApiKey),
+            ApiProvider::Google => ("Google Gemini", Some(GOOGLE_DEFAULT_URL), AuthStyle::QueryParam),
+            // The key is optional, for servers behind an authenticating proxy
+            ApiProvider::Ollama => ("Ollama", Some(OLLAMA_DEFAULT_URL), AuthStyle::Bearer),
+            // The key is optional and sent as a bearer token when set
+            ApiProvider::Custom => ("Custom (OpenAI-compatible)", None, AuthStyle::Bearer),
+        };
+
+        let required_fields: &[&str] = match self {
+            ApiProvider::Ollama => &["name", "model"],
+            ApiProvider::Custom => &["name", "model", "base_url"],
+            _ => &["name", "api_key", "model"],
+        };
+
+        ProviderMeta {
+            provider: self.clone(),
+            display_name: display_name.to_string(),
+            default_base_url: default_base_url.map(str::to_string),
+            required_fields: required_fields.iter().map(|field| field.to_string()).collect(),
+            supports_streaming: matches!(self, ApiProvider::OpenAI),
+            // Images go out in OpenAI's `image_url` format, which only
+            // OpenAI-style endpoints understand
+            supports_vision: matches!(self, ApiProvider::OpenAI | ApiProvider::Custom),
+            supports_tools: false,
+            auth_style,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatWithLastMessage {
     pub id: String,
     pub title: String,
     pub api_config_id: Option<String>,
     pub api_config_name: Option<String>,
+    pub sort_order: Option<i64>,
+    pub pinned: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub last_message: Option<String>,
     pub last_message_time: Option<DateTime<Utc>>,
     pub unread_count: i64,
+    /// `created_at` in the timezone the caller asked for; `created_at` stays authoritative
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at_local: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GlobalHitKind {
+    Chat,
+    Message,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GlobalHit {
+    pub kind: GlobalHitKind,
+    pub chat_id: String,
+    pub chat_title: String,
+    pub message_id: Option<String>,
+    pub snippet: String,
+    pub timestamp: DateTime<Utc>,
+    pub relevance: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -88,35 +788,417 @@ pub struct CreateMessageRequest {
     pub content: String,
     pub role: MessageRole,
     pub images: Option<Vec<String>>,
+    /// Don't insert the message if it repeats the chat's latest message
+    #[serde(default)]
+    pub skip_if_duplicate: bool,
 }
 
+/// One message for `create_messages`
 #[derive(Debug, Serialize, Deserialize)]
-pub struct UpdateChatRequest {
-    pub title: String,
+pub struct NewMessage {
+    pub role: MessageRole,
+    pub content: String,
+    #[serde(default)]
+    pub images: Option<Vec<String>>,
+    #[serde(default)]
     pub api_config_id: Option<String>,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    /// Original timestamp when importing. Messages without one are stamped
+    /// now, a microsecond apart so they keep their order.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+/// A partial update: omitted fields keep their current value
+pub struct UpdateChatRequest {
+    #[serde(default)]
+    pub title: Option<String>,
+    /// `null` goes back to the default config
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub api_config_id: Option<Option<String>>,
+    /// `null` clears the override
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub temperature_override: Option<Option<f32>>,
+    /// `null` clears the override
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub max_tokens_override: Option<Option<i32>>,
+    /// `null` or blank text clears the note
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub pinned_context: Option<Option<String>>,
+}
+
+impl UpdateChatRequest {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if let Some(temperature) = self.temperature_override.flatten() {
+            if !(0.0..=2.0).contains(&temperature) {
+                return Err(ValidationError::new("temperature_override", format!("must be between 0 and 2, got {}", temperature)));
+            }
+        }
+
+        if let Some(max_tokens) = self.max_tokens_override.flatten() {
+            if max_tokens < 0 {
+                return Err(ValidationError::new("max_tokens_override", format!("must not be negative, got {}", max_tokens)));
+            }
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateApiConfigRequest {
     pub name: String,
-    pub provider: ApiProvider,
+    /// Falls back to `AppSettings::default_provider` when omitted
+    #[serde(default)]
+    pub provider: Option<ApiProvider>,
     pub api_key: String,
+    /// Endpoint override. Expected form per provider:
+    /// - OpenAI / Custom: the full chat completions URL, e.g. `https://api.openai.com/v1/chat/completions`
+    /// - Anthropic: the full messages URL, e.g. `https://api.anthropic.com/v1/messages`
+    /// - Ollama: the server root, e.g. `http://localhost:11434` or `https://ollama.example.com/proxy`
+    /// - Google: the models collection, e.g. `https://generativelanguage.googleapis.com/v1beta/models`,
+    ///   or its OpenAI-compatible `.../openai/chat/completions` endpoint
+    ///
+    /// Trailing slashes are trimmed on save.
     pub base_url: Option<String>,
     pub model: String,
-    pub temperature: f32,
+    /// Falls back to `AppSettings::default_temperature` when omitted
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    /// Falls back to `AppSettings::default_max_tokens` when omitted
+    #[serde(default)]
     pub max_tokens: Option<i32>,
     pub is_default: bool,
+    /// Config to retry with when this one's provider is unavailable. Not
+    /// carried through export/import, since ids differ between installs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fallback_config_id: Option<String>,
+    /// How long Ollama keeps the model loaded after a request: a duration
+    /// such as `5m` or `1h30m`, a number of seconds, or `-1` for forever.
+    /// Ignored by other providers.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+    /// Defaults to the last 10 messages when omitted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_strategy: Option<ContextStrategy>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UpdateApiConfigRequest {
     pub name: String,
     pub api_key: String,
+    /// Same form as `CreateApiConfigRequest::base_url`
     pub base_url: Option<String>,
     pub model: String,
     pub temperature: f32,
     pub max_tokens: Option<i32>,
     pub is_default: bool,
+    /// Same as `CreateApiConfigRequest::fallback_config_id`. Omitted leaves
+    /// it unchanged, `null` clears it.
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub fallback_config_id: Option<Option<String>>,
+    /// Same as `CreateApiConfigRequest::keep_alive`. Omitted leaves it
+    /// unchanged, `null` restores Ollama's default.
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub keep_alive: Option<Option<String>>,
+    /// Omitted leaves it unchanged, `null` restores the default of the
+    /// last 10 messages
+    #[serde(default, deserialize_with = "explicit_null")]
+    pub context_strategy: Option<Option<ContextStrategy>>,
+}
+
+/// Portable snapshot of API configs for moving a setup between installs
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiConfigExport {
+    pub version: u32,
+    pub configs: Vec<CreateApiConfigRequest>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ApiConfigImportSummary {
+    pub created: u32,
+    pub updated: u32,
+    pub skipped: u32,
+}
+
+/// A request field that failed validation before reaching the database
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid {field}: {message}")]
+pub struct ValidationError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl ValidationError {
+    pub fn new(field: &'static str, message: impl Into<String>) -> Self {
+        Self { field, message: message.into() }
+    }
+}
+
+/// For partial updates: an omitted field deserializes to `None` (leave it
+/// unchanged) and an explicit `null` to `Some(None)` (clear it)
+fn explicit_null<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Trim whitespace and trailing slashes so the per-provider URL building in
+/// `send_chat_completion` doesn't produce `//` paths. Blank URLs become `None`.
+pub fn normalize_base_url(base_url: Option<&str>) -> Result<Option<String>, ValidationError> {
+    let trimmed = match base_url.map(str::trim) {
+        Some(url) if !url.is_empty() => url.trim_end_matches('/'),
+        _ => return Ok(None),
+    };
+
+    let parsed = reqwest::Url::parse(trimmed)
+        .map_err(|e| ValidationError::new("base_url", format!("'{}' is not a valid URL: {}", trimmed, e)))?;
+
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(ValidationError::new("base_url", format!("must use http or https, got '{}'", parsed.scheme())));
+    }
+
+    if parsed.host_str().is_none_or(|host| host.is_empty()) {
+        return Err(ValidationError::new("base_url", format!("'{}' has no host", trimmed)));
+    }
+
+    Ok(Some(trimmed.to_string()))
+}
+
+/// A duration Ollama accepts for `keep_alive`: seconds, or Go-style units like `1h30m`
+static KEEP_ALIVE: LazyLock<regex::Regex> = LazyLock::new(|| {
+    regex::Regex::new(r"^-?(\d+|(\d+(\.\d+)?(ns|us|µs|ms|s|m|h))+)$").expect("valid keep_alive pattern")
+});
+
+/// Trim an Ollama `keep_alive` value and check it's something Ollama accepts.
+/// Blank means unset.
+pub fn normalize_keep_alive(keep_alive: Option<&str>) -> Result<Option<String>, ValidationError> {
+    let trimmed = match keep_alive.map(str::trim) {
+        Some(value) if !value.is_empty() => value,
+        _ => return Ok(None),
+    };
+
+    if !KEEP_ALIVE.is_match(trimmed) {
+        return Err(ValidationError::new("keep_alive", format!("'{}' is not a duration like '5m', '1h30m' or '-1'", trimmed)));
+    }
+
+    Ok(Some(trimmed.to_string()))
+}
+
+/// `keep_alive` as it goes into an Ollama request. Ollama reads strings as
+/// durations with units, so a bare number of seconds is sent as a number.
+pub fn keep_alive_json(keep_alive: &str) -> serde_json::Value {
+    keep_alive.parse::<i64>()
+        .map(serde_json::Value::from)
+        .unwrap_or_else(|_| serde_json::Value::from(keep_alive))
+}
+
+/// Messages sent verbatim under the default strategy
+pub const DEFAULT_CONTEXT_MESSAGES: usize = 10;
+
+/// How many messages at a time a `Summarized` context folds into its summary
+pub const SUMMARY_CHUNK_MESSAGES: usize = 10;
+
+/// How a chat's history is chosen for each request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "value", rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// The last N messages
+    RecentN(usize),
+    /// As many of the latest messages as fit in this many estimated tokens
+    TokenBudget(usize),
+    /// The last 10 to 19 messages, plus a model-written summary of everything
+    /// before them that is extended 10 messages at a time
+    Summarized,
+    /// Every message, trimmed from the oldest only if the model's context window would overflow
+    Full,
+}
+
+impl Default for ContextStrategy {
+    fn default() -> Self {
+        ContextStrategy::RecentN(DEFAULT_CONTEXT_MESSAGES)
+    }
+}
+
+impl TryFrom<String> for ContextStrategy {
+    type Error = serde_json::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        serde_json::from_str(&value)
+    }
+}
+
+impl ContextStrategy {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        match self {
+            ContextStrategy::RecentN(0) => Err(ValidationError::new("context_strategy", "recent_n must keep at least one message")),
+            ContextStrategy::TokenBudget(0) => Err(ValidationError::new("context_strategy", "token_budget must be more than zero")),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn validate_api_config_fields(
+    provider: &ApiProvider,
+    base_url: Option<&str>,
+    model: &str,
+    temperature: f32,
+    max_tokens: Option<i32>,
+    context_strategy: Option<&ContextStrategy>,
+) -> Result<(), ValidationError> {
+    if let Some(strategy) = context_strategy {
+        strategy.validate()?;
+    }
+
+    if model.trim().is_empty() {
+        return Err(ValidationError::new("model", "must not be empty"));
+    }
+
+    if !(0.0..=2.0).contains(&temperature) {
+        return Err(ValidationError::new("temperature", format!("must be between 0 and 2, got {}", temperature)));
+    }
+
+    if let Some(max_tokens) = max_tokens {
+        if max_tokens < 0 {
+            return Err(ValidationError::new("max_tokens", format!("must not be negative, got {}", max_tokens)));
+        }
+    }
+
+    if matches!(provider, ApiProvider::Custom) && base_url.is_none_or(|url| url.trim().is_empty()) {
+        return Err(ValidationError::new("base_url", "is required for custom providers"));
+    }
+
+    Ok(())
+}
+
+impl CreateApiConfigRequest {
+    /// Fill omitted fields from the global app settings
+    pub fn apply_defaults(&mut self, settings: &AppSettings) {
+        if self.provider.is_none() {
+            self.provider = settings.default_provider.clone();
+        }
+        if self.temperature.is_none() {
+            self.temperature = Some(settings.default_temperature);
+        }
+        if self.max_tokens.is_none() {
+            self.max_tokens = settings.default_max_tokens;
+        }
+    }
+
+    /// Clean up user-entered values before validation
+    pub fn normalize(&mut self) -> Result<(), ValidationError> {
+        self.base_url = normalize_base_url(self.base_url.as_deref())?;
+        self.keep_alive = normalize_keep_alive(self.keep_alive.as_deref())?;
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let provider = self.provider.as_ref()
+            .ok_or_else(|| ValidationError::new("provider", "is required when no default provider is set"))?;
+        let temperature = self.temperature
+            .ok_or_else(|| ValidationError::new("temperature", "is required"))?;
+
+        validate_api_config_fields(
+            provider,
+            self.base_url.as_deref(),
+            &self.model,
+            temperature,
+            self.max_tokens,
+            self.context_strategy.as_ref(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AppSettings {
+    pub default_temperature: f32,
+    pub default_max_tokens: Option<i32>,
+    pub default_provider: Option<ApiProvider>,
+    /// Abort a streamed reply after this many seconds without any data
+    pub stream_idle_timeout_secs: i64,
+    /// Reconnect this many times when a streamed reply drops mid-response
+    pub stream_max_reconnects: i64,
+    /// Worker threads for multi-file search and copy; 0 uses one per CPU
+    pub max_threads: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UpdateAppSettingsRequest {
+    pub default_temperature: f32,
+    #[serde(default)]
+    pub default_max_tokens: Option<i32>,
+    #[serde(default)]
+    pub default_provider: Option<ApiProvider>,
+    /// Left unchanged when omitted
+    #[serde(default)]
+    pub stream_idle_timeout_secs: Option<i64>,
+    /// Left unchanged when omitted
+    #[serde(default)]
+    pub stream_max_reconnects: Option<i64>,
+    /// Left unchanged when omitted
+    #[serde(default)]
+    pub max_threads: Option<i64>,
+}
+
+impl UpdateAppSettingsRequest {
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        if !(0.0..=2.0).contains(&self.default_temperature) {
+            return Err(ValidationError::new("default_temperature", format!("must be between 0 and 2, got {}", self.default_temperature)));
+        }
+
+        if let Some(max_tokens) = self.default_max_tokens {
+            if max_tokens < 0 {
+                return Err(ValidationError::new("default_max_tokens", format!("must not be negative, got {}", max_tokens)));
+            }
+        }
+
+        if let Some(timeout) = self.stream_idle_timeout_secs {
+            if !(1..=3600).contains(&timeout) {
+                return Err(ValidationError::new("stream_idle_timeout_secs", format!("must be between 1 and 3600, got {}", timeout)));
+            }
+        }
+
+        if let Some(reconnects) = self.stream_max_reconnects {
+            if !(0..=10).contains(&reconnects) {
+                return Err(ValidationError::new("stream_max_reconnects", format!("must be between 0 and 10, got {}", reconnects)));
+            }
+        }
+
+        if let Some(threads) = self.max_threads {
+            if !(0..=64).contains(&threads) {
+                return Err(ValidationError::new("max_threads", format!("must be between 0 and 64, got {}", threads)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl UpdateApiConfigRequest {
+    /// Clean up user-entered values before validation
+    pub fn normalize(&mut self) -> Result<(), ValidationError> {
+        self.base_url = normalize_base_url(self.base_url.as_deref())?;
+        if let Some(keep_alive) = &self.keep_alive {
+            self.keep_alive = Some(normalize_keep_alive(keep_alive.as_deref())?);
+        }
+        Ok(())
+    }
+
+    /// Updates can't change the provider, so the caller passes the stored one
+    pub fn validate(&self, provider: &ApiProvider) -> Result<(), ValidationError> {
+        validate_api_config_fields(
+            provider,
+            self.base_url.as_deref(),
+            &self.model,
+            self.temperature,
+            self.max_tokens,
+            self.context_strategy.flatten().as_ref(),
+        )
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -127,7 +1209,7 @@ pub struct ChatCompletionRequest {
     pub max_tokens: Option<i32>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
     pub content: serde_json::Value, // Can be string or array of content objects
@@ -136,9 +1218,69 @@ pub struct ChatMessage {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatCompletionResponse {
     pub choices: Vec<ChatChoice>,
+    #[serde(default)]
+    pub usage: Option<TokenUsage>,
+}
+
+/// Token counts as reported by the provider
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct TokenUsage {
+    #[serde(default)]
+    pub prompt_tokens: u32,
+    #[serde(default)]
+    pub completion_tokens: u32,
+    #[serde(default)]
+    pub total_tokens: u32,
+}
+
+impl TokenUsage {
+    pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Self {
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            total_tokens: prompt_tokens + completion_tokens,
+        }
+    }
+
+    /// Add another request's usage, e.g. after a stream reconnected
+    pub fn add(&mut self, other: TokenUsage) {
+        self.prompt_tokens += other.prompt_tokens;
+        self.completion_tokens += other.completion_tokens;
+        self.total_tokens += other.total_tokens;
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ChatChoice {
-    pub message: ChatMessage,
+    pub message: ChatResponseMessage,
+    #[serde(default)]
+    pub finish_reason: Option<String>,
+}
+
+/// An assistant reply in an OpenAI-compatible response. Reasoning models put
+/// their thoughts in `reasoning` (OpenAI, OpenRouter) or `reasoning_content` (DeepSeek).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChatResponseMessage {
+    pub role: String,
+    pub content: serde_json::Value,
+    #[serde(default)]
+    pub reasoning: Option<String>,
+    #[serde(default)]
+    pub reasoning_content: Option<String>,
+}
+
+/// A model reply with its reasoning split out of the visible content
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CompletionOutput {
+    pub content: String,
+    pub reasoning: Option<String>,
+    /// Present when the provider reported it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+    /// Set when a fallback config answered instead of the one asked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_config_id: Option<String>,
+    /// Why the model stopped, in OpenAI's terms (`stop`, `length`, ...)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<String>,
 }
\ No newline at end of file
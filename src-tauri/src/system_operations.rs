@@ -5,6 +5,10 @@ use std::collections::HashMap;
 use std::path::Path;
 use std::fs;
 use std::io::Read;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use rayon::prelude::*;
+use walkdir::WalkDir;
+use crate::file_operations::{is_cancelled, with_worker_pool, Cancelled};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AppInfo {
@@ -20,8 +24,14 @@ pub struct CommandResult {
     pub stderr: String,
     pub exit_code: i32,
     pub success: bool,
+    /// Set when stdout or stderr exceeded the output cap and was cut short
+    #[serde(default)]
+    pub truncated: bool,
 }
 
+/// Default cap on captured stdout and stderr, per stream (4 MB)
+pub const DEFAULT_MAX_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FileSystemOperation {
     pub operation_type: FileOperationType,
@@ -48,6 +58,71 @@ pub struct ProcessInfo {
     pub cpu_usage: Option<f32>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EnvInfo {
+    pub os: String,
+    pub arch: String,
+    pub family: String,
+    pub hostname: Option<String>,
+    pub username: Option<String>,
+    pub home_directory: Option<String>,
+    pub current_directory: Option<String>,
+    pub path_separator: String,
+    pub shell: Option<String>,
+    pub env_vars: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitStatus {
+    pub branch: Option<String>,
+    pub upstream: Option<String>,
+    pub ahead: u32,
+    pub behind: u32,
+    pub files: Vec<GitFileStatus>,
+    pub clean: bool,
+}
+
+/// One changed path. `index` and `worktree` use git's status letters
+/// (`M`, `A`, `D`, `R`, `C`, `U`, `.` for unchanged, `?` for untracked).
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitFileStatus {
+    pub path: String,
+    pub original_path: Option<String>,
+    pub index: String,
+    pub worktree: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitCommit {
+    pub hash: String,
+    pub author: String,
+    pub email: String,
+    pub date: String,
+    pub subject: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GitDiff {
+    pub diff: String,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DisplayInfo {
+    pub index: usize,
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub is_primary: bool,
+}
+
+// Environment variables that are safe to expose; anything else may hold secrets
+const ENV_VAR_ALLOWLIST: &[&str] = &[
+    "PATH", "PATHEXT", "HOME", "USERPROFILE", "USER", "USERNAME", "SHELL", "COMSPEC",
+    "LANG", "LC_ALL", "LANGUAGE", "TERM", "TMPDIR", "TEMP", "TMP", "EDITOR", "VISUAL",
+    "XDG_SESSION_TYPE", "XDG_CURRENT_DESKTOP", "DESKTOP_SESSION", "OS", "PROCESSOR_ARCHITECTURE",
+];
+
 // Permission levels for operations
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum PermissionLevel {
@@ -219,8 +294,68 @@ pub fn get_installed_applications() -> Result<Vec<AppInfo>> {
     Ok(apps)
 }
 
+/// The user's login shell: `$SHELL` where set (including Git Bash and MSYS on
+/// Windows), otherwise `%ComSpec%` on Windows and `sh` elsewhere
+pub fn detect_default_shell() -> String {
+    if let Some(shell) = std::env::var_os("SHELL").filter(|shell| !shell.is_empty()) {
+        if Path::new(&shell).is_file() {
+            return shell.to_string_lossy().to_string();
+        }
+    }
+
+    if cfg!(target_os = "windows") {
+        std::env::var("ComSpec").unwrap_or_else(|_| "cmd".to_string())
+    } else {
+        "sh".to_string()
+    }
+}
+
+/// Build a command that has `shell` run `command`, using the flag that shell
+/// expects (`/C` for cmd, `-Command` for PowerShell, `-c` otherwise)
+fn shell_command(shell: &str, command: &str) -> Command {
+    let name = Path::new(shell)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let mut cmd = Command::new(shell);
+    match name.as_str() {
+        "cmd" => cmd.args(["/C", command]),
+        "powershell" | "pwsh" => cmd.args(["-NoProfile", "-NonInteractive", "-Command", command]),
+        _ => cmd.args(["-c", command]),
+    };
+    cmd
+}
+
+/// Shells `execute_terminal_command` can be asked to use by name
+pub const ALLOWED_SHELLS: &[&str] = &["sh", "bash", "zsh", "fish", "cmd", "powershell", "pwsh"];
+
+/// Resolve a requested shell to its executable on PATH. Only names in
+/// `ALLOWED_SHELLS` are accepted, plus the detected default shell as returned
+/// by `detect_default_shell`; arbitrary paths are rejected.
+fn resolve_shell(shell: &str) -> Result<String> {
+    if shell == detect_default_shell() {
+        return Ok(shell.to_string());
+    }
+
+    let name = shell.to_ascii_lowercase();
+    let name = name.strip_suffix(".exe").unwrap_or(&name);
+    if !ALLOWED_SHELLS.contains(&name) {
+        return Err(anyhow!("Unsupported shell '{}'; expected one of: {}", shell, ALLOWED_SHELLS.join(", ")));
+    }
+
+    find_executable(name)?.ok_or_else(|| anyhow!("Shell '{}' was not found on PATH", name))
+}
+
 // Terminal command execution with safety checks
-pub fn execute_terminal_command(command: &str, working_dir: Option<&str>) -> Result<CommandResult> {
+/// Run `command` through `shell` (one of `ALLOWED_SHELLS`), or the detected
+/// default shell when `None`
+pub fn execute_terminal_command(
+    command: &str,
+    working_dir: Option<&str>,
+    max_output_bytes: Option<usize>,
+    shell: Option<&str>,
+) -> Result<CommandResult> {
     // Check if command is potentially dangerous
     let dangerous_commands = vec![
         "rm -rf /", "format", "del /f", "deltree", 
@@ -233,36 +368,331 @@ pub fn execute_terminal_command(command: &str, working_dir: Option<&str>) -> Res
         }
     }
 
-    let mut cmd = if cfg!(target_os = "windows") {
-        let mut c = Command::new("cmd");
-        c.args(&["/C", command]);
-        c
-    } else {
-        let mut c = Command::new("sh");
-        c.args(&["-c", command]);
-        c
+    let shell = match shell {
+        Some(shell) if !shell.trim().is_empty() => resolve_shell(shell.trim())?,
+        _ => detect_default_shell(),
     };
+    let mut cmd = shell_command(&shell, command);
 
     if let Some(dir) = working_dir {
         cmd.current_dir(dir);
     }
 
-    let output = cmd
+    let mut child = cmd
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
-        .output()?;
+        .spawn()?;
+
+    let limit = max_output_bytes.unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+    let stdout = child.stdout.take()
+        .ok_or_else(|| anyhow!("Failed to capture stdout"))?;
+    let stderr = child.stderr.take()
+        .ok_or_else(|| anyhow!("Failed to capture stderr"))?;
+
+    // Drain both pipes concurrently so neither can fill up and block the child
+    let stderr_reader = std::thread::spawn(move || read_capped(stderr, limit));
+    let (stdout, stdout_truncated) = read_capped(stdout, limit)?;
+    let (stderr, stderr_truncated) = stderr_reader.join()
+        .map_err(|_| anyhow!("Failed to read stderr"))??;
+
+    let status = child.wait()?;
 
     Ok(CommandResult {
-        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
-        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
-        exit_code: output.status.code().unwrap_or(-1),
-        success: output.status.success(),
+        stdout: String::from_utf8_lossy(&stdout).to_string(),
+        stderr: String::from_utf8_lossy(&stderr).to_string(),
+        exit_code: status.code().unwrap_or(-1),
+        success: status.success(),
+        truncated: stdout_truncated || stderr_truncated,
+    })
+}
+
+/// Interpreters `run_code` may use unless a session narrows the list
+pub const DEFAULT_CODE_INTERPRETERS: &[&str] = &["python", "node", "sh"];
+
+/// Default and upper bound on how long `run_code` lets a snippet run
+pub const DEFAULT_RUN_CODE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+pub const MAX_RUN_CODE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RunCodeResult {
+    #[serde(flatten)]
+    pub output: CommandResult,
+    /// Executable that ran the snippet
+    pub interpreter: String,
+    /// Set when the snippet was killed for running past its timeout
+    pub timed_out: bool,
+}
+
+/// Interpreter key (as used in allowlists), candidate executables and file extension for a language tag
+fn interpreter_for(language: &str) -> Option<(&'static str, &'static [&'static str], &'static str)> {
+    match language.trim().to_ascii_lowercase().as_str() {
+        "python" | "python3" | "py" => Some(("python", &["python3", "python"], "py")),
+        "javascript" | "js" | "node" => Some(("node", &["node"], "js")),
+        "sh" | "shell" | "bash" => Some(("sh", &["sh"], "sh")),
+        _ => None,
+    }
+}
+
+/// Kill a child along with the processes it started, which would otherwise
+/// keep its output pipes open
+fn kill_process_tree(child: &mut std::process::Child) {
+    let pid = child.id().to_string();
+    if cfg!(target_os = "windows") {
+        let _ = Command::new("taskkill").args(["/F", "/T", "/PID", &pid]).output();
+    } else {
+        // The child leads its own process group, so this reaches the whole group
+        let _ = Command::new("kill").args(["-9", "--", &format!("-{}", pid)]).output();
+    }
+    let _ = child.kill();
+}
+
+/// Removes the snippet file however `run_code` returns
+struct TempScript(std::path::PathBuf);
+
+impl Drop for TempScript {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Write a snippet to a temp file and run it with the interpreter for `language`,
+/// killing it after `timeout`. Only interpreters named in `allowed` may run.
+pub fn run_code(
+    language: &str,
+    code: &str,
+    allowed: &[String],
+    working_dir: Option<&str>,
+    timeout: std::time::Duration,
+    max_output_bytes: Option<usize>,
+    cancel: Option<&AtomicBool>,
+) -> Result<RunCodeResult> {
+    let (key, candidates, extension) = interpreter_for(language)
+        .ok_or_else(|| anyhow!("Unsupported language: {} (expected python, node or sh)", language))?;
+
+    if !allowed.iter().any(|name| name.eq_ignore_ascii_case(key)) {
+        return Err(anyhow!("Interpreter '{}' is not in the allowed list: {}", key, allowed.join(", ")));
+    }
+
+    let mut interpreter = None;
+    for candidate in candidates {
+        if let Some(path) = find_executable(candidate)? {
+            interpreter = Some(path);
+            break;
+        }
+    }
+    let interpreter = interpreter.ok_or_else(|| anyhow!("No {} interpreter found on PATH", key))?;
+
+    let script = TempScript(std::env::temp_dir().join(format!("chatme-run-{}.{}", uuid::Uuid::new_v4(), extension)));
+    fs::write(&script.0, code)
+        .map_err(|e| anyhow!("Failed to write snippet: {}", e))?;
+
+    let mut cmd = Command::new(&interpreter);
+    cmd.arg(&script.0);
+    if let Some(dir) = working_dir {
+        cmd.current_dir(dir);
+    }
+    // Its own process group, so a timeout also reaches anything the snippet started
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+
+    let mut child = cmd
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start {}: {}", interpreter, e))?;
+
+    let limit = max_output_bytes.unwrap_or(DEFAULT_MAX_OUTPUT_BYTES);
+    let stdout = child.stdout.take()
+        .ok_or_else(|| anyhow!("Failed to capture stdout"))?;
+    let stderr = child.stderr.take()
+        .ok_or_else(|| anyhow!("Failed to capture stderr"))?;
+
+    let stdout_reader = std::thread::spawn(move || read_capped(stdout, limit));
+    let stderr_reader = std::thread::spawn(move || read_capped(stderr, limit));
+
+    let deadline = std::time::Instant::now() + timeout;
+    let mut timed_out = false;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if is_cancelled(cancel) {
+            kill_process_tree(&mut child);
+            child.wait()?;
+            return Err(Cancelled { progress: serde_json::Value::Null }.into());
+        }
+        if std::time::Instant::now() >= deadline {
+            timed_out = true;
+            kill_process_tree(&mut child);
+            break child.wait()?;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    };
+
+    let (stdout, stdout_truncated) = stdout_reader.join()
+        .map_err(|_| anyhow!("Failed to read stdout"))??;
+    let (stderr, stderr_truncated) = stderr_reader.join()
+        .map_err(|_| anyhow!("Failed to read stderr"))??;
+
+    Ok(RunCodeResult {
+        output: CommandResult {
+            stdout: String::from_utf8_lossy(&stdout).to_string(),
+            stderr: String::from_utf8_lossy(&stderr).to_string(),
+            exit_code: status.code().unwrap_or(-1),
+            success: status.success() && !timed_out,
+            truncated: stdout_truncated || stderr_truncated,
+        },
+        interpreter,
+        timed_out,
     })
 }
 
-// Enhanced file operations
-pub fn perform_file_operation(operation: &FileSystemOperation) -> Result<String> {
+/// Keep the first `limit` bytes of a stream and discard the rest, reading to EOF
+fn read_capped(mut reader: impl Read, limit: usize) -> Result<(Vec<u8>, bool)> {
+    let mut captured = Vec::new();
+    let mut truncated = false;
+    let mut buffer = [0u8; 8192];
+
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+
+        let room = limit.saturating_sub(captured.len());
+        if read > room {
+            truncated = true;
+        }
+        captured.extend_from_slice(&buffer[..read.min(room)]);
+    }
+
+    Ok((captured, truncated))
+}
+
+/// Upper bound on concurrently open shell sessions
+pub const MAX_SHELL_SESSIONS: usize = 8;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ShellStream {
+    Stdout,
+    Stderr,
+}
+
+/// A long-lived shell whose state (cwd, env, variables) persists between inputs.
+/// The child is killed when the session is dropped.
+#[derive(Debug)]
+pub struct ShellSession {
+    child: std::process::Child,
+    stdin: std::process::ChildStdin,
+}
+
+impl ShellSession {
+    /// Start a shell, forwarding its output to `on_output` from background threads.
+    /// `on_exit` runs once the shell closes its stdout.
+    pub fn spawn(
+        working_dir: Option<&str>,
+        on_output: impl Fn(ShellStream, String) + Send + Clone + 'static,
+        on_exit: impl FnOnce() + Send + 'static,
+    ) -> Result<Self> {
+        let mut cmd = if cfg!(target_os = "windows") {
+            let mut c = Command::new("cmd");
+            c.arg("/Q");
+            c
+        } else {
+            Command::new("sh")
+        };
+
+        if let Some(dir) = working_dir {
+            cmd.current_dir(dir);
+        }
+
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start shell: {}", e))?;
+
+        let stdin = child.stdin.take()
+            .ok_or_else(|| anyhow!("Failed to open shell stdin"))?;
+        let stdout = child.stdout.take()
+            .ok_or_else(|| anyhow!("Failed to capture shell stdout"))?;
+        let stderr = child.stderr.take()
+            .ok_or_else(|| anyhow!("Failed to capture shell stderr"))?;
+
+        let on_stderr = on_output.clone();
+        std::thread::spawn(move || forward_output(stderr, ShellStream::Stderr, on_stderr));
+        std::thread::spawn(move || {
+            forward_output(stdout, ShellStream::Stdout, on_output);
+            on_exit();
+        });
+
+        Ok(Self { child, stdin })
+    }
+
+    /// Write input to the shell, adding a trailing newline if missing
+    pub fn send(&mut self, input: &str) -> Result<()> {
+        use std::io::Write;
+
+        let mut line = input.to_string();
+        if !line.ends_with('\n') {
+            line.push('\n');
+        }
+
+        self.stdin.write_all(line.as_bytes())
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| anyhow!("Failed to write to shell: {}", e))
+    }
+
+    /// Whether the shell has exited, e.g. after an `exit` it was sent
+    pub fn has_exited(&mut self) -> bool {
+        !matches!(self.child.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for ShellSession {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Pass chunks of a stream to `on_output` until EOF, never splitting a UTF-8 character
+fn forward_output(mut reader: impl Read, stream: ShellStream, on_output: impl Fn(ShellStream, String)) {
+    let mut buffer = [0u8; 4096];
+    let mut pending: Vec<u8> = Vec::new();
+
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => read,
+        };
+        pending.extend_from_slice(&buffer[..read]);
+
+        // Hold back an incomplete trailing character until the rest arrives
+        let valid = match std::str::from_utf8(&pending) {
+            Ok(_) => pending.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => pending.len(),
+        };
+
+        if valid > 0 {
+            let chunk: Vec<u8> = pending.drain(..valid).collect();
+            on_output(stream, String::from_utf8_lossy(&chunk).to_string());
+        }
+    }
+
+    if !pending.is_empty() {
+        on_output(stream, String::from_utf8_lossy(&pending).to_string());
+    }
+}
+
+// Enhanced file operations. Raising `cancel` stops a recursive copy or delete
+// between files with a `Cancelled` error describing what was already done.
+pub fn perform_file_operation(operation: &FileSystemOperation, cancel: Option<&AtomicBool>) -> Result<String> {
     let source_path = Path::new(&operation.source);
     
     match operation.operation_type {
@@ -273,7 +703,12 @@ pub fn perform_file_operation(operation: &FileSystemOperation) -> Result<String>
             if source_path.is_file() {
                 fs::copy(&operation.source, dest)?;
             } else if source_path.is_dir() && operation.recursive {
-                copy_dir_recursive(source_path, Path::new(dest))?;
+                let mut copied = 0;
+                if let Err(e) = copy_dir_recursive(source_path, Path::new(dest), cancel, &mut copied) {
+                    return Err(with_progress(e, format!(
+                        "Copied {} files from {} to {} before stopping", copied, operation.source, dest
+                    )));
+                }
             } else {
                 return Err(anyhow!("Source is a directory but recursive flag is not set"));
             }
@@ -292,7 +727,12 @@ pub fn perform_file_operation(operation: &FileSystemOperation) -> Result<String>
                 fs::remove_file(&operation.source)?;
             } else if source_path.is_dir() {
                 if operation.recursive {
-                    fs::remove_dir_all(&operation.source)?;
+                    let mut removed = 0;
+                    if let Err(e) = remove_dir_recursive(source_path, cancel, &mut removed) {
+                        return Err(with_progress(e, format!(
+                            "Deleted {} files from {} before stopping", removed, operation.source
+                        )));
+                    }
                 } else {
                     fs::remove_dir(&operation.source)?;
                 }
@@ -318,23 +758,71 @@ pub fn perform_file_operation(operation: &FileSystemOperation) -> Result<String>
     }
 }
 
-fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
-    if !dst.exists() {
-        fs::create_dir_all(dst)?;
+// Attach a progress note to a cancellation coming out of a recursive helper
+fn with_progress(error: anyhow::Error, progress: String) -> anyhow::Error {
+    if error.is::<Cancelled>() {
+        Cancelled { progress: serde_json::Value::String(progress) }.into()
+    } else {
+        error
     }
+}
 
-    for entry in fs::read_dir(src)? {
+// Recreates the directory tree first, then copies the files on the worker pool
+fn copy_dir_recursive(src: &Path, dst: &Path, cancel: Option<&AtomicBool>, copied: &mut usize) -> Result<()> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(src).follow_links(true) {
+        if is_cancelled(cancel) {
+            return Err(Cancelled { progress: serde_json::Value::Null }.into());
+        }
         let entry = entry?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
+        let dst_path = dst.join(entry.path().strip_prefix(src)?);
 
-        if src_path.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&dst_path)?;
         } else {
-            fs::copy(&src_path, &dst_path)?;
+            files.push((entry.into_path(), dst_path));
         }
     }
 
+    let done = AtomicUsize::new(0);
+    let outcome = with_worker_pool(|| {
+        files.par_iter().try_for_each(|(src_path, dst_path)| -> Result<()> {
+            if is_cancelled(cancel) {
+                return Err(Cancelled { progress: serde_json::Value::Null }.into());
+            }
+            fs::copy(src_path, dst_path)?;
+            done.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        })
+    });
+
+    *copied += done.into_inner();
+    outcome
+}
+
+// Like `fs::remove_dir_all` (symlinks are removed, not followed), but checks
+// for cancellation between entries
+fn remove_dir_recursive(path: &Path, cancel: Option<&AtomicBool>, removed: &mut usize) -> Result<()> {
+    for entry in fs::read_dir(path)? {
+        if is_cancelled(cancel) {
+            return Err(Cancelled { progress: serde_json::Value::Null }.into());
+        }
+        let entry = entry?;
+        let entry_path = entry.path();
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            remove_dir_recursive(&entry_path, cancel, removed)?;
+        } else if file_type.is_symlink() && cfg!(target_os = "windows") && entry_path.is_dir() {
+            // Directory links on Windows are removed like empty directories
+            fs::remove_dir(&entry_path)?;
+        } else {
+            fs::remove_file(&entry_path)?;
+            *removed += 1;
+        }
+    }
+
+    fs::remove_dir(path)?;
     Ok(())
 }
 
@@ -389,6 +877,359 @@ pub fn get_running_processes() -> Result<Vec<ProcessInfo>> {
     Ok(processes)
 }
 
+// Resolve an executable name against PATH, like `which`/`where`
+pub fn find_executable(name: &str) -> Result<Option<String>> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(anyhow!("Executable name must not be empty"));
+    }
+
+    // Windows tries each PATHEXT extension unless the name already has one
+    let extensions: Vec<String> = if cfg!(target_os = "windows") && Path::new(name).extension().is_none() {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_string())
+            .split(';')
+            .filter(|ext| !ext.is_empty())
+            .map(|ext| ext.to_lowercase())
+            .collect()
+    } else {
+        vec![String::new()]
+    };
+
+    let candidates = |dir: &Path| -> Vec<std::path::PathBuf> {
+        extensions.iter().map(|ext| dir.join(format!("{}{}", name, ext))).collect()
+    };
+
+    // A name with a directory component is checked as given, not searched for
+    if name.contains('/') || name.contains(std::path::MAIN_SEPARATOR) {
+        let found = candidates(Path::new(""))
+            .into_iter()
+            .find(|path| is_executable(path));
+        return Ok(found.map(|path| {
+            std::path::absolute(&path).unwrap_or(path).to_string_lossy().to_string()
+        }));
+    }
+
+    let path_var = match std::env::var_os("PATH") {
+        Some(path_var) => path_var,
+        None => return Ok(None),
+    };
+
+    for dir in std::env::split_paths(&path_var) {
+        if let Some(found) = candidates(&dir).into_iter().find(|path| is_executable(path)) {
+            let resolved = std::path::absolute(&found).unwrap_or(found);
+            return Ok(Some(resolved.to_string_lossy().to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+fn is_executable(path: &Path) -> bool {
+    let metadata = match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => return false,
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        true
+    }
+}
+
+// Clipboard access; an empty or non-text clipboard reads as an empty string
+pub fn read_clipboard_text() -> Result<String> {
+    let mut clipboard = arboard::Clipboard::new()
+        .map_err(|e| anyhow!("Failed to access clipboard: {}", e))?;
+
+    match clipboard.get_text() {
+        Ok(text) => Ok(text),
+        Err(arboard::Error::ContentNotAvailable) => Ok(String::new()),
+        Err(e) => Err(anyhow!("Failed to read clipboard: {}", e)),
+    }
+}
+
+pub fn write_clipboard_text(text: &str) -> Result<()> {
+    let mut clipboard = arboard::Clipboard::new()
+        .map_err(|e| anyhow!("Failed to access clipboard: {}", e))?;
+
+    clipboard.set_text(text)
+        .map_err(|e| anyhow!("Failed to write clipboard: {}", e))
+}
+
+// Screen capture
+pub fn list_displays() -> Result<Vec<DisplayInfo>> {
+    let monitors = xcap::Monitor::all()
+        .map_err(|e| anyhow!("Failed to enumerate displays: {}", e))?;
+
+    Ok(monitors
+        .iter()
+        .enumerate()
+        .map(|(index, monitor)| DisplayInfo {
+            index,
+            name: monitor.name().unwrap_or_default(),
+            width: monitor.width().unwrap_or_default(),
+            height: monitor.height().unwrap_or_default(),
+            is_primary: monitor.is_primary().unwrap_or(false),
+        })
+        .collect())
+}
+
+// Capture a display (the primary one by default) as a PNG data URL
+pub fn capture_screenshot_data_url(display: Option<usize>) -> Result<String> {
+    let monitors = xcap::Monitor::all()
+        .map_err(|e| anyhow!("Failed to enumerate displays: {}", e))?;
+
+    let monitor = match display {
+        Some(index) => monitors.get(index)
+            .ok_or_else(|| anyhow!("Display {} not found ({} available)", index, monitors.len()))?,
+        None => monitors.iter()
+            .find(|monitor| monitor.is_primary().unwrap_or(false))
+            .or_else(|| monitors.first())
+            .ok_or_else(|| anyhow!("No displays available"))?,
+    };
+
+    let image = monitor.capture_image()
+        .map_err(|e| anyhow!("Failed to capture screenshot: {}", e))?;
+
+    let mut png = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut png), xcap::image::ImageFormat::Png)
+        .map_err(|e| anyhow!("Failed to encode screenshot: {}", e))?;
+
+    use base64::Engine;
+    Ok(format!("data:image/png;base64,{}", base64::engine::general_purpose::STANDARD.encode(&png)))
+}
+
+// Describe the host platform without exposing arbitrary environment variables
+pub fn get_environment_info() -> Result<EnvInfo> {
+    let env_vars = ENV_VAR_ALLOWLIST
+        .iter()
+        .filter_map(|name| std::env::var(name).ok().map(|value| (name.to_string(), value)))
+        .collect();
+
+    let username = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok();
+
+    let shell = if cfg!(target_os = "windows") {
+        std::env::var("COMSPEC").ok()
+    } else {
+        std::env::var("SHELL").ok()
+    };
+
+    Ok(EnvInfo {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        family: std::env::consts::FAMILY.to_string(),
+        hostname: get_hostname(),
+        username,
+        home_directory: dirs::home_dir().map(|dir| dir.to_string_lossy().to_string()),
+        current_directory: std::env::current_dir().ok().map(|dir| dir.to_string_lossy().to_string()),
+        path_separator: std::path::MAIN_SEPARATOR.to_string(),
+        shell,
+        env_vars,
+    })
+}
+
+fn get_hostname() -> Option<String> {
+    if let Ok(name) = std::env::var("COMPUTERNAME").or_else(|_| std::env::var("HOSTNAME")) {
+        if !name.is_empty() {
+            return Some(name);
+        }
+    }
+
+    if let Ok(name) = fs::read_to_string("/etc/hostname") {
+        let name = name.trim();
+        if !name.is_empty() {
+            return Some(name.to_string());
+        }
+    }
+
+    Command::new("hostname")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|name| !name.is_empty())
+}
+
+// Read-only git queries. Only these fixed subcommands can run, with options
+// that keep git from launching helpers (fsmonitor, external diff, pager).
+const MAX_GIT_OUTPUT_BYTES: usize = 256 * 1024;
+const MAX_GIT_LOG_ENTRIES: usize = 200;
+
+fn run_git(directory: &str, args: &[&str]) -> Result<(Vec<u8>, bool)> {
+    if !Path::new(directory).is_dir() {
+        return Err(anyhow!("Directory does not exist: {}", directory));
+    }
+
+    let mut child = Command::new("git")
+        .args(["-C", directory, "-c", "core.fsmonitor=false", "--no-pager"])
+        .args(args)
+        // Don't take the index lock just to refresh stat info
+        .env("GIT_OPTIONAL_LOCKS", "0")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| anyhow!("Failed to run git: {}", e))?;
+
+    let stdout = child.stdout.take()
+        .ok_or_else(|| anyhow!("Failed to capture stdout"))?;
+    let stderr = child.stderr.take()
+        .ok_or_else(|| anyhow!("Failed to capture stderr"))?;
+
+    let stderr_reader = std::thread::spawn(move || read_capped(stderr, MAX_GIT_OUTPUT_BYTES));
+    let (output, truncated) = read_capped(stdout, MAX_GIT_OUTPUT_BYTES)?;
+    let (errors, _) = stderr_reader.join()
+        .map_err(|_| anyhow!("Failed to read stderr"))??;
+
+    let status = child.wait()?;
+    if !status.success() {
+        return Err(anyhow!("git {} failed: {}", args[0], String::from_utf8_lossy(&errors).trim()));
+    }
+
+    Ok((output, truncated))
+}
+
+pub fn git_status(directory: &str) -> Result<GitStatus> {
+    let (output, _) = run_git(directory, &["status", "--porcelain=v2", "--branch", "-z"])?;
+    let output = String::from_utf8_lossy(&output);
+
+    let mut status = GitStatus {
+        branch: None,
+        upstream: None,
+        ahead: 0,
+        behind: 0,
+        files: Vec::new(),
+        clean: true,
+    };
+
+    let mut records = output.split('\0').filter(|record| !record.is_empty());
+    while let Some(record) = records.next() {
+        if let Some(header) = record.strip_prefix("# ") {
+            let (key, value) = header.split_once(' ').unwrap_or((header, ""));
+            match key {
+                "branch.head" if value != "(detached)" => status.branch = Some(value.to_string()),
+                "branch.upstream" => status.upstream = Some(value.to_string()),
+                "branch.ab" => {
+                    for count in value.split_whitespace() {
+                        if let Some(ahead) = count.strip_prefix('+') {
+                            status.ahead = ahead.parse().unwrap_or(0);
+                        } else if let Some(behind) = count.strip_prefix('-') {
+                            status.behind = behind.parse().unwrap_or(0);
+                        }
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+
+        // Ordinary (1), renamed/copied (2) and unmerged (u) entries put the
+        // path after a fixed number of space-separated fields
+        let (kind, rest) = record.split_at(1);
+        let fields = match kind {
+            "1" => 9,
+            "2" => 10,
+            "u" => 11,
+            "?" => {
+                status.files.push(GitFileStatus {
+                    path: rest.trim_start().to_string(),
+                    original_path: None,
+                    index: "?".to_string(),
+                    worktree: "?".to_string(),
+                });
+                continue;
+            }
+            _ => continue,
+        };
+
+        let parts: Vec<&str> = record.splitn(fields, ' ').collect();
+        if parts.len() != fields {
+            continue;
+        }
+
+        let mut codes = parts[1].chars();
+        let original_path = if kind == "2" {
+            records.next().map(|path| path.to_string())
+        } else {
+            None
+        };
+
+        status.files.push(GitFileStatus {
+            path: parts[fields - 1].to_string(),
+            original_path,
+            index: codes.next().unwrap_or('.').to_string(),
+            worktree: codes.next().unwrap_or('.').to_string(),
+        });
+    }
+
+    status.clean = status.files.is_empty();
+    Ok(status)
+}
+
+pub fn git_log(directory: &str, limit: usize) -> Result<Vec<GitCommit>> {
+    let count = format!("--max-count={}", limit.clamp(1, MAX_GIT_LOG_ENTRIES));
+    // Unit and record separators can't appear in names or subjects.
+    // log.showSignature would otherwise splice gpg output into the records.
+    let (output, _) = run_git(directory, &[
+        "log",
+        &count,
+        "--no-color",
+        "--no-show-signature",
+        "--format=%H%x1f%an%x1f%ae%x1f%aI%x1f%s%x1e",
+    ])?;
+    let output = String::from_utf8_lossy(&output);
+
+    let commits = output
+        .split('\x1e')
+        .filter_map(|record| {
+            let fields: Vec<&str> = record.trim_start_matches('\n').split('\x1f').collect();
+            match fields.as_slice() {
+                [hash, author, email, date, subject] => Some(GitCommit {
+                    hash: hash.to_string(),
+                    author: author.to_string(),
+                    email: email.to_string(),
+                    date: date.to_string(),
+                    subject: subject.to_string(),
+                }),
+                _ => None,
+            }
+        })
+        .collect();
+
+    Ok(commits)
+}
+
+/// Unstaged changes by default, or what is staged for the next commit
+pub fn git_diff(directory: &str, staged: bool, path: Option<&str>) -> Result<GitDiff> {
+    let mut args = vec!["diff", "--no-color", "--no-ext-diff", "--no-textconv"];
+    if staged {
+        args.push("--cached");
+    }
+    // Everything after `--` is a pathspec, never an option
+    if let Some(path) = path {
+        args.push("--");
+        args.push(path);
+    }
+
+    let (output, truncated) = run_git(directory, &args)?;
+
+    Ok(GitDiff {
+        diff: String::from_utf8_lossy(&output).to_string(),
+        truncated,
+    })
+}
+
 // Kill a process
 pub fn kill_process(pid: u32) -> Result<()> {
     if cfg!(target_os = "windows") {
@@ -405,10 +1246,101 @@ pub fn kill_process(pid: u32) -> Result<()> {
 }
 
 // Check permission level for an operation
+/// Locations where writes and deletes always need explicit permission
+const SYSTEM_DIRECTORIES: &[&str] = &[
+    "C:\\Windows", "C:\\Program Files", "/usr", "/bin", "/sbin", "/etc", "/boot",
+    "/System", "/Library", "/Applications"
+];
+
+/// Whether `path` lies under a system directory. Relative paths and `..`
+/// segments are resolved first so they can't be used to sidestep the check,
+/// and paths are compared by component so `/usrdata` isn't under `/usr`.
+fn is_system_path(path: &str) -> bool {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| std::path::PathBuf::from(path));
+    
+    let mut normalized = std::path::PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            std::path::Component::ParentDir => {
+                normalized.pop();
+            }
+            std::path::Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    
+    let normalized = std::path::PathBuf::from(normalized.to_string_lossy().to_lowercase());
+    let original = std::path::PathBuf::from(path.to_lowercase());
+    
+    SYSTEM_DIRECTORIES.iter().any(|dir| {
+        let dir = std::path::PathBuf::from(dir.to_lowercase());
+        normalized.starts_with(&dir) || original.starts_with(&dir)
+    })
+}
+
+/// Commands that make a snippet Dangerous when it runs them
+const DANGEROUS_COMMANDS: &[&str] = &[
+    "sudo", "doas", "runas", "format", "fdisk", "mkfs", "diskpart", "regedit", "reg"
+];
+
+/// Whether `script` runs one of `DANGEROUS_COMMANDS`, a forced recursive
+/// delete or a raw `dd` copy. Only the command word of each shell-style
+/// segment counts, so `"{}".format(x)` or `administrator_count` don't.
+fn runs_dangerous_command(script: &str) -> bool {
+    let script = script.to_lowercase().replace("$(", "\n");
+    script
+        .split(['\n', ';', '|', '&', '`', '{', '}'])
+        .any(|segment| {
+            let mut words = segment.split_whitespace()
+                .map(|word| word.trim_matches(|c| c == '"' || c == '\''));
+            let Some(command) = words.next() else {
+                return false;
+            };
+            let command = command.rsplit(['/', '\\']).next().unwrap_or(command);
+            let command = command.strip_suffix(".exe").unwrap_or(command);
+            let args: Vec<&str> = words.collect();
+            // `name = value` is an assignment, not a command
+            if args.first().is_some_and(|arg| arg.starts_with('=')) {
+                return false;
+            }
+
+            match command {
+                "rm" => {
+                    let short_flags = |flag: char| args.iter()
+                        .any(|arg| arg.starts_with('-') && !arg.starts_with("--") && arg.contains(flag));
+                    (short_flags('r') || args.contains(&"--recursive")) && (short_flags('f') || args.contains(&"--force"))
+                }
+                "del" | "erase" | "rd" | "rmdir" => args.iter().any(|arg| matches!(*arg, "/f" | "/s" | "/q")),
+                "dd" => args.iter().any(|arg| arg.starts_with("if=") || arg.starts_with("of=")),
+                _ => DANGEROUS_COMMANDS.contains(&command) || command.starts_with("mkfs."),
+            }
+        })
+}
+
 pub fn check_permission_level(operation: &str, params: &HashMap<String, serde_json::Value>) -> OperationPermission {
     let mut details = HashMap::new();
     
     match operation {
+        "run_code" => {
+            let language = params.get("language").and_then(|v| v.as_str()).unwrap_or("unknown");
+            details.insert("language".to_string(), language.to_string());
+            
+            let is_dangerous = params.get("code")
+                .and_then(|v| v.as_str())
+                .is_none_or(runs_dangerous_command);
+            
+            OperationPermission {
+                operation: "Run Code".to_string(),
+                description: format!("Run a {} snippet", language),
+                level: if is_dangerous {
+                    PermissionLevel::Dangerous
+                } else {
+                    PermissionLevel::Moderate
+                },
+                details,
+            }
+        },
+        
         "execute_command" => {
             if let Some(cmd) = params.get("command").and_then(|v| v.as_str()) {
                 details.insert("command".to_string(), cmd.to_string());
@@ -465,14 +1397,7 @@ pub fn check_permission_level(operation: &str, params: &HashMap<String, serde_js
             if let Some(path) = params.get("path").and_then(|v| v.as_str()) {
                 details.insert("path".to_string(), path.to_string());
                 
-                // Check if it's a system directory
-                let system_dirs = vec![
-                    "C:\\Windows", "C:\\Program Files", "/usr", "/bin", "/etc",
-                    "/System", "/Library", "/Applications"
-                ];
-                
-                let is_system = system_dirs.iter()
-                    .any(|dir| path.starts_with(dir));
+                let is_system = is_system_path(path);
                 
                 OperationPermission {
                     operation: "Delete File/Directory".to_string(),
@@ -494,6 +1419,94 @@ pub fn check_permission_level(operation: &str, params: &HashMap<String, serde_js
             }
         },
         
+        "write_file" => {
+            if let Some(path) = params.get("path").and_then(|v| v.as_str()) {
+                details.insert("path".to_string(), path.to_string());
+                OperationPermission {
+                    operation: "Write File".to_string(),
+                    description: format!("Write: {}", path),
+                    level: if is_system_path(path) {
+                        PermissionLevel::Dangerous
+                    } else {
+                        PermissionLevel::Moderate
+                    },
+                    details,
+                }
+            } else {
+                OperationPermission {
+                    operation: "Write File".to_string(),
+                    description: "Write unknown path".to_string(),
+                    level: PermissionLevel::Dangerous,
+                    details,
+                }
+            }
+        },
+        
+        "file_operation" => {
+            let operation_type = params.get("operation_type").and_then(|v| v.as_str()).unwrap_or_default();
+            let source = params.get("source").and_then(|v| v.as_str());
+            let destination = params.get("destination").and_then(|v| v.as_str());
+            
+            // Deletes get the dedicated delete check
+            if operation_type == "delete" {
+                let mut delete_params = HashMap::new();
+                if let Some(source) = source {
+                    delete_params.insert("path".to_string(), serde_json::json!(source));
+                }
+                return check_permission_level("delete_file", &delete_params);
+            }
+            
+            details.insert("operation_type".to_string(), operation_type.to_string());
+            if let Some(source) = source {
+                details.insert("source".to_string(), source.to_string());
+            }
+            if let Some(destination) = destination {
+                details.insert("destination".to_string(), destination.to_string());
+            }
+            
+            // Moves and renames also remove the source
+            let touches_system = destination.is_some_and(is_system_path)
+                || (matches!(operation_type, "move" | "rename" | "create_directory") && source.is_some_and(is_system_path));
+            
+            OperationPermission {
+                operation: "File Operation".to_string(),
+                description: match destination {
+                    Some(destination) => format!("{} {} to {}", operation_type, source.unwrap_or("unknown path"), destination),
+                    None => format!("{} {}", operation_type, source.unwrap_or("unknown path")),
+                },
+                level: if source.is_none() || touches_system {
+                    PermissionLevel::Dangerous
+                } else {
+                    PermissionLevel::Moderate
+                },
+                details,
+            }
+        },
+        
+        "capture_screenshot" => {
+            if let Some(display) = params.get("display") {
+                details.insert("display".to_string(), display.to_string());
+            }
+            OperationPermission {
+                operation: "Capture Screenshot".to_string(),
+                description: "Capture the contents of the screen".to_string(),
+                level: PermissionLevel::Moderate,
+                details,
+            }
+        },
+        
+        "write_clipboard" => {
+            if let Some(text) = params.get("text").and_then(|v| v.as_str()) {
+                details.insert("length".to_string(), text.chars().count().to_string());
+            }
+            OperationPermission {
+                operation: "Write Clipboard".to_string(),
+                description: "Replace the clipboard contents".to_string(),
+                level: PermissionLevel::Moderate,
+                details,
+            }
+        },
+        
         "kill_process" => {
             if let Some(pid) = params.get("pid") {
                 details.insert("pid".to_string(), pid.to_string());
@@ -521,3 +1534,32 @@ pub fn check_permission_level(operation: &str, params: &HashMap<String, serde_js
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_paths_match_whole_components() {
+        assert!(is_system_path("/usr"));
+        assert!(is_system_path("/usr/bin/env"));
+        assert!(is_system_path("/ETC/hosts"));
+        assert!(is_system_path("/home/../etc/passwd"));
+
+        assert!(!is_system_path("/usrdata/report.txt"));
+        assert!(!is_system_path("/binaries"));
+        assert!(!is_system_path("/etcetera/notes"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn only_allowed_shells_resolve() {
+        let sh = resolve_shell("sh").unwrap();
+        assert!(Path::new(&sh).is_absolute(), "{}", sh);
+        assert_eq!(resolve_shell(&detect_default_shell()).unwrap(), detect_default_shell());
+
+        assert!(resolve_shell("python3").is_err());
+        assert!(resolve_shell("/tmp/bash").is_err());
+        assert!(execute_terminal_command("echo hi", None, None, Some("/usr/bin/env")).is_err());
+    }
+}
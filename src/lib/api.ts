@@ -11,6 +11,8 @@ import type {
   UpdateApiConfigRequest,
   DirectoryContents,
   SearchResult,
+  FileMatchCount,
+  WriteReport,
   AgentSession,
   AgentAction,
   AgentCapability
@@ -42,8 +44,8 @@ export async function createMessage(request: CreateMessageRequest): Promise<Mess
   return await invoke('create_message', { request });
 }
 
-export async function getMessages(chatId: string): Promise<Message[]> {
-  return await invoke('get_messages', { chatId });
+export async function getMessages(chatId: string, includeMetrics?: boolean): Promise<Message[]> {
+  return await invoke('get_messages', { chatId, includeMetrics });
 }
 
 export async function deleteMessage(messageId: string): Promise<void> {
@@ -71,17 +73,15 @@ export async function updateApiConfig(configId: string, request: UpdateApiConfig
   return await invoke('update_api_config', { configId, request });
 }
 
-export async function deleteApiConfig(configId: string): Promise<void> {
-  return await invoke('delete_api_config', { configId });
+export async function deleteApiConfig(configId: string, force?: boolean): Promise<number> {
+  return await invoke('delete_api_config', { configId, force });
 }
 
 // AI Chat operations
-export async function sendAiMessage(chatId: string, userMessage: string): Promise<Message> {
-  return await invoke('send_ai_message', { chatId, userMessage });
-}
-
-export async function sendAiMessageStreaming(chatId: string, userMessage: string, images?: string[]): Promise<string> {
-  return await invoke('send_ai_message_streaming', { chatId, userMessage, images });
+// With `stream`, the reply also arrives as `streaming_chunk` events; either way
+// this resolves to the stored assistant message once it's complete
+export async function sendAiMessage(chatId: string, userMessage: string, images?: string[], stream?: boolean): Promise<Message> {
+  return await invoke('send_ai_message', { chatId, userMessage, images, stream });
 }
 
 // File Operations
@@ -95,19 +95,44 @@ export async function readDirectory(directoryPath: string, recursive?: boolean):
 
 export async function searchFiles(
   directoryPath: string,
-  pattern: string,
+  pattern: string | string[],
   fileExtension?: string,
   caseSensitive?: boolean,
   recursive?: boolean,
-  maxResults?: number
+  maxResults?: number,
+  fileTimeoutMs?: number,
+  wholeWord?: boolean,
+  fixedString?: boolean,
+  skipIgnoredDirectories?: boolean // Skip .git, node_modules and target
 ): Promise<SearchResult[]> {
+  return await invoke('search_files', {
+    directoryPath,
+    ...(Array.isArray(pattern) ? { patterns: pattern } : { pattern }),
+    fileExtension,
+    caseSensitive,
+    recursive,
+    maxResults,
+    fileTimeoutMs,
+    wholeWord,
+    fixedString,
+    skipIgnoredDirectories
+  });
+}
+
+export async function countMatchesInFiles(
+  directoryPath: string,
+  pattern: string,
+  fileExtension?: string,
+  caseSensitive?: boolean,
+  recursive?: boolean
+): Promise<FileMatchCount[]> {
   return await invoke('search_files', {
     directoryPath,
     pattern,
     fileExtension,
     caseSensitive,
     recursive,
-    maxResults
+    countOnly: true
   });
 }
 
@@ -115,7 +140,7 @@ export async function readFile(filePath: string): Promise<string> {
   return await invoke('read_file', { filePath });
 }
 
-export async function writeFile(filePath: string, contents: string): Promise<string> {
+export async function writeFile(filePath: string, contents: string): Promise<WriteReport> {
   return await invoke('write_file', { filePath, contents });
 }
 
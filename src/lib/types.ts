@@ -2,8 +2,14 @@ export interface Chat {
   id: string;
   title: string;
   api_config_id: string | null;
+  sort_order: number | null;
+  pinned: boolean; // Kept when old chats are purged
+  temperature_override: number | null;
+  max_tokens_override: number | null;
+  pinned_context: string | null;
   created_at: string;
   updated_at: string;
+  created_at_local?: string; // Present when a timezone was requested
 }
 
 export interface Message {
@@ -13,6 +19,24 @@ export interface Message {
   role: 'user' | 'assistant';
   created_at: string;
   images?: string[]; // Array of base64 encoded images
+  api_config_id?: string | null;
+  reasoning?: string | null;
+  finish_reason?: string | null; // 'length' when cut off by the token limit; only those can be continued
+  metrics?: MessageMetrics;
+  created_at_local?: string; // Present when a timezone was requested
+}
+
+export interface MessageMetrics {
+  word_count: number;
+  character_count: number;
+  line_count: number;
+  reading_time_seconds: number;
+  estimated_tokens: number;
+}
+
+export interface CodeBlock {
+  language: string | null;
+  code: string;
 }
 
 export interface ChatWithLastMessage {
@@ -20,13 +44,25 @@ export interface ChatWithLastMessage {
   title: string;
   api_config_id: string | null;
   api_config_name: string | null;
+  sort_order: number | null;
+  pinned: boolean;
   created_at: string;
   updated_at: string;
   last_message: string | null;
   last_message_time: string | null;
   unread_count: number;
+  created_at_local?: string; // Present when a timezone was requested
+}
+
+export interface ChatExport {
+  chat: Chat;
+  messages: Message[];
 }
 
+export type ExportFormat = 'jsonl' | 'json';
+
+export type MessageFormat = 'markdown' | 'plain_text' | 'html';
+
 export interface ApiConfig {
   id: string;
   name: string;
@@ -37,12 +73,60 @@ export interface ApiConfig {
   temperature: number;
   max_tokens: number | null;
   is_default: boolean;
+  fallback_config_id: string | null; // Tried when this provider is unavailable
+  keep_alive: string | null; // Ollama only, e.g. "5m" or "-1"
+  context_strategy: ContextStrategy;
   created_at: string;
   updated_at: string;
 }
 
+export type ContextStrategy =
+  | { type: 'recent_n'; value: number } // The last N messages
+  | { type: 'token_budget'; value: number } // As many latest messages as fit in this many tokens
+  | { type: 'summarized' } // Last 10-19 messages plus a summary of the rest, extended 10 at a time
+  | { type: 'full' }; // Everything, trimmed only to fit the model's context window
+
 export type ApiProvider = 'openai' | 'anthropic' | 'google' | 'ollama' | 'custom';
 
+export type ApiErrorKind =
+  | 'authentication'
+  | 'rate_limited'
+  | 'model_not_found'
+  | 'invalid_request'
+  | 'server_error'
+  | 'unreachable'
+  | 'other';
+
+export interface ApiErrorDetails {
+  provider: ApiProvider;
+  status: number | null; // null when the provider couldn't be reached
+  code: string | null;
+  type: string | null;
+  message: string; // Raw body when the provider didn't send JSON
+  kind: ApiErrorKind;
+}
+
+export type AuthStyle = 'bearer' | 'x_api_key' | 'query_param' | 'none';
+
+export interface ProviderMeta {
+  provider: ApiProvider;
+  display_name: string;
+  default_base_url: string | null;
+  required_fields: string[];
+  supports_streaming: boolean;
+  supports_vision: boolean;
+  supports_tools: boolean;
+  auth_style: AuthStyle;
+}
+
+export interface PromptTemplate {
+  id: string;
+  name: string;
+  body: string; // {{name}} placeholders are filled in by render_template
+  created_at: string;
+  updated_at: string;
+}
+
 export interface CreateChatRequest {
   title: string;
   api_config_id?: string | null;
@@ -53,22 +137,39 @@ export interface CreateMessageRequest {
   content: string;
   role: 'user' | 'assistant';
   images?: string[]; // Array of base64 encoded images
+  skip_if_duplicate?: boolean;
 }
 
-export interface UpdateChatRequest {
-  title: string;
+export interface NewMessage {
+  role: 'user' | 'assistant';
+  content: string;
+  images?: string[];
   api_config_id?: string | null;
+  reasoning?: string | null;
+  created_at?: string; // Original timestamp when importing; defaults to now, in order
+}
+
+// Omitted fields keep their current value; null clears them
+export interface UpdateChatRequest {
+  title?: string;
+  api_config_id?: string | null; // null goes back to the default config
+  temperature_override?: number | null;
+  max_tokens_override?: number | null;
+  pinned_context?: string | null; // null or blank text clears the note
 }
 
 export interface CreateApiConfigRequest {
   name: string;
-  provider: ApiProvider;
+  provider?: ApiProvider | null; // Falls back to AppSettings.default_provider
   api_key: string;
   base_url?: string | null;
   model: string;
-  temperature: number;
+  temperature?: number | null; // Falls back to AppSettings.default_temperature
   max_tokens?: number | null;
   is_default: boolean;
+  fallback_config_id?: string | null;
+  keep_alive?: string | null; // Ollama only, e.g. "5m", "1h30m" or "-1"
+  context_strategy?: ContextStrategy | null; // Defaults to the last 10 messages
 }
 
 export interface UpdateApiConfigRequest {
@@ -79,6 +180,27 @@ export interface UpdateApiConfigRequest {
   temperature: number;
   max_tokens?: number | null;
   is_default: boolean;
+  fallback_config_id?: string | null; // null clears the fallback; omitting it leaves it unchanged
+  keep_alive?: string | null; // null restores Ollama's default; omitting it leaves it unchanged
+  context_strategy?: ContextStrategy | null; // null restores the last 10 messages; omitting it leaves it unchanged
+}
+
+export interface AppSettings {
+  default_temperature: number;
+  default_max_tokens: number | null;
+  default_provider: ApiProvider | null;
+  stream_idle_timeout_secs: number;
+  stream_max_reconnects: number;
+  max_threads: number; // Workers for multi-file search and copy; 0 = one per CPU
+}
+
+export interface UpdateAppSettingsRequest {
+  default_temperature: number;
+  default_max_tokens?: number | null;
+  default_provider?: ApiProvider | null;
+  stream_idle_timeout_secs?: number | null; // Left unchanged when omitted
+  stream_max_reconnects?: number | null; // Left unchanged when omitted
+  max_threads?: number | null; // Left unchanged when omitted
 }
 
 export interface StreamingMessage {
@@ -98,6 +220,23 @@ export interface FileInfo {
   file_type?: string;
 }
 
+export interface TreeNode {
+  name: string;
+  path: string;
+  is_directory: boolean;
+  children: TreeNode[]; // Directories first, then files
+  truncated: boolean; // Contents left out (depth limit, entry budget or unreadable)
+}
+
+export type FilePreview = {
+  info: FileInfo;
+  mime: string | null;
+} & (
+  | { kind: 'text'; content: string; truncated: boolean }
+  | { kind: 'image'; data_url: string }
+  | { kind: 'binary'; hex_dump: string }
+);
+
 export interface DirectoryContents {
   files: FileInfo[];
   directories: FileInfo[];
@@ -111,6 +250,56 @@ export interface SearchResult {
   line_content: string;
   match_start: number;
   match_end: number;
+  matched_pattern: string;
+}
+
+export interface SearchSummary {
+  total_matches: number;
+  files_searched: number;
+  truncated: boolean; // Stopped at max_results
+  cancelled: boolean; // Stopped by cancel_search
+}
+
+export interface SearchHitEvent {
+  search_id: string;
+  results: SearchResult[]; // Matches from one file
+}
+
+export interface SearchCompleteEvent {
+  search_id: string;
+  summary?: SearchSummary;
+  error?: string;
+}
+
+export interface WriteReport {
+  path: string;
+  bytes_written: number;
+  created: boolean;
+  diff: string | null;
+  diff_truncated: boolean;
+}
+
+export interface DirectoryChange {
+  watched_path: string; // The directory passed to watch_directory
+  kind: 'create' | 'modify' | 'remove' | 'other';
+  paths: string[];
+}
+
+export interface DuplicateGroup {
+  size: number; // Bytes per copy
+  hash: string; // sha256 of the shared contents
+  paths: string[];
+}
+
+export interface DuplicateScanProgress {
+  files_scanned: number;
+  files_to_hash: number; // 0 until the walk finishes
+  files_hashed: number;
+}
+
+export interface FileMatchCount {
+  file_path: string;
+  match_count: number;
 }
 
 // Agentic Mode Types
@@ -121,6 +310,28 @@ export interface AgentAction {
   result?: any;
   success: boolean;
   error_message?: string;
+  cancelled: boolean; // result then describes the work already done
+}
+
+export interface AgentActionRecord {
+  id: number;
+  session_id: string;
+  action_type: string;
+  description: string;
+  parameters: Record<string, any>; // long strings are shortened
+  success: boolean;
+  cancelled: boolean;
+  error_message?: string;
+  created_at: string;
+}
+
+export interface OperationLogEntry {
+  id: number;
+  operation: string; // e.g. launch_app, execute_command, file_operation, kill_process
+  parameters: Record<string, any>; // shortened, with secrets masked
+  success: boolean;
+  result: string; // What happened, or the error
+  created_at: string;
 }
 
 export interface AgentSession {
@@ -130,6 +341,7 @@ export interface AgentSession {
   context: Record<string, any>;
   current_directory: string;
   capabilities: string[];
+  allowed_interpreters: string[]; // Interpreters run_code may use
 }
 
 export interface AgentCapability {
@@ -144,4 +356,84 @@ export interface AgentParameter {
   description: string;
   required: boolean;
   default_value?: any;
-}
\ No newline at end of file
+}
+
+export interface ToolDefinition {
+  type: 'function';
+  function: {
+    name: string;
+    description: string;
+    parameters: {
+      type: 'object';
+      properties: Record<string, { type: string; description: string; default?: any; items?: { type: string } }>;
+      required: string[];
+    };
+  };
+}
+export interface PingInfo {
+  version: string;
+  uptime_seconds: number;
+  database_ok: boolean;
+  agent_sessions: number;
+  active_streams: number;
+}
+
+export interface VersionInfo {
+  app_version: string;
+  tauri_version: string;
+  sqlite_version: string;
+  schema_version: number | null; // Latest database migration applied
+  os: string;
+  os_family: string;
+  arch: string;
+  debug_build: boolean;
+}
+
+export interface AbortReport {
+  streams: number;
+  agent_actions: number;
+  file_followers: number;
+  directory_watches: number;
+  searches: number;
+  shell_sessions: number;
+}
+
+export interface TableRecovery {
+  table: string;
+  rows_recovered: number;
+  rows_lost: number;
+}
+
+export interface RepairReport {
+  problems: string[];
+  tables: TableRecovery[];
+  backup_path: string;
+}
+
+export interface IntegrityReport {
+  problems: string[];
+  restart_required: boolean;
+  startup_repair?: RepairReport;
+}
+
+export interface ContextFit {
+  prompt_tokens: number;
+  context_limit: number | null;
+  reserved_response_tokens: number;
+  headroom: number | null;
+  fits: boolean;
+}
+
+export interface TokenUsage {
+  prompt_tokens: number;
+  completion_tokens: number;
+  total_tokens: number;
+}
+
+export interface ProviderUsage {
+  api_config_id: string | null; // null for chats on the default config
+  config_name: string | null;
+  provider: ApiProvider | null;
+  assistant_messages: number;
+  estimated_tokens: number;
+}
@@ -5,7 +5,7 @@ import InputBox from "@/components/app/input-box";
 import MessageItem from "@/components/app/message-item";
 import StreamingMessageItem from "@/components/app/streaming-message-item";
 import { toast } from "sonner";
-import { getMessages, sendAiMessageStreaming, createMessage } from "@/lib/api";
+import { getMessages, sendAiMessage, createMessage } from "@/lib/api";
 import { handleAgentQuery, getAvailableAgentTools, parseAndExecuteCommands } from "@/lib/agent-utils";
 import { useAgent } from "@/contexts/AgentContext";
 import type { Message, StreamingMessage } from "@/lib/types";
@@ -340,9 +340,9 @@ You have the ability to:
 Always execute commands naturally within your response. Be helpful, informative, and proactive.`;
             }
             
-            // sendAiMessageStreaming creates the user message, so we don't create it separately
+            // sendAiMessage creates the user message, so we don't create it separately
             // This prevents duplication and ensures only the original user message is saved
-            await sendAiMessageStreaming(chatId, messageForLLM, images);
+            await sendAiMessage(chatId, messageForLLM, images, true);
             
         } catch (error) {
             console.error('Failed to send message:', error);